@@ -141,4 +141,60 @@ pub enum OxygenError {
     
     #[msg("User must sign all transactions involving their funds")]
     UserSignatureRequired,
+    
+    // Trading settlement errors
+    #[msg("Settlement pool does not match the position's market")]
+    SettlementPoolMismatch,
+    
+    // Specific math overflow errors to pinpoint the failing calculation
+    #[msg("Math overflow while valuing collateral")]
+    CollateralValueOverflow,
+    
+    #[msg("Math overflow while valuing borrows")]
+    BorrowValueOverflow,
+    
+    #[msg("Math overflow while accruing interest rates")]
+    RateAccrualOverflow,
+    
+    #[msg("Math overflow while calculating PnL")]
+    PnlOverflow,
+    
+    // Flash loan errors
+    #[msg("Flash loan was not repaid with its fee")]
+    FlashLoanNotRepaid,
+    
+    // Market risk errors
+    #[msg("Position would exceed the market's open interest cap")]
+    OpenInterestCapExceeded,
+    
+    // Liquidation errors
+    #[msg("Liquidation amount exceeds the close factor")]
+    ExceedsCloseFactor,
+    
+    // Order errors
+    #[msg("Reduce-only order would increase or flip the position")]
+    ReduceOnlyViolation,
+    
+    // Grace period errors
+    #[msg("Position is still within its liquidation grace period")]
+    LiquidationGracePeriodActive,
+    
+    // Native SOL errors
+    #[msg("Pool asset is not wrapped SOL")]
+    NotNativeSolPool,
+    
+    // Trading errors
+    #[msg("Position value is below the market minimum")]
+    PositionTooSmall,
+    
+    // Risk parameter errors
+    #[msg("Pool loan-to-value must stay below its liquidation threshold")]
+    InvalidRiskParameters,
+    
+    // Deposit cap errors
+    #[msg("Deposit would exceed the pool's total deposit cap")]
+    PoolDepositCapExceeded,
+    
+    #[msg("Deposit would exceed the pool's per-user deposit cap")]
+    UserDepositCapExceeded,
 }
\ No newline at end of file
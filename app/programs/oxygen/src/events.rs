@@ -18,6 +18,7 @@ pub struct WithdrawEvent {
     pub pool: Pubkey,             // Pool where withdrawal was made
     pub asset_mint: Pubkey,       // Asset that was withdrawn
     pub amount: u64,              // Amount withdrawn
+    pub fee: u64,                 // Withdrawal fee kept in reserves
     pub from_collateral: bool,    // Whether withdrawn from collateral
     pub from_lending: bool,       // Whether withdrawn from lending
     pub timestamp: i64,           // When the withdrawal happened
@@ -49,6 +50,7 @@ pub struct BorrowEvent {
     pub pool: Pubkey,             // Pool borrowed from
     pub asset_mint: Pubkey,       // Asset that was borrowed
     pub amount: u64,              // Amount borrowed
+    pub fee: u64,                 // Borrow fee withheld from the amount sent
     pub interest_rate: u64,       // Interest rate at time of borrow
     pub timestamp: i64,           // When the borrow happened
 }
@@ -74,6 +76,7 @@ pub struct LiquidationEvent {
     pub collateral_amount: u64,   // Amount of collateral liquidated
     pub debt_amount: u64,         // Amount of debt repaid
     pub liquidation_bonus: u64,   // Bonus received by liquidator
+    pub insurance_fund_cut: u64,  // Part of the bonus paid into the pool's insurance fund
     pub timestamp: i64,           // When the liquidation happened
 }
 
@@ -93,6 +96,7 @@ pub struct YieldClaimedEvent {
     pub pool: Pubkey,             // Pool where yield was claimed from
     pub asset_mint: Pubkey,       // Asset that generated yield
     pub amount: u64,              // Amount of yield claimed
+    pub reinvested: bool,         // Whether the yield was compounded into the deposit instead of paid out
     pub timestamp: i64,           // When the yield was claimed
 }
 
@@ -104,5 +108,142 @@ pub struct PoolUtilizationUpdatedEvent {
     pub utilization_rate: u64,    // New utilization rate
     pub borrow_interest_rate: u64, // New borrow interest rate
     pub lending_interest_rate: u64, // New lending interest rate
+    pub cumulative_borrow_rate: u128, // Cumulative borrow index after the update
+    pub cumulative_lending_rate: u128, // Cumulative lending index after the update
     pub timestamp: i64,           // When the update happened
+}
+
+#[event]
+pub struct PoolAccountingDriftEvent {
+    pub pool: Pubkey,             // Pool whose totals drifted
+    pub asset_mint: Pubkey,       // Asset in the pool
+    pub expected_amount: u64,     // Amount the user-side accounting tried to remove
+    pub recorded_amount: u64,     // Amount the pool-side accounting actually held
+    pub timestamp: i64,           // When the drift was detected
+}
+
+#[event]
+pub struct ProtocolFeesWithdrawnEvent {
+    pub pool: Pubkey,             // Pool the fees were collected in
+    pub asset_mint: Pubkey,       // Asset the fees are denominated in
+    pub recipient: Pubkey,        // Fee account that received the fees
+    pub amount: u64,              // Amount of protocol fees withdrawn
+    pub timestamp: i64,           // When the withdrawal happened
+}
+
+// Insurance fund events
+#[event]
+pub struct InsuranceFundDepositEvent {
+    pub depositor: Pubkey,        // Account that topped up the fund
+    pub pool: Pubkey,             // Pool whose insurance fund was funded
+    pub asset_mint: Pubkey,       // Asset deposited
+    pub amount: u64,              // Amount added to the fund
+    pub insurance_fund: u64,      // Fund balance after the deposit
+    pub timestamp: i64,           // When the deposit happened
+}
+
+#[event]
+pub struct BadDebtSettledEvent {
+    pub user: Pubkey,             // Borrower whose remaining debt was written off
+    pub pool: Pubkey,             // Pool the debt was owed to
+    pub asset_mint: Pubkey,       // Asset the debt was denominated in
+    pub debt_written_off: u64,    // Principal left unpaid after the borrower's collateral ran out
+    pub covered_by_insurance: u64, // Part of the loss paid from the insurance fund
+    pub socialized: u64,          // Part of the loss spread across depositors
+    pub timestamp: i64,           // When the debt was settled
+}
+
+// Flash loan events
+#[event]
+pub struct FlashLoanEvent {
+    pub borrower: Pubkey,         // User who took the flash loan
+    pub pool: Pubkey,             // Pool the loan was drawn from
+    pub asset_mint: Pubkey,       // Asset that was loaned
+    pub amount: u64,              // Amount loaned
+    pub fee: u64,                 // Fee paid into the reserve
+    pub timestamp: i64,           // When the loan happened
+}
+
+// Position events
+// Emitted when a new leveraged position is created for off-chain monitoring
+#[event]
+pub struct PositionCreatedEvent {
+    pub position_id: u64,         // Leveraged position identifier
+    pub market: Pubkey,           // Market the position trades on
+    pub user: Pubkey,             // Owner of the position
+    pub liquidation_price: u64,   // Price at which the position gets liquidated
+    pub timestamp: i64,           // When the position was created
+}
+
+#[event]
+pub struct PositionLiquidatedEvent {
+    pub position_id: u64,         // Leveraged position identifier
+    pub user: Pubkey,             // Owner of the position
+    pub market: Pubkey,           // Market the position traded on
+    pub liquidation_price: u64,   // Price the position was liquidated at
+    pub remaining_margin: u64,    // Margin returned to the user's available collateral
+    pub margin_lost: u64,         // Margin kept in the quote reserve
+    pub timestamp: i64,           // When the liquidation happened
+}
+
+#[event]
+pub struct LeveragedPositionLiquidatedEvent {
+    pub keeper: Pubkey,           // Keeper that performed the liquidation
+    pub user: Pubkey,             // Owner of the liquidated position
+    pub position_id: u64,         // Leveraged position identifier
+    pub market: Pubkey,           // Market the position traded on
+    pub execution_price: u64,     // Price the position was liquidated at
+    pub keeper_bonus: u64,        // Quote tokens paid to the keeper from remaining margin
+    pub timestamp: i64,           // When the liquidation happened
+}
+
+#[event]
+pub struct FundingPaidEvent {
+    pub user: Pubkey,             // Owner of the position
+    pub market: Pubkey,           // Market the position trades on
+    pub position_id: u64,         // Leveraged position identifier
+    pub amount: i64,              // Funding settled (negative when paid, positive when received)
+    pub flagged_for_liquidation: bool, // Whether the margin couldn't cover the payment
+    pub timestamp: i64,           // When the funding was settled
+}
+
+#[event]
+pub struct FundingLiquidationEvent {
+    pub user: Pubkey,             // Owner of the position
+    pub market: Pubkey,           // Market the position traded on
+    pub position_id: u64,         // Leveraged position identifier
+    pub funding_owed: u64,        // Funding payment the position was charged
+    pub funding_paid: u64,        // Part of the payment its margin covered
+    pub margin_released: u64,     // Margin left over and returned to the user's collateral
+    pub exit_price: u64,          // Oracle mark price the position was closed at
+    pub trading_pnl: i64,         // Trading PnL realized on close, excluding funding
+    pub timestamp: i64,           // When the position was liquidated
+}
+
+#[event]
+pub struct PositionTransferredEvent {
+    pub previous_owner: Pubkey,   // Wallet that owned the position
+    pub new_owner: Pubkey,        // Wallet that now owns the position
+    pub collateral_count: u8,     // Number of collateral entries migrated
+    pub borrow_count: u8,         // Number of borrow entries migrated
+    pub leveraged_position_count: u8, // Number of leveraged positions migrated
+    pub timestamp: i64,           // When the transfer happened
+}
+
+#[event]
+pub struct CollateralFlagChangedEvent {
+    pub user: Pubkey,             // Owner of the deposit
+    pub pool: Pubkey,             // Pool the deposit belongs to
+    pub is_collateral: bool,      // Whether the deposit now backs borrows
+    pub is_lending: bool,         // Whether the deposit is now lent out
+    pub timestamp: i64,           // When the flags changed
+}
+
+#[event]
+pub struct CircuitBreakerTrippedEvent {
+    pub asset_mint: Pubkey,       // Asset of the paused pool
+    pub previous_price: u64,      // Last recorded oracle price
+    pub new_price: u64,           // Oracle price that tripped the breaker
+    pub paused_until: i64,        // When operations resume
+    pub timestamp: i64,           // When the breaker tripped
 }
\ No newline at end of file
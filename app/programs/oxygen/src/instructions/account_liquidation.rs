@@ -0,0 +1,178 @@
+use anchor_lang::prelude::*;
+use std::collections::HashMap;
+use crate::state::{Pool, MarketInfo, PositionStatus};
+use crate::errors::OxygenError;
+use crate::instructions::OrderSide;
+use crate::instructions::liquidate::*;
+use crate::modules::trading::TradingModule;
+use crate::events::LeveragedPositionLiquidatedEvent;
+
+// Unwinds an underwater account in one instruction: its liquidatable leveraged
+// positions on one market are closed first, worst loss first, which frees their
+// margin, and then its debt in the debt pool is liquidated up to the close factor.
+// The liquidator is paid through the lending liquidation bonus; positions closed
+// here pay no separate keeper fee. Positions are closed at the market's oracle mark
+// price, never a price chosen by the liquidator.
+
+#[derive(Accounts)]
+pub struct AccountLiquidation<'info> {
+    // Borrower, debt and collateral pools and the liquidator's token accounts, as
+    // for `liquidate`; extra collateral pools follow as remaining accounts
+    pub liquidation: Liquidate<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market_info.serum_market.as_ref()],
+        bump = market_info.bump,
+    )]
+    pub market_info: Account<'info, MarketInfo>,
+
+    #[account(
+        seeds = [b"pool", base_asset_pool.asset_mint.as_ref()],
+        bump = base_asset_pool.bump,
+        constraint = base_asset_pool.asset_mint == market_info.asset_mint @ OxygenError::SettlementPoolMismatch,
+    )]
+    pub base_asset_pool: Account<'info, Pool>,
+
+    // The market's quote pool, omitted when it is the debt or collateral pool
+    #[account(
+        mut,
+        seeds = [b"pool", quote_asset_pool.asset_mint.as_ref()],
+        bump = quote_asset_pool.bump,
+        constraint = quote_asset_pool.asset_mint == market_info.quote_mint @ OxygenError::SettlementPoolMismatch,
+    )]
+    pub quote_asset_pool: Option<Account<'info, Pool>>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, AccountLiquidation<'info>>
+) -> Result<()> {
+    let accounts = &mut *ctx.accounts;
+    let now = Clock::get()?.unix_timestamp;
+    let liquidator = accounts.liquidation.liquidator.key();
+    let user = accounts.liquidation.user.key();
+    let debt_pool_key = accounts.liquidation.debt_pool.key();
+    let collateral_pool_key = accounts.liquidation.collateral_pool.key();
+    let quote_mint = accounts.market_info.quote_mint;
+    
+    // Use the named quote pool, or whichever lending pool is the market's quote pool,
+    // so the same pool is never loaded twice
+    let quote_pool = match accounts.quote_asset_pool.as_mut() {
+        Some(pool) => {
+            require!(
+                pool.key() != debt_pool_key && pool.key() != collateral_pool_key,
+                OxygenError::InvalidParameter
+            );
+            pool
+        }
+        None if accounts.liquidation.debt_pool.asset_mint == quote_mint => &mut accounts.liquidation.debt_pool,
+        None if accounts.liquidation.collateral_pool.asset_mint == quote_mint => &mut accounts.liquidation.collateral_pool,
+        None => return err!(OxygenError::SettlementPoolMismatch),
+    };
+    
+    // Reject liquidations while either market pool is paused
+    accounts.base_asset_pool.ensure_not_paused()?;
+    quote_pool.ensure_not_paused()?;
+    
+    // Leveraged positions must not be liquidated against a stale market price
+    require!(
+        accounts.base_asset_pool.is_oracle_fresh(now) && quote_pool.is_oracle_fresh(now),
+        OxygenError::StaleOracleData
+    );
+    
+    let mark_price = TradingModule::mark_price(&accounts.base_asset_pool, now)?;
+    
+    let mut pool_data = HashMap::new();
+    pool_data.insert(
+        accounts.base_asset_pool.key(),
+        (accounts.base_asset_pool.health_price(now)?, accounts.base_asset_pool.liquidation_threshold)
+    );
+    pool_data.insert(
+        quote_pool.key(),
+        (quote_pool.health_price(now)?, quote_pool.liquidation_threshold)
+    );
+    
+    // Close the market's liquidatable positions, largest loss first
+    let positions_closed = {
+        let user_position = &mut *accounts.liquidation.user_position.load_mut()?;
+        let market = accounts.market_info.serum_market;
+        
+        let mut candidates = Vec::new();
+        for position in user_position.leveraged_positions() {
+            if position.status != PositionStatus::Open || position.market != market {
+                continue;
+            }
+            
+            let is_liquidatable = match position.side {
+                OrderSide::Buy => mark_price <= position.liquidation_price,
+                OrderSide::Sell => mark_price >= position.liquidation_price,
+            };
+            if !is_liquidatable {
+                continue;
+            }
+            
+            let (pnl, is_profit) = TradingModule::calculate_pnl(
+                position.side,
+                position.entry_price,
+                mark_price,
+                position.size
+            )?;
+            candidates.push((if is_profit { 0 } else { pnl }, position.id));
+        }
+        candidates.sort_by(|a, b| b.0.cmp(&a.0));
+        
+        for (_, position_id) in &candidates {
+            TradingModule::liquidate_position(
+                user_position,
+                &mut accounts.market_info,
+                quote_pool,
+                *position_id,
+                mark_price,
+                &pool_data
+            )?;
+            
+            emit!(LeveragedPositionLiquidatedEvent {
+                keeper: liquidator,
+                user,
+                position_id: *position_id,
+                market,
+                execution_price: mark_price,
+                keeper_bonus: 0,
+                timestamp: now,
+            });
+        }
+        
+        user_position.last_updated = now;
+        candidates.len()
+    };
+    
+    // Then repay as much of the remaining debt as the close factor allows
+    let has_debt = accounts.liquidation.user_position
+        .load()?
+        .borrow_index(&debt_pool_key)
+        .is_some();
+    
+    let debt_liquidated = if has_debt {
+        process_liquidation(
+            ctx.program_id,
+            &mut accounts.liquidation,
+            ctx.remaining_accounts,
+            LiquidationAmount::UpToCloseFactor
+        )?
+    } else {
+        0
+    };
+    
+    require!(
+        positions_closed > 0 || debt_liquidated > 0,
+        OxygenError::CannotLiquidate
+    );
+    
+    msg!(
+        "Account liquidation closed {} leveraged positions and repaid {} debt tokens",
+        positions_closed,
+        debt_liquidated
+    );
+    
+    Ok(())
+}
\ No newline at end of file
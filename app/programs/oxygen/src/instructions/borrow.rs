@@ -1,8 +1,10 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 use std::collections::HashMap;
 use crate::state::{Pool, UserPosition};
 use crate::errors::OxygenError;
+use crate::modules::oracle::OracleModule;
+use crate::modules::collateral::CollateralManager;
 use crate::events::{BorrowEvent, PoolUtilizationUpdatedEvent};
 // Import the wallet integration module
 use crate::modules::wallet_integration::WalletIntegration;
@@ -13,6 +15,19 @@ pub struct BorrowParams {
     pub maintain_collateral_lending: bool, // Whether to maintain lending position while borrowing
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BorrowToHealthParams {
+    pub target_health_factor: u64,    // Health factor to borrow down to (10000 = 1.0)
+    pub maintain_collateral_lending: bool, // Whether to maintain lending position while borrowing
+}
+
+// How much a borrow asks for: a fixed amount, or as much as keeps the
+// position at a target health factor
+enum BorrowAmount {
+    Exact(u64),
+    ToHealth(u64),
+}
+
 #[derive(Accounts)]
 pub struct Borrow<'info> {
     #[account(mut)]
@@ -22,44 +37,69 @@ pub struct Borrow<'info> {
         mut,
         seeds = [b"pool", pool.asset_mint.as_ref()],
         bump = pool.bump,
+        has_one = asset_mint,
     )]
     pub pool: Account<'info, Pool>,
     
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
     #[account(
         mut,
         constraint = user_token_account.mint == pool.asset_mint,
         constraint = user_token_account.owner == user.key(),
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"reserve", pool.key().as_ref()],
         bump,
         constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
     )]
-    pub asset_reserve: Account<'info, TokenAccount>,
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"position", user.key().as_ref()],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key(),
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
     )]
-    pub user_position: Account<'info, UserPosition>,
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    /// CHECK: Pyth price account, validated against the pool's configured oracle
+    #[account(constraint = price_oracle.key() == pool.price_oracle @ OxygenError::InvalidOracleConfig)]
+    pub price_oracle: Option<UncheckedAccount<'info>>,
     
-    pub token_program: Program<'info, anchor_spl::token::Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub clock: Sysvar<'info, Clock>,
 }
 
-pub fn handler(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
-    let amount = params.amount;
-    require!(amount > 0, OxygenError::InvalidParameter);
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, Borrow<'info>>, params: BorrowParams) -> Result<()> {
+    require!(params.amount > 0, OxygenError::InvalidParameter);
+    
+    process_borrow(ctx, BorrowAmount::Exact(params.amount), params.maintain_collateral_lending)
+}
+
+/// Borrow the largest amount that leaves the position at or just above a target health factor
+pub fn borrow_to_health<'info>(ctx: Context<'_, '_, '_, 'info, Borrow<'info>>, params: BorrowToHealthParams) -> Result<()> {
+    require!(params.target_health_factor > 0, OxygenError::InvalidParameter);
     
+    process_borrow(ctx, BorrowAmount::ToHealth(params.target_health_factor), params.maintain_collateral_lending)
+}
+
+fn process_borrow<'info>(
+    ctx: Context<'_, '_, '_, 'info, Borrow<'info>>,
+    requested: BorrowAmount,
+    maintain_collateral_lending: bool
+) -> Result<()> {
     let pool = &mut ctx.accounts.pool;
-    let user_position = &mut ctx.accounts.user_position;
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
     let clock = Clock::get()?;
     
+    // Reject borrows while the pool is paused
+    pool.ensure_not_paused()?;
+    
     // NON-CUSTODIAL: Ensure the pool is immutable and admin-less
     require!(pool.immutable, OxygenError::PoolIsUpgradable);
     require!(pool.admin_less, OxygenError::AdminOperationsNotSupported);
@@ -70,49 +110,134 @@ pub fn handler(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
         &ctx.accounts.user
     )?;
     
+    // Cap how often the position can be changed
+    user_position.record_action(clock.unix_timestamp)?;
+    
     // Update pool rates before any operations
     pool.update_rates(clock.unix_timestamp)?;
     
-    // Check if the pool has enough liquidity
-    require!(
-        pool.total_deposits.checked_sub(pool.total_borrows).ok_or(ErrorCode::MathOverflow)? >= amount,
-        OxygenError::InsufficientLiquidity
-    );
+    // Pull the latest oracle price so health checks use live collateral values
+    OracleModule::refresh_pool_price(pool, ctx.accounts.price_oracle.as_deref())?;
+    
+    // A price jump in this update trips the circuit breaker, so no borrow goes through on it
+    pool.ensure_not_paused()?;
     
     // Calculate maximum borrow amount based on user's collateral
     let mut has_sufficient_collateral = false;
     let mut user_has_collateral_for_asset = false;
     
-    // Create pool data map for health factor calculation
-    // In a real implementation, this would involve fetching oracle prices
+    // Create pool data map for health factor calculation from oracle prices
+    // The borrowed pool is always included, and the user's other collateral and
+    // borrow pools are passed as remaining accounts
+    pool.validate_risk_params()?;
     let mut pool_data = HashMap::new();
-    pool_data.insert(pool.key(), (10000, pool.liquidation_threshold)); // Mock price data
+    pool_data.insert(pool.key(), (pool.health_price(clock.unix_timestamp)?, pool.liquidation_threshold));
+    
+    // Value existing borrows at their interest-inclusive debt
+    let mut borrow_rates = HashMap::new();
+    borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        let other_pool: Account<Pool> = Account::try_from(account_info)?;
+        if other_pool.key() == pool.key() {
+            continue;
+        }
+        
+        other_pool.validate_risk_params()?;
+        pool_data.insert(
+            other_pool.key(),
+            (other_pool.health_price(clock.unix_timestamp)?, other_pool.liquidation_threshold)
+        );
+        borrow_rates.insert(other_pool.key(), other_pool.cumulative_borrow_rate);
+    }
+    
+    // An unpriced collateral pool would understate capacity, and an unpriced borrow
+    // pool would hide debt and let the user borrow past their limit
+    user_position.require_priced_pools(&pool_data)?;
     
     // Track if the user is already lending this asset to keep that status
-    for collateral in &mut user_position.collaterals {
-        if collateral.pool == pool.key() {
-            user_has_collateral_for_asset = true;
-            
-            // Make sure we maintain lending status if the user asked for it
-            if params.maintain_collateral_lending && collateral.is_lending {
-                // We don't need to modify anything - the asset stays in lending pool
-                msg!("Maintaining lending position while borrowing");
-            }
+    if let Some(index) = user_position.collateral_index(&pool.key()) {
+        user_has_collateral_for_asset = true;
+        
+        // Make sure we maintain lending status if the user asked for it
+        if maintain_collateral_lending && user_position.collaterals()[index].is_lending {
+            // We don't need to modify anything - the asset stays in lending pool
+            msg!("Maintaining lending position while borrowing");
         }
     }
     
-    // Calculate borrowing capacity based on all user's collateral
-    let (borrowing_capacity, _) = calculate_borrowing_capacity(user_position, &pool_data)?;
+    // Walk the position once for the pre-borrow health; the capacity check and
+    // the post-borrow health factor both reuse these sums
+    let breakdown_before = user_position.compute_health_breakdown(&pool_data, &borrow_rates)?;
+    let health_factor_before = breakdown_before.health_factor;
+    
+    // Borrowing capacity is the threshold-weighted value of all the user's collateral
+    let borrowing_capacity = breakdown_before.weighted_collateral_value;
     
     // Get current borrow value in USD
-    let current_borrow_value = calculate_borrow_value(user_position, &pool_data)?;
+    let current_borrow_value = breakdown_before.borrowed_value;
+    
+    let amount = match requested {
+        BorrowAmount::Exact(amount) => amount,
+        BorrowAmount::ToHealth(target_health_factor) => {
+            // Borrowing only lowers health, so the position must start above the target
+            require!(
+                health_factor_before > target_health_factor,
+                OxygenError::InvalidParameter
+            );
+            
+            CollateralManager::find_max_borrowable_amount(
+                user_position,
+                &pool_data,
+                &pool.key(),
+                target_health_factor
+            )?
+        }
+    };
+    require!(amount > 0, OxygenError::InvalidParameter);
+    
+    // Check if the pool has enough liquidity
+    require!(
+        pool.total_deposits.checked_sub(pool.total_borrows).ok_or(ErrorCode::MathOverflow)? >= amount,
+        OxygenError::InsufficientLiquidity
+    );
+    
+    // Keep total borrows within the pool's debt ceiling, where 0 means unlimited
+    if pool.debt_ceiling > 0 {
+        let total_borrows_after = pool.total_borrows
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        require!(
+            total_borrows_after <= pool.debt_ceiling,
+            OxygenError::DebtCeilingReached
+        );
+    }
     
     // Check if user can borrow the requested amount
-    let new_borrow_value = current_borrow_value.checked_add(amount as u128).ok_or(ErrorCode::MathOverflow)?;
+    // The new borrow is valued at the borrowed asset's price like the existing ones
+    let (borrow_price, _) = pool_data[&pool.key()];
+    let amount_value = (amount as u128)
+        .checked_mul(borrow_price as u128)
+        .ok_or(OxygenError::BorrowValueOverflow)?;
+    let new_borrow_value = current_borrow_value.checked_add(amount_value).ok_or(ErrorCode::MathOverflow)?;
     has_sufficient_collateral = new_borrow_value <= borrowing_capacity;
     
     require!(has_sufficient_collateral, OxygenError::InsufficientCollateral);
     
+    // Staying within capacity only keeps the position at the liquidation edge, so
+    // new borrows must also leave the stricter opening health factor as a buffer
+    let health_factor_after_borrow = borrowing_capacity
+        .checked_mul(10000)
+        .ok_or(OxygenError::CollateralValueOverflow)?
+        .checked_div(new_borrow_value)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    require!(
+        health_factor_after_borrow >= pool.min_open_health_factor as u128,
+        OxygenError::HealthFactorTooLow
+    );
+    
     // Calculate scaled borrow amount based on the cumulative borrow rate
     let scaled_borrow_amount = (amount as u128)
         .checked_mul(1_000_000_000_000) // 10^12 precision
@@ -125,19 +250,31 @@ pub fn handler(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
         pool.key(), 
         amount, 
         scaled_borrow_amount,
-        pool.get_utilization_rate()  // Current interest rate
+        pool.get_borrow_rate()?,  // Current interest rate
+        clock.unix_timestamp
     )?;
     
+    // The full amount is owed, but the borrow fee is withheld in the reserve
+    let (borrow_fee, host_fee, protocol_fee) = pool.split_borrow_fee(amount)?;
+    let amount_after_fee = amount
+        .checked_sub(borrow_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    pool.accrued_host_fees = pool.accrued_host_fees
+        .checked_add(host_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    pool.accrued_protocol_fees = pool.accrued_protocol_fees
+        .checked_add(protocol_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
     // Update pool totals
     pool.total_borrows = pool.total_borrows
         .checked_add(amount)
         .ok_or(ErrorCode::MathOverflow)?;
     
-    // Recalculate pool utilization rate after borrow
-    pool.update_utilization_rate()?;
-    
-    // Calculate health factor before the transfer
-    let health_factor_before = user_position.calculate_health_factor(&pool_data)?;
+    // Borrows draw from lent funds first, so shrink the remaining lendable supply
+    pool.available_lending_supply = pool.available_lending_supply.saturating_sub(amount);
     
     // Transfer tokens from pool reserve to user
     let pool_seeds = &[
@@ -158,8 +295,15 @@ pub fn handler(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
         &[0u8, 0u8, 0u8, 0u8] // Placeholder for actual instruction data
     )?;
     
-    let cpi_accounts = Transfer {
+    // Check the reserve actually holds the tokens, so accounting drift fails clearly
+    require!(
+        ctx.accounts.asset_reserve.amount >= amount_after_fee,
+        OxygenError::InsufficientReserves
+    );
+    
+    let cpi_accounts = TransferChecked {
         from: ctx.accounts.asset_reserve.to_account_info(),
+        mint: ctx.accounts.asset_mint.to_account_info(),
         to: ctx.accounts.user_token_account.to_account_info(),
         authority: ctx.accounts.pool.to_account_info(),
     };
@@ -170,10 +314,12 @@ pub fn handler(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
         pool_signer,
     );
     
-    token::transfer(cpi_context, amount)?;
+    token_interface::transfer_checked(cpi_context, amount_after_fee, ctx.accounts.asset_mint.decimals)?;
     
-    // Recalculate health factor after the borrow
-    let health_factor_after = user_position.calculate_health_factor(&pool_data)?;
+    // The new debt is valued like in the capacity check, so the position doesn't need another pass
+    let health_factor_after = user_position.record_health_factor(
+        breakdown_before.health_factor_with_borrow(amount_value)?
+    )?;
     user_position.last_updated = clock.unix_timestamp;
     
     // Emit borrow event
@@ -182,6 +328,7 @@ pub fn handler(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
         pool: pool.key(),
         asset_mint: pool.asset_mint,
         amount,
+        fee: borrow_fee,
         interest_rate: pool.get_borrow_rate()?,
         timestamp: clock.unix_timestamp,
     });
@@ -194,6 +341,8 @@ pub fn handler(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
         utilization_rate,
         borrow_interest_rate: pool.get_borrow_rate()?,
         lending_interest_rate: pool.get_lending_rate()?,
+        cumulative_borrow_rate: pool.cumulative_borrow_rate,
+        cumulative_lending_rate: pool.cumulative_lending_rate,
         timestamp: clock.unix_timestamp,
     });
     
@@ -205,65 +354,4 @@ pub fn handler(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
     );
     
     Ok(())
-}
-
-/// Calculate the maximum borrowing capacity of a user based on their collateral
-fn calculate_borrowing_capacity(
-    user_position: &UserPosition,
-    pool_data: &HashMap<Pubkey, (u64, u64)>
-) -> Result<(u128, u128)> {
-    let mut total_collateral_value = 0u128;
-    let mut weighted_collateral_value = 0u128;
-    
-    // Calculate collateral value
-    for collateral in &user_position.collaterals {
-        if !collateral.is_collateral {
-            continue;
-        }
-        
-        if let Some((price, liquidation_threshold)) = pool_data.get(&collateral.pool) {
-            let value = (collateral.amount_deposited as u128)
-                .checked_mul(*price as u128)
-                .ok_or(ErrorCode::MathOverflow)?;
-            
-            let weighted_value = value
-                .checked_mul(*liquidation_threshold as u128)
-                .ok_or(ErrorCode::MathOverflow)?
-                .checked_div(10000)
-                .ok_or(ErrorCode::MathOverflow)?;
-            
-            total_collateral_value = total_collateral_value
-                .checked_add(value)
-                .ok_or(ErrorCode::MathOverflow)?;
-                
-            weighted_collateral_value = weighted_collateral_value
-                .checked_add(weighted_value)
-                .ok_or(ErrorCode::MathOverflow)?;
-        }
-    }
-    
-    Ok((weighted_collateral_value, total_collateral_value))
-}
-
-/// Calculate the current borrow value in USD
-fn calculate_borrow_value(
-    user_position: &UserPosition,
-    pool_data: &HashMap<Pubkey, (u64, u64)>
-) -> Result<u128> {
-    let mut total_borrowed_value = 0u128;
-    
-    // Calculate borrowed value
-    for borrow in &user_position.borrows {
-        if let Some((price, _)) = pool_data.get(&borrow.pool) {
-            let value = (borrow.amount_borrowed as u128)
-                .checked_mul(*price as u128)
-                .ok_or(ErrorCode::MathOverflow)?;
-            
-            total_borrowed_value = total_borrowed_value
-                .checked_add(value)
-                .ok_or(ErrorCode::MathOverflow)?;
-        }
-    }
-    
-    Ok(total_borrowed_value)
 }
\ No newline at end of file
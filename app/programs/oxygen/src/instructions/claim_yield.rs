@@ -1,8 +1,9 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Transfer};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use crate::state::{Pool, UserPosition};
 use crate::errors::OxygenError;
 use crate::modules::yield_generation::YieldModule;
+use crate::events::PoolUtilizationUpdatedEvent;
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct ClaimYieldParams {
@@ -18,46 +19,73 @@ pub struct ClaimYield<'info> {
         mut,
         seeds = [b"pool", pool.asset_mint.as_ref()],
         bump = pool.bump,
+        has_one = asset_mint,
     )]
     pub pool: Account<'info, Pool>,
     
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
     #[account(
         mut,
         constraint = user_token_account.mint == pool.asset_mint,
         constraint = user_token_account.owner == user.key(),
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"reserve", pool.key().as_ref()],
         bump,
         constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
     )]
-    pub asset_reserve: Account<'info, TokenAccount>,
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"position", user.key().as_ref()],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key(),
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
     )]
-    pub user_position: Account<'info, UserPosition>,
+    pub user_position: AccountLoader<'info, UserPosition>,
     
-    pub token_program: Program<'info, anchor_spl::token::Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundYield<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
 }
 
 pub fn handler(ctx: Context<ClaimYield>, params: ClaimYieldParams) -> Result<()> {
     let pool = &mut ctx.accounts.pool;
-    let user_position = &mut ctx.accounts.user_position;
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
     let clock = Clock::get()?;
     
+    // Reject claims while the pool is paused
+    pool.ensure_not_paused()?;
+    
     // Update pool rates and yields before claiming
     pool.update_rates(clock.unix_timestamp)?;
     
     // Check if the user has any lending position in this pool
     let mut has_lending_position = false;
-    for collateral in &user_position.collaterals {
+    for collateral in user_position.collaterals() {
         if collateral.pool == pool.key() && collateral.is_lending {
             has_lending_position = true;
             break;
@@ -66,74 +94,34 @@ pub fn handler(ctx: Context<ClaimYield>, params: ClaimYieldParams) -> Result<()>
     
     require!(has_lending_position, OxygenError::CollateralNotFound);
     
-    // Calculate accrued yield
-    let accrued_yield = YieldModule::claim_yield(
-        pool,
-        user_position,
-        &pool.key(),
-        clock.unix_timestamp
-    )?;
-    
-    require!(accrued_yield > 0, OxygenError::InvalidParameter);
-    
-    // Check if reinvestment is requested
+    // Accrued yield is valued at the cumulative lending rate, and the position's
+    // scaled amount is reset against the current rate so it can't be claimed twice
     if params.reinvest {
-        // If reinvesting, add to the user's collateral position
-        for collateral in &mut user_position.collaterals {
-            if collateral.pool == pool.key() && collateral.is_lending {
-                // Add yield to the deposit
-                collateral.amount_deposited = collateral.amount_deposited
-                    .checked_add(accrued_yield)
-                    .ok_or(ErrorCode::MathOverflow)?;
-                
-                // Update scaled amount to reflect the new deposit
-                let additional_scaled = pool.deposit_to_scaled(accrued_yield)?;
-                collateral.amount_scaled = collateral.amount_scaled
-                    .checked_add(additional_scaled)
-                    .ok_or(ErrorCode::MathOverflow)?;
-                
-                break;
-            }
-        }
-        
-        // Update pool totals to reflect the reinvestment
-        pool.total_deposits = pool.total_deposits
-            .checked_add(accrued_yield)
-            .ok_or(ErrorCode::MathOverflow)?;
-            
-        if params.reinvest {
-            // If reinvesting, also update the available lending supply
-            pool.available_lending_supply = pool.available_lending_supply
-                .checked_add(accrued_yield)
-                .ok_or(ErrorCode::MathOverflow)?;
-        }
+        // Reinvested yield is compounded into the deposit without leaving the reserve
+        let accrued_yield = YieldModule::compound_yield(
+            pool,
+            user_position,
+            &ctx.accounts.user,
+            clock.unix_timestamp,
+        )?;
         
-        pool.update_utilization_rate()?;
+        require!(accrued_yield > 0, OxygenError::InvalidParameter);
         
         msg!("Reinvested yield of {} tokens", accrued_yield);
     } else {
-        // If not reinvesting, transfer tokens to the user
-        let pool_seeds = &[
-            b"pool".as_ref(),
-            pool.asset_mint.as_ref(),
-            &[pool.bump],
-        ];
-        
-        let pool_signer = &[&pool_seeds[..]];
-        
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.asset_reserve.to_account_info(),
-            to: ctx.accounts.user_token_account.to_account_info(),
-            authority: ctx.accounts.pool.to_account_info(),
-        };
-        
-        let cpi_context = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts,
-            pool_signer,
-        );
+        // If not reinvesting, the yield module transfers tokens to the user
+        let accrued_yield = YieldModule::claim_yield(
+            pool,
+            user_position,
+            &ctx.accounts.asset_mint,
+            &ctx.accounts.asset_reserve,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.token_program,
+            &ctx.accounts.user,
+            clock.unix_timestamp,
+        )?;
         
-        token::transfer(cpi_context, accrued_yield)?;
+        require!(accrued_yield > 0, OxygenError::InvalidParameter);
         
         msg!("Claimed yield of {} tokens", accrued_yield);
     }
@@ -141,5 +129,43 @@ pub fn handler(ctx: Context<ClaimYield>, params: ClaimYieldParams) -> Result<()>
     // Update user position's last updated timestamp
     user_position.last_updated = clock.unix_timestamp;
     
+    // Emit pool utilization updated event so indexers see the rate advance
+    emit!(PoolUtilizationUpdatedEvent {
+        pool: pool.key(),
+        asset_mint: pool.asset_mint,
+        utilization_rate: pool.get_utilization_rate(),
+        borrow_interest_rate: pool.get_borrow_rate()?,
+        lending_interest_rate: pool.get_lending_rate()?,
+        cumulative_borrow_rate: pool.cumulative_borrow_rate,
+        cumulative_lending_rate: pool.cumulative_lending_rate,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+/// Compound accrued lending yield into the user's deposit
+/// Unlike claim_yield, no token accounts are involved since the yield never leaves the reserve
+pub fn compound_yield(ctx: Context<CompoundYield>) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
+    let clock = Clock::get()?;
+    
+    pool.ensure_not_paused()?;
+    pool.update_rates(clock.unix_timestamp)?;
+    
+    let compounded = YieldModule::compound_yield(
+        pool,
+        user_position,
+        &ctx.accounts.user,
+        clock.unix_timestamp,
+    )?;
+    
+    require!(compounded > 0, OxygenError::InvalidParameter);
+    
+    user_position.last_updated = clock.unix_timestamp;
+    
+    msg!("Compounded yield of {} tokens", compounded);
+    
     Ok(())
 }
\ No newline at end of file
@@ -1,8 +1,10 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Transfer};
+use anchor_lang::system_program;
+use anchor_spl::token_interface::{self, CloseAccount, Mint, SyncNative, TokenAccount, TokenInterface, TransferChecked};
 use std::collections::HashMap;
 use crate::state::{Pool, UserPosition};
 use crate::errors::OxygenError;
+use crate::modules::oracle::OracleModule;
 use crate::modules::yield_generation::YieldModule;
 use crate::events::{DepositEvent, LendingEnabledEvent, PoolUtilizationUpdatedEvent};
 
@@ -22,42 +24,200 @@ pub struct Deposit<'info> {
         mut,
         seeds = [b"pool", pool.asset_mint.as_ref()],
         bump = pool.bump,
+        has_one = asset_mint,
     )]
     pub pool: Account<'info, Pool>,
     
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
     #[account(
         mut,
         constraint = user_token_account.mint == pool.asset_mint,
         constraint = user_token_account.owner == user.key(),
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"reserve", pool.key().as_ref()],
         bump,
         constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
     )]
-    pub asset_reserve: Account<'info, TokenAccount>,
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"position", user.key().as_ref()],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key(),
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
     )]
-    pub user_position: Account<'info, UserPosition>,
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    /// CHECK: Pyth price account, validated against the pool's configured oracle
+    #[account(constraint = price_oracle.key() == pool.price_oracle @ OxygenError::InvalidOracleConfig)]
+    pub price_oracle: Option<UncheckedAccount<'info>>,
     
-    pub token_program: Program<'info, anchor_spl::token::Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub clock: Sysvar<'info, Clock>,
 }
 
+#[derive(Accounts)]
+pub struct DepositSol<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+        has_one = asset_mint,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
+    /// Temporary wSOL account the lamports are wrapped into, closed before the instruction ends
+    #[account(
+        init,
+        payer = user,
+        token::mint = asset_mint,
+        token::authority = user,
+        seeds = [b"wsol", user.key().as_ref()],
+        bump
+    )]
+    pub wsol_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"reserve", pool.key().as_ref()],
+        bump,
+        constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
+    )]
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    /// CHECK: Pyth price account, validated against the pool's configured oracle
+    #[account(constraint = price_oracle.key() == pool.price_oracle @ OxygenError::InvalidOracleConfig)]
+    pub price_oracle: Option<UncheckedAccount<'info>>,
+    
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Accounts the deposit itself works on, so token and native SOL deposits can
+// share it with a different source account
+struct DepositAccounts<'a, 'info> {
+    user: &'a Signer<'info>,
+    pool: &'a mut Account<'info, Pool>,
+    asset_mint: &'a InterfaceAccount<'info, Mint>,
+    source_token_account: &'a InterfaceAccount<'info, TokenAccount>,
+    asset_reserve: &'a mut InterfaceAccount<'info, TokenAccount>,
+    user_position: &'a AccountLoader<'info, UserPosition>,
+    price_oracle: Option<&'a AccountInfo<'info>>,
+    token_program: &'a Interface<'info, TokenInterface>,
+}
+
 pub fn handler(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
+    let accounts = ctx.accounts;
+    
+    process_deposit(
+        DepositAccounts {
+            user: &accounts.user,
+            pool: &mut accounts.pool,
+            asset_mint: &accounts.asset_mint,
+            source_token_account: &accounts.user_token_account,
+            asset_reserve: &mut accounts.asset_reserve,
+            user_position: &accounts.user_position,
+            price_oracle: accounts.price_oracle.as_deref(),
+            token_program: &accounts.token_program,
+        },
+        params,
+    )
+}
+
+/// Deposit native SOL into a wrapped-SOL pool
+/// The lamports are wrapped in a temporary wSOL account that is closed once
+/// its tokens have moved into the reserve, refunding its rent to the user
+pub fn deposit_sol(ctx: Context<DepositSol>, params: DepositParams) -> Result<()> {
+    require!(ctx.accounts.pool.is_native_sol(), OxygenError::NotNativeSolPool);
+    require!(params.amount > 0, OxygenError::InvalidParameter);
+    
+    // The wallet funds the wrapped amount and the temporary account's rent (165 bytes
+    // for a plain token account) and must still be rent-exempt itself. The rent was
+    // already moved into the temporary account when it was created, so count it there
+    let rent = Rent::get()?;
+    let wsol_info = ctx.accounts.wsol_account.to_account_info();
+    let required_balance = params.amount
+        .checked_add(rent.minimum_balance(wsol_info.data_len()))
+        .and_then(|required| required.checked_add(rent.minimum_balance(0)))
+        .ok_or(ErrorCode::MathOverflow)?;
+    let available_balance = ctx.accounts.user.lamports()
+        .checked_add(wsol_info.lamports())
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(available_balance >= required_balance, OxygenError::InsufficientBalance);
+    
+    // Wrap: move the lamports into the temporary account and sync its token balance
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.user.to_account_info(),
+                to: ctx.accounts.wsol_account.to_account_info(),
+            },
+        ),
+        params.amount,
+    )?;
+    
+    token_interface::sync_native(CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        SyncNative {
+            account: ctx.accounts.wsol_account.to_account_info(),
+        },
+    ))?;
+    ctx.accounts.wsol_account.reload()?;
+    
+    let accounts = &mut *ctx.accounts;
+    process_deposit(
+        DepositAccounts {
+            user: &accounts.user,
+            pool: &mut accounts.pool,
+            asset_mint: &accounts.asset_mint,
+            source_token_account: &accounts.wsol_account,
+            asset_reserve: &mut accounts.asset_reserve,
+            user_position: &accounts.user_position,
+            price_oracle: accounts.price_oracle.as_deref(),
+            token_program: &accounts.token_program,
+        },
+        params,
+    )?;
+    
+    // The temporary account is emptied by the deposit, so closing it only returns its rent.
+    // If any step above fails the whole transaction reverts, so it never outlives the call
+    token_interface::close_account(CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.wsol_account.to_account_info(),
+            destination: ctx.accounts.user.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        },
+    ))
+}
+
+fn process_deposit(accounts: DepositAccounts<'_, '_>, params: DepositParams) -> Result<()> {
     let amount = params.amount;
     require!(amount > 0, OxygenError::InvalidParameter);
     
-    let pool = &mut ctx.accounts.pool;
-    let user_position = &mut ctx.accounts.user_position;
+    let pool = &mut *accounts.pool;
+    let user_position = &mut accounts.user_position.load_mut()?;
     let clock = Clock::get()?;
     
     // Ensure the pool is non-custodial and immutable
@@ -65,19 +225,17 @@ pub fn handler(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
     require!(pool.admin_less, OxygenError::AdminOperationsNotSupported);
     
     // Verify that operations are not paused - should never be possible in admin_less mode
-    if pool.operation_state_flags & 0x1 != 0 {
-        return Err(OxygenError::OperationPaused.into());
-    }
+    pool.ensure_not_paused()?;
     
     // Strictly enforce user signature - only users can move their funds
     require!(
-        ctx.accounts.user.is_signer,
+        accounts.user.is_signer,
         OxygenError::UserSignatureRequired
     );
     
     // Ensure position belongs to the current user
     require!(
-        user_position.owner == ctx.accounts.user.key(),
+        user_position.owner == accounts.user.key(),
         OxygenError::OnlyPositionOwnerAllowed
     );
     
@@ -90,9 +248,10 @@ pub fn handler(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
     if clock.unix_timestamp - user_position.last_updated < 10 { // 10 second cooldown
         return Err(OxygenError::PositionModificationCooldown.into());
     }
+    user_position.record_action(clock.unix_timestamp)?;
     
     // Check if the user has enough token balance
-    let user_token_balance = ctx.accounts.user_token_account.amount;
+    let user_token_balance = accounts.source_token_account.amount;
     if user_token_balance < amount {
         return Err(OxygenError::InsufficientBalance.into());
     }
@@ -103,12 +262,79 @@ pub fn handler(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
         return Err(OxygenError::TransactionSizeExceeded.into());
     }
     
+    // Transfer tokens from the source account to the pool reserve
+    let cpi_accounts = TransferChecked {
+        from: accounts.source_token_account.to_account_info(),
+        mint: accounts.asset_mint.to_account_info(),
+        to: accounts.asset_reserve.to_account_info(),
+        authority: accounts.user.to_account_info(),
+    };
+    
+    let cpi_context = CpiContext::new(
+        accounts.token_program.to_account_info(),
+        cpi_accounts,
+    );
+    
+    let reserve_balance_before = accounts.asset_reserve.amount;
+    token_interface::transfer_checked(cpi_context, amount, accounts.asset_mint.decimals)?;
+    
+    // Token-2022 transfer fees are withheld from the amount sent, so credit
+    // only what actually reached the reserve
+    accounts.asset_reserve.reload()?;
+    let amount = accounts.asset_reserve.amount
+        .checked_sub(reserve_balance_before)
+        .ok_or(OxygenError::MathOverflow)?;
+    require!(amount > 0, OxygenError::InvalidParameter);
+    
     // Update pool rates before any operations
     pool.update_rates(clock.unix_timestamp)?;
     
+    // Pull the latest oracle price so health checks use live collateral values
+    OracleModule::refresh_pool_price(pool, accounts.price_oracle)?;
+    
     // Calculate scaled amount based on the current exchange rate
     // This accounts for accumulated yield in the pool
     let scaled_amount = pool.deposit_to_scaled(amount)?;
+
+    // Reject dust deposits that would round down to a zero scaled balance,
+    // since they would never earn any yield
+    require!(scaled_amount > 0, OxygenError::InvalidParameter);
+
+    // Enforce the pool's deposit caps on the amount actually credited (0 = unlimited)
+    if pool.pool_deposit_cap > 0 {
+        let pool_total_after = pool.total_deposits
+            .checked_add(amount)
+            .ok_or(OxygenError::MathOverflow)?;
+        require!(
+            pool_total_after <= pool.pool_deposit_cap,
+            OxygenError::PoolDepositCapExceeded
+        );
+    }
+    
+    if pool.per_user_deposit_cap > 0 {
+        let user_deposited = user_position.collaterals()
+            .iter()
+            .find(|collateral| collateral.pool == pool.key())
+            .map_or(0, |collateral| collateral.amount_deposited);
+        let user_total_after = user_deposited
+            .checked_add(amount)
+            .ok_or(OxygenError::MathOverflow)?;
+        require!(
+            user_total_after <= pool.per_user_deposit_cap,
+            OxygenError::UserDepositCapExceeded
+        );
+    }
+    
+    // A top-up keeps the existing entry's flags and start time; changing them goes
+    // through set_collateral_status and update_lending_status, which keep the
+    // pool's lending totals in step
+    let existing_flags = user_position.collateral_index(&pool.key())
+        .map(|index| {
+            let collateral = &user_position.collaterals()[index];
+            (collateral.is_collateral, collateral.is_lending)
+        });
+    let (use_as_collateral, enable_lending) = existing_flags
+        .unwrap_or((params.use_as_collateral, params.enable_lending));
     
     // Add deposit to user's collateral position
     user_position.add_collateral(
@@ -119,19 +345,19 @@ pub fn handler(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
     
     // Set the collateral usage flag for this deposit
     // Find the collateral we just added/updated
-    for collateral in &mut user_position.collaterals {
-        if collateral.pool == pool.key() {
-            collateral.is_collateral = params.use_as_collateral;
-            
-            // Set lending status and timestamp
-            collateral.is_lending = params.enable_lending;
+    if let Some(index) = user_position.collateral_index(&pool.key()) {
+        let collateral = &mut user_position.collaterals_mut()[index];
+        collateral.is_collateral = use_as_collateral;
+        
+        // Set lending status and timestamp for a new entry
+        if existing_flags.is_none() {
+            collateral.is_lending = enable_lending;
             collateral.deposit_timestamp = clock.unix_timestamp;
-            break;
         }
     }
     
     // Check lending capacity when enabling lending
-    if params.enable_lending {
+    if enable_lending {
         // Calculate how much is already being lent out
         let total_after_deposit = pool.total_lent.checked_add(amount)
             .ok_or(OxygenError::MathOverflow)?;
@@ -149,53 +375,18 @@ pub fn handler(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
         }
     }
     
-    // Transfer tokens from user to pool reserve
-    let cpi_accounts = Transfer {
-        from: ctx.accounts.user_token_account.to_account_info(),
-        to: ctx.accounts.asset_reserve.to_account_info(),
-        authority: ctx.accounts.user.to_account_info(),
-    };
-    
-    let cpi_context = CpiContext::new(
-        ctx.accounts.token_program.to_account_info(),
-        cpi_accounts,
-    );
-    
-    token::transfer(cpi_context, amount)?;
-    
-    // Update pool totals
-    pool.total_deposits = pool.total_deposits
-        .checked_add(amount)
-        .ok_or(OxygenError::MathOverflow)?;
-    
-    // If lending is enabled, update the lending supply and total_lent
-    if params.enable_lending {
-        pool.available_lending_supply = pool.available_lending_supply
-            .checked_add(amount)
-            .ok_or(OxygenError::MathOverflow)?;
-        
-        pool.total_lent = pool.total_lent
+    // Update pool totals, including the lending supply and total_lent if lending is enabled
+    if enable_lending {
+        pool.add_lent_deposit(amount)?;
+    } else {
+        pool.total_deposits = pool.total_deposits
             .checked_add(amount)
             .ok_or(OxygenError::MathOverflow)?;
     }
     
-    // Recalculate pool utilization rate after deposit
-    pool.update_utilization_rate()?;
-    
     // Update health factor using oracle prices if available
     let mut pool_data = HashMap::new();
-    
-    if pool.price_oracle != Pubkey::default() {
-        // Using oracle price for calculations
-        if (!verify_oracle_freshness(pool)) {
-            return Err(OxygenError::StaleOracleData.into());
-        }
-        
-        pool_data.insert(pool.key(), (pool.last_oracle_price, pool.liquidation_threshold));
-    } else {
-        // Fallback to default pricing
-        pool_data.insert(pool.key(), (10000, pool.liquidation_threshold));
-    }
+    pool_data.insert(pool.key(), (pool.health_price(clock.unix_timestamp)?, pool.liquidation_threshold));
     
     let _ = user_position.calculate_health_factor(&pool_data)?;
     
@@ -203,19 +394,19 @@ pub fn handler(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
     
     // Emit deposit event
     emit!(DepositEvent {
-        user: ctx.accounts.user.key(),
+        user: accounts.user.key(),
         pool: pool.key(),
         asset_mint: pool.asset_mint,
         amount,
-        is_collateral: params.use_as_collateral,
-        is_lending: params.enable_lending,
+        is_collateral: use_as_collateral,
+        is_lending: enable_lending,
         timestamp: clock.unix_timestamp,
     });
     
     // If lending is enabled, also emit a lending enabled event
-    if params.enable_lending {
+    if enable_lending {
         emit!(LendingEnabledEvent {
-            user: ctx.accounts.user.key(),
+            user: accounts.user.key(),
             pool: pool.key(),
             asset_mint: pool.asset_mint,
             amount,
@@ -231,28 +422,17 @@ pub fn handler(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
         utilization_rate,
         borrow_interest_rate: pool.get_borrow_rate()?,
         lending_interest_rate: pool.get_lending_rate()?,
+        cumulative_borrow_rate: pool.cumulative_borrow_rate,
+        cumulative_lending_rate: pool.cumulative_lending_rate,
         timestamp: clock.unix_timestamp,
     });
     
     msg!(
         "Deposited {} tokens to pool (collateral: {}, lending: {})",
         amount,
-        params.use_as_collateral,
-        params.enable_lending
+        use_as_collateral,
+        enable_lending
     );
     
     Ok(())
-}
-
-// Helper function to verify oracle price freshness
-fn verify_oracle_freshness(pool: &Pool) -> bool {
-    if pool.price_oracle == Pubkey::default() {
-        return false;
-    }
-    
-    // Check if the oracle price update is within an acceptable time window
-    let max_oracle_staleness = 300; // 5 minutes in seconds
-    let clock = Clock::get().unwrap();
-    
-    clock.unix_timestamp - pool.last_oracle_update < max_oracle_staleness
 }
\ No newline at end of file
@@ -0,0 +1,84 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::Pool;
+use crate::errors::OxygenError;
+use crate::events::InsuranceFundDepositEvent;
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct DepositToInsuranceFundParams {
+    pub amount: u64,                  // Amount to add to the pool's insurance fund
+}
+
+#[derive(Accounts)]
+pub struct DepositToInsuranceFund<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+        has_one = asset_mint,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        constraint = depositor_token_account.mint == pool.asset_mint,
+        constraint = depositor_token_account.owner == depositor.key(),
+    )]
+    pub depositor_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"reserve", pool.key().as_ref()],
+        bump,
+        constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
+    )]
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+pub fn handler(ctx: Context<DepositToInsuranceFund>, params: DepositToInsuranceFundParams) -> Result<()> {
+    let amount = params.amount;
+    require!(amount > 0, OxygenError::InvalidParameter);
+    
+    let clock = Clock::get()?;
+    
+    // Insurance fund tokens are held in the pool reserve but don't count as deposits
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.depositor_token_account.to_account_info(),
+        mint: ctx.accounts.asset_mint.to_account_info(),
+        to: ctx.accounts.asset_reserve.to_account_info(),
+        authority: ctx.accounts.depositor.to_account_info(),
+    };
+    
+    let cpi_context = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        cpi_accounts,
+    );
+    
+    token_interface::transfer_checked(cpi_context, amount, ctx.accounts.asset_mint.decimals)?;
+    
+    let pool = &mut ctx.accounts.pool;
+    pool.insurance_fund = pool.insurance_fund
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    emit!(InsuranceFundDepositEvent {
+        depositor: ctx.accounts.depositor.key(),
+        pool: pool.key(),
+        asset_mint: pool.asset_mint,
+        amount,
+        insurance_fund: pool.insurance_fund,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    msg!("Added {} to insurance fund of pool {}", amount, pool.key());
+    
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,146 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::Pool;
+use crate::errors::OxygenError;
+use crate::events::FlashLoanEvent;
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct FlashLoanParams {
+    pub amount: u64,                  // Amount to borrow for the duration of the transaction
+    pub callback_data: Vec<u8>,       // Instruction data passed to the callback program
+}
+
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+        has_one = asset_mint,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"reserve", pool.key().as_ref()],
+        bump,
+        constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
+    )]
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = borrower_token_account.mint == pool.asset_mint,
+        constraint = borrower_token_account.owner == borrower.key(),
+    )]
+    pub borrower_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    /// CHECK: Arbitrary program invoked with the loaned funds; it must return them plus the fee
+    #[account(executable, constraint = callback_program.key() != crate::ID)]
+    pub callback_program: AccountInfo<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, FlashLoan<'info>>, params: FlashLoanParams) -> Result<()> {
+    let amount = params.amount;
+    require!(amount > 0, OxygenError::InvalidParameter);
+    
+    let clock = Clock::get()?;
+    let pool = &ctx.accounts.pool;
+    
+    // Reject flash loans while the pool is paused
+    pool.ensure_not_paused()?;
+    
+    let fee = (amount as u128)
+        .checked_mul(pool.flash_loan_fee as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::MathOverflow)? as u64;
+    
+    // Record the reserve balance so repayment can be verified after the callback
+    let balance_before = ctx.accounts.asset_reserve.amount;
+    require!(balance_before >= amount, OxygenError::InsufficientLiquidity);
+    
+    let required_balance = balance_before
+        .checked_add(fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    // Transfer the loan from the pool reserve to the borrower
+    let pool_seeds = &[
+        b"pool".as_ref(),
+        pool.asset_mint.as_ref(),
+        &[pool.bump],
+    ];
+    
+    let pool_signer = &[&pool_seeds[..]];
+    
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.asset_reserve.to_account_info(),
+        mint: ctx.accounts.asset_mint.to_account_info(),
+        to: ctx.accounts.borrower_token_account.to_account_info(),
+        authority: ctx.accounts.pool.to_account_info(),
+    };
+    
+    let cpi_context = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        cpi_accounts,
+        pool_signer,
+    );
+    
+    token_interface::transfer_checked(cpi_context, amount, ctx.accounts.asset_mint.decimals)?;
+    
+    // Hand control to the borrower's program, which receives the remaining
+    // accounts and must transfer the loan plus fee back into the reserve
+    let callback_instruction = Instruction {
+        program_id: ctx.accounts.callback_program.key(),
+        accounts: ctx.remaining_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: params.callback_data,
+    };
+    
+    let mut callback_accounts = ctx.remaining_accounts.to_vec();
+    callback_accounts.push(ctx.accounts.callback_program.clone());
+    
+    invoke(&callback_instruction, &callback_accounts)?;
+    
+    // The whole transaction reverts unless the reserve was made whole plus the fee
+    ctx.accounts.asset_reserve.reload()?;
+    require!(
+        ctx.accounts.asset_reserve.amount >= required_balance,
+        OxygenError::FlashLoanNotRepaid
+    );
+    
+    // The fee stays in the reserve as protocol revenue, withdrawable like borrow fees
+    let pool = &mut ctx.accounts.pool;
+    pool.accrued_protocol_fees = pool.accrued_protocol_fees
+        .checked_add(fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    emit!(FlashLoanEvent {
+        borrower: ctx.accounts.borrower.key(),
+        pool: ctx.accounts.pool.key(),
+        asset_mint: ctx.accounts.pool.asset_mint,
+        amount,
+        fee,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    msg!("Flash loan of {} tokens repaid with {} fee", amount, fee);
+    
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use std::collections::HashMap;
+use crate::state::{Pool, UserPosition};
+use crate::modules::collateral::CollateralManager;
+
+/// Summary of a user's health returned to front-ends and keepers
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct AccountHealth {
+    pub health_factor: u64,          // Current health factor (10000 = 1.0)
+    pub total_collateral_value: u128, // Unweighted value of collateral deposits
+    pub total_borrow_value: u128,    // Value of borrows including accrued interest
+    pub is_liquidatable: bool,       // Whether the position can be liquidated now
+}
+
+#[derive(Accounts)]
+pub struct GetAccountHealth<'info> {
+    /// CHECK: Only used to derive the position address
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    // Every collateral and borrow pool of the user is passed as a remaining account
+}
+
+/// Return the user's current health without mutating state, so it can be run via simulateTransaction
+/// Prices come from each pool's stored oracle price and stale prices are rejected
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, GetAccountHealth<'info>>) -> Result<AccountHealth> {
+    let clock = Clock::get()?;
+    let mut pool_data = HashMap::new();
+    let mut borrow_rates = HashMap::new();
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        let pool: Account<Pool> = Account::try_from(account_info)?;
+        
+        pool_data.insert(pool.key(), (pool.health_price(clock.unix_timestamp)?, pool.liquidation_threshold));
+        borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+    }
+    
+    let user_position = &ctx.accounts.user_position.load()?;
+    let breakdown = user_position.compute_health_breakdown(&pool_data, &borrow_rates)?;
+    
+    // Matches the liquidation check, which requires a health factor below 1.0
+    const LIQUIDATION_THRESHOLD: u64 = 10000;
+    
+    Ok(AccountHealth {
+        health_factor: breakdown.health_factor,
+        total_collateral_value: CollateralManager::calculate_total_collateral_value(user_position, &pool_data)?,
+        total_borrow_value: breakdown.borrowed_value,
+        is_liquidatable: breakdown.health_factor < LIQUIDATION_THRESHOLD,
+    })
+}
\ No newline at end of file
@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+use std::collections::HashMap;
+use crate::state::{Pool, UserPosition};
+use crate::errors::OxygenError;
+
+/// Interest owed on one of a user's borrows, returned to front-ends
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct BorrowDebt {
+    pub pool: Pubkey,                // Pool the asset was borrowed from
+    pub principal: u64,              // Amount originally borrowed and not yet repaid
+    pub current_debt: u64,           // Debt including interest accrued to now
+    pub accrued_interest: u64,       // Interest owed on top of the principal
+}
+
+#[derive(Accounts)]
+pub struct GetBorrowDebt<'info> {
+    /// CHECK: Only used to derive the position address
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    // Every borrow pool of the user is passed as a remaining account
+}
+
+/// Return each of the user's borrows with its interest-inclusive debt without mutating state
+/// Pool rates are advanced to the current time in memory only, so the debt is up to date
+/// even if nobody has touched the pool recently
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, GetBorrowDebt<'info>>) -> Result<Vec<BorrowDebt>> {
+    let clock = Clock::get()?;
+    let mut borrow_rates = HashMap::new();
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        let mut pool: Account<Pool> = Account::try_from(account_info)?;
+        pool.update_rates(clock.unix_timestamp)?;
+        
+        borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+    }
+    
+    let user_position = &ctx.accounts.user_position.load()?;
+    let mut debts = Vec::with_capacity(user_position.borrows().len());
+    
+    for borrow in user_position.borrows() {
+        let cumulative_borrow_rate = borrow_rates
+            .get(&borrow.pool)
+            .ok_or(OxygenError::InvalidParameter)?;
+        let current_debt = borrow.debt_at_rate(*cumulative_borrow_rate)?;
+        
+        debts.push(BorrowDebt {
+            pool: borrow.pool,
+            principal: borrow.amount_borrowed,
+            current_debt,
+            accrued_interest: current_debt.saturating_sub(borrow.amount_borrowed),
+        });
+    }
+    
+    Ok(debts)
+}
\ No newline at end of file
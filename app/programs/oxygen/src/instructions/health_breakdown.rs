@@ -0,0 +1,36 @@
+use anchor_lang::prelude::*;
+use std::collections::HashMap;
+use crate::state::{Pool, UserPosition, HealthBreakdown};
+
+#[derive(Accounts)]
+pub struct ComputeHealthBreakdown<'info> {
+    /// CHECK: Only used to derive the position address
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    // Every collateral and borrow pool of the user is passed as a remaining account
+}
+
+/// Return each component of the user's health factor without mutating state
+/// Pools that aren't passed in are left out of the breakdown
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, ComputeHealthBreakdown<'info>>) -> Result<HealthBreakdown> {
+    let now = Clock::get()?.unix_timestamp;
+    let mut pool_data = HashMap::new();
+    let mut borrow_rates = HashMap::new();
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        let pool: Account<Pool> = Account::try_from(account_info)?;
+        
+        // Price each pool the same way health checks do, rejecting stale oracle prices
+        pool_data.insert(pool.key(), (pool.health_price(now)?, pool.liquidation_threshold));
+        borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+    }
+    
+    ctx.accounts.user_position.load()?.compute_health_breakdown(&pool_data, &borrow_rates)
+}
\ No newline at end of file
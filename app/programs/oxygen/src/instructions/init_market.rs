@@ -0,0 +1,124 @@
+use anchor_lang::prelude::*;
+use anchor_spl::dex::Dex;
+use anchor_spl::token::Mint;
+use crate::state::MarketInfo;
+use crate::errors::OxygenError;
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InitializeMarketParams {
+    pub oracle: Pubkey,                // Price oracle for the market
+    pub optimal_leverage: u64,         // Recommended max leverage (in basis points, 10000 = 1x)
+    pub max_leverage: u64,             // Maximum allowed leverage (in basis points, 10000 = 1x)
+    pub liquidation_fee: u64,          // Fee charged when positions are liquidated (in basis points)
+    pub maintenance_margin_ratio: u64, // Min margin relative to position value (in basis points)
+    pub min_margin: u64,               // Minimum margin per position
+    pub min_position_value: u64,       // Minimum notional per position, so dust positions aren't opened
+    pub max_open_interest: u64,        // Max aggregate notional allowed on each side
+    pub max_oracle_deviation: u64,     // Max market vs pool oracle price deviation (in basis points)
+    pub max_funding_rate: u64,         // Funding rate at fully one-sided open interest (millionths per hour)
+}
+
+#[derive(Accounts)]
+pub struct InitializeMarket<'info> {
+    /// Pays for the market account; gets no special privileges over it
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = payer,
+        space = MarketInfo::space(),
+        seeds = [b"market", serum_market.key().as_ref()],
+        bump
+    )]
+    pub market_info: Account<'info, MarketInfo>,
+    
+    /// CHECK: Serum market the leveraged positions trade on, owned by the DEX program
+    #[account(owner = dex_program.key() @ OxygenError::InvalidSerumMarket)]
+    pub serum_market: UncheckedAccount<'info>,
+    
+    // Serum markets only settle through the legacy token program, so these mints
+    // stay on `anchor_spl::token::Mint` rather than the token interface
+    pub asset_mint: Account<'info, Mint>,
+    
+    #[account(constraint = quote_mint.key() != asset_mint.key() @ OxygenError::InvalidParameter)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    pub dex_program: Program<'info, Dex>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<InitializeMarket>, params: InitializeMarketParams) -> Result<()> {
+    // Leverage is expressed in basis points, so anything below 1x is meaningless
+    require!(
+        params.max_leverage >= 10000,
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.optimal_leverage >= 10000 && params.optimal_leverage <= params.max_leverage,
+        OxygenError::InvalidParameter
+    );
+    
+    // The liquidation price needs 1 / leverage to exceed the maintenance margin
+    // ratio, otherwise positions at max leverage would be liquidatable on open
+    let max_maintenance_margin_ratio = 100_000_000 / params.max_leverage;
+    require!(
+        params.maintenance_margin_ratio > 0
+            && params.maintenance_margin_ratio < max_maintenance_margin_ratio,
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.liquidation_fee <= 2000, // Max 20% fee
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.max_open_interest > 0,
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.max_oracle_deviation > 0 && params.max_oracle_deviation <= 10000,
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.max_funding_rate <= 10000, // Max 1% of position value per hour
+        OxygenError::InvalidParameter
+    );
+    
+    let market_info = &mut ctx.accounts.market_info;
+    
+    market_info.serum_market = ctx.accounts.serum_market.key();
+    market_info.asset_mint = ctx.accounts.asset_mint.key();
+    market_info.quote_mint = ctx.accounts.quote_mint.key();
+    market_info.oracle = params.oracle;
+    market_info.last_oracle_price = 0;
+    market_info.max_oracle_deviation = params.max_oracle_deviation;
+    market_info.optimal_leverage = params.optimal_leverage;
+    market_info.max_leverage = params.max_leverage;
+    market_info.liquidation_fee = params.liquidation_fee;
+    market_info.maintenance_margin_ratio = params.maintenance_margin_ratio;
+    market_info.min_margin = params.min_margin;
+    market_info.min_position_value = params.min_position_value;
+    market_info.long_open_interest = 0;
+    market_info.short_open_interest = 0;
+    market_info.max_open_interest = params.max_open_interest;
+    market_info.funding_pool_balance = 0;
+    market_info.realized_loss_balance = 0;
+    market_info.max_funding_rate = params.max_funding_rate;
+    market_info.funding_rate = 0;
+    market_info.cumulative_funding_index = 0;
+    market_info.last_funding_update = Clock::get()?.unix_timestamp;
+    market_info.bump = *ctx.bumps.get("market_info").unwrap();
+    
+    msg!(
+        "Initialized market for Serum market {} with max leverage {}",
+        market_info.serum_market,
+        market_info.max_leverage
+    );
+    
+    Ok(())
+}
\ No newline at end of file
@@ -1,23 +1,43 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, TokenAccount};
-use crate::state::{Pool};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::{Pool, InterestRateModelKind};
 use crate::errors::OxygenError;
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct InitializePoolParams {
     pub optimal_utilization: u64,    // Optimal utilization rate (in basis points)
+    pub base_rate: u64,              // Borrow rate at zero utilization (in basis points)
+    pub slope1: u64,                 // Borrow rate increase up to optimal utilization (in basis points)
+    pub slope2: u64,                 // Borrow rate increase beyond optimal utilization, or up to the second kink (in basis points)
+    pub interest_rate_model: InterestRateModelKind, // Shape of the borrow rate curve
+    pub second_optimal_utilization: u64, // Second kink for the three-slope model (in basis points)
+    pub slope3: u64,                 // Borrow rate increase beyond the second kink (in basis points)
+    pub debt_ceiling: u64,           // Max total borrows from the pool in token units (0 = unlimited)
     pub loan_to_value: u64,          // Max loan-to-value ratio (in basis points)
     pub liquidation_threshold: u64,  // Liquidation threshold (in basis points)
     pub liquidation_bonus: u64,      // Liquidation bonus (in basis points)
+    pub liquidation_penalty: u64,    // Protocol penalty charged to liquidated borrowers (in basis points)
+    pub liquidation_grace_period: i64, // Seconds a borrower may stay unhealthy before being liquidated
+    pub insurance_fund_share: u64,   // Share of liquidation bonuses paid into the insurance fund (in basis points)
+    pub min_open_health_factor: u64, // Min health factor after a new borrow (in basis points)
+    pub min_withdraw_health_factor: u64, // Min health factor after a collateral withdrawal (in basis points)
     pub borrow_fee: u64,             // Fee for borrowing (in basis points)
     pub flash_loan_fee: u64,         // Fee for flash loans (in basis points)
     pub host_fee_percentage: u8,     // Host fee percentage (0-100)
     pub protocol_fee_percentage: u8, // Protocol fee percentage (0-100)
+    pub pool_deposit_cap: u64,       // Max total deposits in the pool (0 = unlimited)
+    pub per_user_deposit_cap: u64,   // Max deposits per user in the pool (0 = unlimited)
     pub lending_enabled: bool,       // Whether lending is enabled for this pool
     pub max_lending_ratio: u64,      // Maximum % of deposits that can be used for lending (basis points)
     pub min_lending_duration: u64,   // Minimum duration for lending positions in seconds
     pub lending_fee: u64,            // Fee for lending out assets (in basis points)
+    pub lending_withdrawal_fee: u64, // Fee charged on lending withdrawals (in basis points)
     pub lending_interest_share: u64, // Percentage of interest that goes to lenders (basis points)
+    pub max_oracle_staleness: i64,   // Max oracle price age before liquidations are blocked (seconds)
+    pub max_confidence_bps: u64,     // Max oracle confidence interval relative to price (in basis points)
+    pub twap_enabled: bool,          // Whether liquidations use the oracle TWAP instead of the spot price
+    pub max_price_change_bps: u64,   // Max oracle price move in one update before the pool pauses (in basis points, 0 = off)
+    pub circuit_breaker_cooldown: i64, // Seconds the pool stays paused after the circuit breaker trips
     
     /// Ensures the pool cannot be upgraded after deployment
     pub immutable: bool,
@@ -43,7 +63,7 @@ pub struct InitializePool<'info> {
     )]
     pub pool: Account<'info, Pool>,
     
-    pub asset_mint: Account<'info, Mint>,
+    pub asset_mint: InterfaceAccount<'info, Mint>,
     
     #[account(
         init_if_needed,
@@ -53,15 +73,44 @@ pub struct InitializePool<'info> {
         seeds = [b"reserve", pool.key().as_ref()],
         bump
     )]
-    pub asset_reserve: Account<'info, TokenAccount>,
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
     
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, anchor_spl::token::Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 pub fn handler(ctx: Context<InitializePool>, params: InitializePoolParams) -> Result<()> {
     // Validate parameters
+    // Kinked models divide by each segment's utilization span, and their slopes must not decrease
+    match params.interest_rate_model {
+        InterestRateModelKind::TwoSlope => {
+            require!(
+                params.optimal_utilization > 0 && params.optimal_utilization < 10000,
+                OxygenError::InvalidParameter
+            );
+            
+            require!(
+                params.slope1 <= params.slope2,
+                OxygenError::InvalidParameter
+            );
+        }
+        InterestRateModelKind::ThreeSlope => {
+            require!(
+                params.optimal_utilization > 0
+                    && params.optimal_utilization < params.second_optimal_utilization
+                    && params.second_optimal_utilization < 10000,
+                OxygenError::InvalidParameter
+            );
+            
+            require!(
+                params.slope1 <= params.slope2 && params.slope2 <= params.slope3,
+                OxygenError::InvalidParameter
+            );
+        }
+        InterestRateModelKind::Fixed => {}
+    }
+    
     require!(
         params.loan_to_value <= 9000 && params.liquidation_threshold <= 9500,
         OxygenError::InvalidParameter
@@ -72,13 +121,41 @@ pub fn handler(ctx: Context<InitializePool>, params: InitializePoolParams) -> Re
         OxygenError::InvalidParameter
     );
     
+    require!(
+        params.borrow_fee <= 1000, // Max 10% fee
+        OxygenError::InvalidParameter
+    );
+    
     require!(
         params.liquidation_bonus <= 2000,
         OxygenError::InvalidParameter
     );
     
     require!(
-        params.host_fee_percentage + params.protocol_fee_percentage <= 100,
+        params.liquidation_penalty <= 1000, // Max 10% penalty on top of the bonus
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.liquidation_grace_period >= 0,
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.insurance_fund_share <= 10000, // Max 100% of the bonus
+        OxygenError::InvalidParameter
+    );
+    
+    // New borrows and collateral withdrawals must leave a buffer above the 1.0
+    // liquidation point
+    require!(
+        params.min_open_health_factor > 10000 && params.min_withdraw_health_factor > 10000,
+        OxygenError::InvalidParameter
+    );
+    
+    // Sum in u16 so values near u8::MAX can't wrap around and pass the check
+    require!(
+        params.host_fee_percentage as u16 + params.protocol_fee_percentage as u16 <= 100,
         OxygenError::InvalidParameter
     );
 
@@ -93,10 +170,31 @@ pub fn handler(ctx: Context<InitializePool>, params: InitializePoolParams) -> Re
         OxygenError::InvalidParameter
     );
 
+    require!(
+        params.lending_withdrawal_fee <= 1000, // Max 10% fee
+        OxygenError::InvalidParameter
+    );
+
     require!(
         params.lending_interest_share <= 10000, // Max 100%
         OxygenError::InvalidParameter
     );
+
+    // Validate oracle parameters
+    require!(
+        params.max_oracle_staleness > 0,
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.max_confidence_bps > 0 && params.max_confidence_bps <= 10000,
+        OxygenError::InvalidParameter
+    );
+    
+    require!(
+        params.max_price_change_bps == 0 || params.circuit_breaker_cooldown > 0,
+        OxygenError::InvalidParameter
+    );
     
     // Enforce immutability if requested - this makes the pool non-upgradeable
     require!(
@@ -110,6 +208,13 @@ pub fn handler(ctx: Context<InitializePool>, params: InitializePoolParams) -> Re
         OxygenError::PoolMustBeAdminLess
     );
     
+    // The reserve is created with init_if_needed, so make sure we never adopt a
+    // pre-existing token account that already holds funds
+    require!(
+        ctx.accounts.asset_reserve.amount == 0,
+        OxygenError::InvalidParameter
+    );
+    
     let pool = &mut ctx.accounts.pool;
     let clock = Clock::get()?;
     
@@ -118,26 +223,56 @@ pub fn handler(ctx: Context<InitializePool>, params: InitializePoolParams) -> Re
     pool.asset_reserve = ctx.accounts.asset_reserve.key();
     pool.total_deposits = 0;
     pool.total_borrows = 0;
+    pool.debt_ceiling = params.debt_ceiling;
     pool.available_lending_supply = 0;
     pool.cumulative_borrow_rate = 1_000_000_000_000; // Initialize with 10^12 (1.0) for stable math
     pool.cumulative_lending_rate = 1_000_000_000_000; // Initialize with 10^12 (1.0) for stable math
     pool.last_updated = clock.unix_timestamp;
     pool.optimal_utilization = params.optimal_utilization;
+    pool.base_rate = params.base_rate;
+    pool.slope1 = params.slope1;
+    pool.slope2 = params.slope2;
+    pool.interest_rate_model = params.interest_rate_model;
+    pool.second_optimal_utilization = params.second_optimal_utilization;
+    pool.slope3 = params.slope3;
     pool.loan_to_value = params.loan_to_value;
     pool.liquidation_threshold = params.liquidation_threshold;
     pool.liquidation_bonus = params.liquidation_bonus;
+    pool.liquidation_penalty = params.liquidation_penalty;
+    pool.liquidation_grace_period = params.liquidation_grace_period;
+    pool.min_open_health_factor = params.min_open_health_factor;
+    pool.min_withdraw_health_factor = params.min_withdraw_health_factor;
     pool.borrow_fee = params.borrow_fee;
     pool.flash_loan_fee = params.flash_loan_fee;
     pool.host_fee_percentage = params.host_fee_percentage;
     pool.protocol_fee_percentage = params.protocol_fee_percentage;
+    pool.accrued_protocol_fees = 0;
+    pool.accrued_host_fees = 0;
+    pool.pool_deposit_cap = params.pool_deposit_cap;
+    pool.per_user_deposit_cap = params.per_user_deposit_cap;
     
     // Initialize new lending parameters
     pool.lending_enabled = params.lending_enabled;
     pool.max_lending_ratio = params.max_lending_ratio;
     pool.min_lending_duration = params.min_lending_duration;
     pool.lending_fee = params.lending_fee;
+    pool.lending_withdrawal_fee = params.lending_withdrawal_fee;
     pool.lending_interest_share = params.lending_interest_share;
     pool.total_lent = 0; // Initialize total amount being lent out
+    pool.bad_debt = 0;
+    pool.insurance_fund = 0;
+    pool.insurance_fund_share = params.insurance_fund_share;
+    
+    // Initialize oracle parameters
+    pool.max_oracle_staleness = params.max_oracle_staleness;
+    pool.max_confidence_bps = params.max_confidence_bps;
+    pool.twap_enabled = params.twap_enabled;
+    pool.price_samples = [0; Pool::PRICE_SAMPLE_SLOTS];
+    pool.price_sample_count = 0;
+    pool.price_sample_index = 0;
+    pool.max_price_change_bps = params.max_price_change_bps;
+    pool.circuit_breaker_cooldown = params.circuit_breaker_cooldown;
+    pool.paused_until = 0;
     
     // Initialize ownership and immutability settings
     pool.user_deposits_authority = ctx.accounts.authority.key();
@@ -0,0 +1,40 @@
+use anchor_lang::prelude::*;
+use crate::state::UserPosition;
+
+// Every user-facing instruction derives the position PDA from the user's wallet
+// and expects it to already be initialized, so this must be the first call a
+// new user makes before depositing.
+
+#[derive(Accounts)]
+pub struct InitializeUserPosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        space = UserPosition::space(),
+        seeds = [b"position", user.key().as_ref()],
+        bump
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<InitializeUserPosition>) -> Result<()> {
+    // A fresh zero-copy account is zeroed, so every list starts out empty
+    let user_position = &mut ctx.accounts.user_position.load_init()?;
+    let clock = Clock::get()?;
+
+    user_position.owner = ctx.accounts.user.key();
+    user_position.locked_trading_margin = 0;
+    user_position.health_factor = u64::MAX; // No borrows, so perfectly healthy
+    user_position.unhealthy_since = 0;
+    user_position.last_updated = clock.unix_timestamp;
+    user_position.bump = *ctx.bumps.get("user_position").unwrap();
+
+    msg!("Initialized position for {}", user_position.owner);
+
+    Ok(())
+}
\ No newline at end of file
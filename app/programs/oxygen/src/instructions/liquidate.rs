@@ -1,8 +1,11 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 use std::collections::HashMap;
 use crate::state::{Pool, UserPosition};
 use crate::errors::OxygenError;
+use crate::modules::oracle::OracleModule;
+use crate::modules::liquidation::LiquidationEngine;
+use crate::events::{RepayEvent, PoolUtilizationUpdatedEvent, BadDebtSettledEvent, LiquidationEvent};
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct LiquidateParams {
@@ -24,6 +27,9 @@ pub struct Liquidate<'info> {
     )]
     pub debt_pool: Account<'info, Pool>,
     
+    #[account(address = debt_pool.asset_mint)]
+    pub debt_mint: InterfaceAccount<'info, Mint>,
+    
     #[account(
         mut,
         seeds = [b"pool", collateral_pool.asset_mint.as_ref()],
@@ -31,133 +37,364 @@ pub struct Liquidate<'info> {
     )]
     pub collateral_pool: Account<'info, Pool>,
     
+    #[account(address = collateral_pool.asset_mint)]
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+    
     #[account(
         mut,
         constraint = debt_reserve.mint == debt_pool.asset_mint,
+        constraint = debt_reserve.key() == debt_pool.asset_reserve,
     )]
-    pub debt_reserve: Account<'info, TokenAccount>,
+    pub debt_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         constraint = collateral_reserve.mint == collateral_pool.asset_mint,
+        constraint = collateral_reserve.key() == collateral_pool.asset_reserve,
     )]
-    pub collateral_reserve: Account<'info, TokenAccount>,
+    pub collateral_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         constraint = liquidator_debt_token_account.mint == debt_pool.asset_mint,
         constraint = liquidator_debt_token_account.owner == liquidator.key(),
     )]
-    pub liquidator_debt_token_account: Account<'info, TokenAccount>,
+    pub liquidator_debt_token_account: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         constraint = liquidator_collateral_token_account.mint == collateral_pool.asset_mint,
         constraint = liquidator_collateral_token_account.owner == liquidator.key(),
     )]
-    pub liquidator_collateral_token_account: Account<'info, TokenAccount>,
+    pub liquidator_collateral_token_account: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"position", user.key().as_ref()],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key(),
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
     )]
-    pub user_position: Account<'info, UserPosition>,
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    /// CHECK: Pyth price account, validated against the debt pool's configured oracle
+    #[account(constraint = debt_price_oracle.key() == debt_pool.price_oracle @ OxygenError::InvalidOracleConfig)]
+    pub debt_price_oracle: Option<UncheckedAccount<'info>>,
+    
+    /// CHECK: Pyth price account, validated against the collateral pool's configured oracle
+    #[account(constraint = collateral_price_oracle.key() == collateral_pool.price_oracle @ OxygenError::InvalidOracleConfig)]
+    pub collateral_price_oracle: Option<UncheckedAccount<'info>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    
+    // Extra collateral pools to seize from when the named one isn't enough are passed
+    // as remaining accounts in groups of (pool, mint, reserve, liquidator token account)
+}
+
+// How much debt a liquidation repays: a fixed amount, or as much as the close
+// factor allows
+pub(crate) enum LiquidationAmount {
+    Exact(u64),
+    UpToCloseFactor,
+}
+
+// Collateral pool passed as remaining accounts, along with its mint, its reserve
+// and the liquidator's token account for its asset
+struct ExtraCollateral<'info> {
+    pool: Account<'info, Pool>,
+    mint: InterfaceAccount<'info, Mint>,
+    reserve: InterfaceAccount<'info, TokenAccount>,
+    liquidator_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+// Oracle prices and mint decimals of a debt and a collateral asset, used to
+// convert token amounts of one into the other
+pub(crate) struct SeizurePrices {
+    pub debt_price: u64,
+    pub debt_decimals: u8,
+    pub collateral_price: u64,
+    pub collateral_decimals: u8,
+}
+
+impl SeizurePrices {
+    // Collateral tokens worth `amount` debt tokens
+    fn debt_to_collateral(&self, amount: u64) -> Result<u64> {
+        convert_at_price(amount, self.debt_price, self.debt_decimals, self.collateral_price, self.collateral_decimals)
+    }
+    
+    // Debt tokens worth `amount` collateral tokens
+    fn collateral_to_debt(&self, amount: u64) -> Result<u64> {
+        convert_at_price(amount, self.collateral_price, self.collateral_decimals, self.debt_price, self.debt_decimals)
+    }
+}
+
+// Helper function to convert `amount` base units of one asset into base units of
+// another worth the same at the given prices
+fn convert_at_price(amount: u64, from_price: u64, from_decimals: u8, to_price: u64, to_decimals: u8) -> Result<u64> {
+    require!(to_price > 0, OxygenError::InvalidOracleData);
+    
+    let converted = (amount as u128)
+        .checked_mul(from_price as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_mul(10u128.pow(to_decimals as u32))
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div((to_price as u128) * 10u128.pow(from_decimals as u32))
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    let converted = u64::try_from(converted)
+        .map_err(|_| ErrorCode::MathOverflow)?;
     
-    pub token_program: Program<'info, anchor_spl::token::Token>,
+    Ok(converted)
 }
 
-pub fn handler(ctx: Context<Liquidate>, params: LiquidateParams) -> Result<()> {
+// Collateral taken from a single pool and the debt it pays for
+pub(crate) struct Seizure {
+    pub debt_covered: u64,           // Debt repaid in exchange for this collateral
+    pub collateral_repaid: u64,      // Collateral worth the debt covered, before any bonus
+    pub liquidator_collateral: u64,  // Collateral sent to the liquidator
+    pub penalty: u64,                // Protocol penalty left in the reserve
+    pub insurance_cut: u64,          // Share of the bonus paid into the insurance fund
+}
+
+impl Seizure {
+    pub(crate) fn total_seized(&self) -> Result<u64> {
+        self.liquidator_collateral
+            .checked_add(self.penalty)
+            .and_then(|total| total.checked_add(self.insurance_cut))
+            .ok_or(ErrorCode::MathOverflow.into())
+    }
+    
+    // Bonus the liquidator keeps once the insurance fund's share is taken out
+    pub(crate) fn liquidator_bonus(&self) -> u64 {
+        self.liquidator_collateral.saturating_sub(self.collateral_repaid)
+    }
+    
+    // The penalty and insurance cut no longer belong to any depositor, so they
+    // leave total_deposits while their tokens remain in the reserve, claimed by
+    // the protocol fees and the insurance fund respectively
+    fn apply_to_pool(&self, collateral_pool: &mut Pool) -> Result<()> {
+        collateral_pool.total_deposits = collateral_pool.total_deposits
+            .checked_sub(self.total_seized()?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        collateral_pool.accrued_protocol_fees = collateral_pool.accrued_protocol_fees
+            .checked_add(self.penalty)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        collateral_pool.insurance_fund = collateral_pool.insurance_fund
+            .checked_add(self.insurance_cut)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        Ok(())
+    }
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, Liquidate<'info>>, params: LiquidateParams) -> Result<()> {
     require!(params.amount > 0, OxygenError::InvalidParameter);
     
-    let debt_pool = &mut ctx.accounts.debt_pool;
-    let collateral_pool = &mut ctx.accounts.collateral_pool;
-    let user_position = &mut ctx.accounts.user_position;
+    process_liquidation(
+        ctx.program_id,
+        ctx.accounts,
+        ctx.remaining_accounts,
+        LiquidationAmount::Exact(params.amount)
+    )?;
+    
+    Ok(())
+}
+
+/// Liquidate the borrower's debt in the debt pool, returning the amount repaid
+pub(crate) fn process_liquidation<'info>(
+    program_id: &Pubkey,
+    accounts: &mut Liquidate<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    requested: LiquidationAmount
+) -> Result<u64> {
+    let debt_pool = &mut accounts.debt_pool;
+    let collateral_pool = &mut accounts.collateral_pool;
+    let user_position = &mut accounts.user_position.load_mut()?;
     let clock = Clock::get()?;
     
+    // Reject liquidations while either pool is paused
+    debt_pool.ensure_not_paused()?;
+    collateral_pool.ensure_not_paused()?;
+    
     // Update pool rates
     debt_pool.update_rates(clock.unix_timestamp)?;
     collateral_pool.update_rates(clock.unix_timestamp)?;
     
-    // Create a mock pool data map for health factor calculation
-    // In a real implementation, this would involve fetching oracle prices and parameters
-    let mut pool_data = HashMap::new();
-    
-    // Mock prices - would come from oracle in real implementation
-    pool_data.insert(debt_pool.key(), (10000, debt_pool.liquidation_threshold));
-    pool_data.insert(collateral_pool.key(), (10000, collateral_pool.liquidation_threshold));
-    
-    // Calculate current health factor
-    user_position.calculate_health_factor(&pool_data)?;
+    // Refresh both prices, rejecting noisy oracle data before seizing collateral
+    OracleModule::refresh_pool_price(debt_pool, accounts.debt_price_oracle.as_deref())?;
+    OracleModule::refresh_pool_price(collateral_pool, accounts.collateral_price_oracle.as_deref())?;
     
-    // Check if position is eligible for liquidation
-    const LIQUIDATION_THRESHOLD: u64 = 10000; // 1.0 in scaled form
+    // Only liquidate on fresh prices, so a stale oracle can't be used to
+    // unfairly seize collateral
     require!(
-        user_position.health_factor < LIQUIDATION_THRESHOLD,
-        OxygenError::CannotLiquidate
+        debt_pool.is_oracle_fresh(clock.unix_timestamp)
+            && collateral_pool.is_oracle_fresh(clock.unix_timestamp),
+        OxygenError::StaleOracleData
     );
     
-    // Find user's debt in the specified pool
-    let mut debt_position_idx = None;
-    for (i, borrow) in user_position.borrows.iter().enumerate() {
-        if borrow.pool == debt_pool.key() {
-            debt_position_idx = Some(i);
-            break;
-        }
+    // Create pool data map for health factor calculation from the refreshed oracle prices,
+    // smoothed by each pool's TWAP where enabled
+    let mut pool_data = HashMap::new();
+    pool_data.insert(debt_pool.key(), (debt_pool.liquidation_price(clock.unix_timestamp)?, debt_pool.liquidation_threshold));
+    pool_data.insert(collateral_pool.key(), (collateral_pool.liquidation_price(clock.unix_timestamp)?, collateral_pool.liquidation_threshold));
+    
+    // Value borrows at their interest-inclusive debt
+    let mut borrow_rates = HashMap::new();
+    borrow_rates.insert(debt_pool.key(), debt_pool.cumulative_borrow_rate);
+    borrow_rates.insert(collateral_pool.key(), collateral_pool.cumulative_borrow_rate);
+    
+    // Load the extra collateral pools so they count towards health and can be seized
+    require!(remaining_accounts.len() % 4 == 0, OxygenError::InvalidParameter);
+    let mut extra_collaterals = Vec::new();
+    
+    for group in remaining_accounts.chunks(4) {
+        let mut pool: Account<Pool> = Account::try_from(&group[0])?;
+        let mint: InterfaceAccount<Mint> = InterfaceAccount::try_from(&group[1])?;
+        let reserve: InterfaceAccount<TokenAccount> = InterfaceAccount::try_from(&group[2])?;
+        let liquidator_token_account: InterfaceAccount<TokenAccount> = InterfaceAccount::try_from(&group[3])?;
+        
+        let expected_pool = Pubkey::create_program_address(
+            &[b"pool", pool.asset_mint.as_ref(), &[pool.bump]],
+            program_id
+        ).map_err(|_| OxygenError::InvalidParameter)?;
+        
+        // Each pool may only appear once, including the named debt and collateral pools
+        require!(
+            pool.key() == expected_pool && !pool_data.contains_key(&pool.key()),
+            OxygenError::InvalidParameter
+        );
+        require!(
+            mint.key() == pool.asset_mint && reserve.key() == pool.asset_reserve,
+            OxygenError::InvalidParameter
+        );
+        require!(
+            liquidator_token_account.mint == pool.asset_mint
+                && liquidator_token_account.owner == accounts.liquidator.key(),
+            OxygenError::InvalidParameter
+        );
+        
+        pool.ensure_not_paused()?;
+        pool.update_rates(clock.unix_timestamp)?;
+        
+        pool_data.insert(pool.key(), (pool.liquidation_price(clock.unix_timestamp)?, pool.liquidation_threshold));
+        borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+        
+        extra_collaterals.push(ExtraCollateral { pool, mint, reserve, liquidator_token_account });
     }
     
-    let debt_position_idx = debt_position_idx.ok_or(OxygenError::InvalidParameter)?;
-    let debt_position = &mut user_position.borrows[debt_position_idx];
+    // A missing pool would count as worthless and could liquidate a healthy position
+    user_position.require_priced_pools(&pool_data)?;
     
-    // Check if liquidation amount <= borrow amount
-    require!(
-        params.amount <= debt_position.amount_borrowed,
-        OxygenError::InvalidParameter
-    );
+    // Calculate current health factor; every pool is priced from a fresh oracle
+    // here, so it may start or clear the grace period
+    user_position.calculate_health_factor_with_rates(&pool_data, &borrow_rates)?;
+    user_position.update_unhealthy_since(clock.unix_timestamp);
     
-    // Find user's collateral in the specified pool
-    let mut collateral_position_idx = None;
-    for (i, collateral) in user_position.collaterals.iter().enumerate() {
-        if collateral.pool == collateral_pool.key() {
-            collateral_position_idx = Some(i);
-            break;
-        }
-    }
+    // Collateral is seized by value, so convert between the two assets at their oracle prices
+    let prices = SeizurePrices {
+        debt_price: pool_data[&debt_pool.key()].0,
+        debt_decimals: accounts.debt_mint.decimals,
+        collateral_price: pool_data[&collateral_pool.key()].0,
+        collateral_decimals: accounts.collateral_mint.decimals,
+    };
+    
+    // Seize from the named collateral pool first
+    let LiquidationPlan { amount, seizure, collateral_index: collateral_position_idx } = match plan_liquidation(
+        user_position,
+        debt_pool,
+        collateral_pool,
+        &pool_data,
+        &prices,
+        requested,
+        clock.unix_timestamp
+    )? {
+        Some(plan) => plan,
+        None => return Ok(0),
+    };
     
-    let collateral_position_idx = collateral_position_idx.ok_or(OxygenError::InvalidParameter)?;
-    let collateral_position = &mut user_position.collaterals[collateral_position_idx];
+    // Update user's collateral position
+    let collateral_position = &mut user_position.collaterals_mut()[collateral_position_idx];
+    collateral_position.amount_deposited = collateral_position.amount_deposited
+        .checked_sub(seizure.total_seized()?)
+        .ok_or(ErrorCode::MathOverflow)?;
+        
+    if collateral_position.amount_deposited == 0 {
+        // Remove empty collateral position
+        user_position.remove_collateral(collateral_position_idx);
+    }
     
-    // Calculate liquidation bonus (e.g., 5-10%)
-    let bonus_rate = debt_pool.liquidation_bonus;
+    // When the named collateral can't cover the requested amount, keep seizing
+    // from the borrower's most valuable remaining collateral among the extra pools
+    let mut extra_pool_data = HashMap::new();
+    let mut extra_decimals = HashMap::new();
+    for extra in &extra_collaterals {
+        extra_pool_data.insert(extra.pool.key(), pool_data[&extra.pool.key()]);
+        extra_decimals.insert(extra.pool.key(), extra.mint.decimals);
+    }
     
-    // Calculate collateral value to seize including bonus
-    // In a real implementation, this would use asset-specific prices from oracles
-    let collateral_to_seize = (params.amount as u128)
-        .checked_mul(10000 + bonus_rate as u128)
-        .ok_or(ErrorCode::MathOverflow)?
-        .checked_div(10000)
-        .ok_or(ErrorCode::MathOverflow)? as u64;
+    let mut liquidation_amount = seizure.debt_covered;
+    let mut extra_seizures = Vec::new();
     
-    // Ensure user has enough collateral
-    require!(
-        collateral_position.amount_deposited >= collateral_to_seize,
-        OxygenError::InsufficientCollateral
-    );
+    while liquidation_amount < amount {
+        let best_pool = match LiquidationEngine::select_best_collateral(user_position, &extra_pool_data, &extra_decimals) {
+            Some(pool) => pool,
+            None => break,
+        };
+        extra_pool_data.remove(&best_pool);
+        
+        let extra_idx = extra_collaterals
+            .iter()
+            .position(|extra| extra.pool.key() == best_pool)
+            .ok_or(OxygenError::InvalidParameter)?;
+        let collateral_idx = user_position
+            .collateral_index(&best_pool)
+            .ok_or(OxygenError::CollateralNotFound)?;
+        
+        let extra = &extra_collaterals[extra_idx];
+        let extra_prices = SeizurePrices {
+            collateral_price: pool_data[&best_pool].0,
+            collateral_decimals: extra.mint.decimals,
+            ..prices
+        };
+        
+        let extra_seizure = plan_seizure(
+            debt_pool,
+            &extra.pool,
+            &extra_prices,
+            amount - liquidation_amount,
+            user_position.collaterals()[collateral_idx].amount_deposited
+        )?;
+        
+        let collateral = &mut user_position.collaterals_mut()[collateral_idx];
+        collateral.amount_deposited = collateral.amount_deposited
+            .checked_sub(extra_seizure.total_seized()?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        if collateral.amount_deposited == 0 {
+            user_position.remove_collateral(collateral_idx);
+        }
+        
+        liquidation_amount = liquidation_amount
+            .checked_add(extra_seizure.debt_covered)
+            .ok_or(ErrorCode::MathOverflow)?;
+        extra_seizures.push((extra_idx, extra_seizure));
+    }
     
     // Transfer debt tokens from liquidator to reserve
-    let cpi_accounts = Transfer {
-        from: ctx.accounts.liquidator_debt_token_account.to_account_info(),
-        to: ctx.accounts.debt_reserve.to_account_info(),
-        authority: ctx.accounts.liquidator.to_account_info(),
+    let cpi_accounts = TransferChecked {
+        from: accounts.liquidator_debt_token_account.to_account_info(),
+        mint: accounts.debt_mint.to_account_info(),
+        to: accounts.debt_reserve.to_account_info(),
+        authority: accounts.liquidator.to_account_info(),
     };
     
     let cpi_context = CpiContext::new(
-        ctx.accounts.token_program.to_account_info(),
+        accounts.token_program.to_account_info(),
         cpi_accounts,
     );
     
-    token::transfer(cpi_context, params.amount)?;
+    token_interface::transfer_checked(cpi_context, liquidation_amount, accounts.debt_mint.decimals)?;
     
     // Transfer collateral tokens from reserve to liquidator
     let pool_seeds = &[
@@ -168,57 +405,458 @@ pub fn handler(ctx: Context<Liquidate>, params: LiquidateParams) -> Result<()> {
     
     let pool_signer = &[&pool_seeds[..]];
     
-    let cpi_accounts = Transfer {
-        from: ctx.accounts.collateral_reserve.to_account_info(),
-        to: ctx.accounts.liquidator_collateral_token_account.to_account_info(),
-        authority: ctx.accounts.collateral_pool.to_account_info(),
+    // Check the reserve actually holds the tokens, so accounting drift fails clearly
+    require!(
+        accounts.collateral_reserve.amount >= seizure.liquidator_collateral,
+        OxygenError::InsufficientReserves
+    );
+    
+    let cpi_accounts = TransferChecked {
+        from: accounts.collateral_reserve.to_account_info(),
+        mint: accounts.collateral_mint.to_account_info(),
+        to: accounts.liquidator_collateral_token_account.to_account_info(),
+        authority: collateral_pool.to_account_info(),
     };
     
     let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.token_program.to_account_info(),
+        accounts.token_program.to_account_info(),
         cpi_accounts,
         pool_signer,
     );
     
-    token::transfer(cpi_context, collateral_to_seize)?;
+    token_interface::transfer_checked(cpi_context, seizure.liquidator_collateral, accounts.collateral_mint.decimals)?;
     
-    // Update user's debt position
-    debt_position.amount_borrowed = debt_position.amount_borrowed
-        .checked_sub(params.amount)
-        .ok_or(ErrorCode::MathOverflow)?;
+    // Pay out and account for the collateral seized from each extra pool
+    for (extra_idx, extra_seizure) in &extra_seizures {
+        let extra = &mut extra_collaterals[*extra_idx];
+        
+        let pool_seeds = &[
+            b"pool".as_ref(),
+            extra.pool.asset_mint.as_ref(),
+            &[extra.pool.bump],
+        ];
+        
+        let pool_signer = &[&pool_seeds[..]];
+        
+        require!(
+            extra.reserve.amount >= extra_seizure.liquidator_collateral,
+            OxygenError::InsufficientReserves
+        );
+        
+        let cpi_accounts = TransferChecked {
+            from: extra.reserve.to_account_info(),
+            mint: extra.mint.to_account_info(),
+            to: extra.liquidator_token_account.to_account_info(),
+            authority: extra.pool.to_account_info(),
+        };
         
-    if debt_position.amount_borrowed == 0 {
-        // Remove empty debt position
-        user_position.borrows.remove(debt_position_idx);
+        let cpi_context = CpiContext::new_with_signer(
+            accounts.token_program.to_account_info(),
+            cpi_accounts,
+            pool_signer,
+        );
+        
+        token_interface::transfer_checked(cpi_context, extra_seizure.liquidator_collateral, extra.mint.decimals)?;
+        
+        extra_seizure.apply_to_pool(&mut extra.pool)?;
+        
+        emit!(LiquidationEvent {
+            liquidator: accounts.liquidator.key(),
+            liquidated: accounts.user.key(),
+            pool: extra.pool.key(),
+            asset_mint: extra.pool.asset_mint,
+            collateral_amount: extra_seizure.liquidator_collateral,
+            debt_amount: extra_seizure.debt_covered,
+            liquidation_bonus: extra_seizure.liquidator_bonus(),
+            insurance_fund_cut: extra_seizure.insurance_cut,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        // Extra pools aren't part of the accounts struct, so persist them explicitly
+        extra.pool.exit(program_id)?;
     }
     
-    // Update user's collateral position
-    collateral_position.amount_deposited = collateral_position.amount_deposited
-        .checked_sub(collateral_to_seize)
-        .ok_or(ErrorCode::MathOverflow)?;
+    // Update user's debt positions, highest-rate borrows first; empty ones are removed
+    let (_, principal_repaid) = user_position.reduce_pool_debt(
+        &debt_pool.key(),
+        liquidation_amount,
+        debt_pool.cumulative_borrow_rate
+    )?;
+    
+    // Once no collateral is left the remaining debt can never be repaid, so write
+    // it off against the insurance fund before socializing it
+    let has_collateral_left = user_position.collaterals()
+        .iter()
+        .any(|collateral| collateral.is_collateral && collateral.amount_deposited > 0);
+    
+    if !has_collateral_left {
+        let mut debt_written_off = 0u64;
+        while let Some(idx) = user_position.borrow_index(&debt_pool.key()) {
+            let remaining_debt = user_position.remove_borrow(idx);
+            debt_written_off = debt_written_off
+                .checked_add(remaining_debt.amount_borrowed)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
         
-    if collateral_position.amount_deposited == 0 {
-        // Remove empty collateral position
-        user_position.collaterals.remove(collateral_position_idx);
+        if debt_written_off > 0 {
+            let (covered_by_insurance, socialized) =
+                LiquidationEngine::settle_bad_debt(debt_pool, debt_written_off)?;
+            
+            emit!(BadDebtSettledEvent {
+                user: accounts.user.key(),
+                pool: debt_pool.key(),
+                asset_mint: debt_pool.asset_mint,
+                debt_written_off,
+                covered_by_insurance,
+                socialized,
+                timestamp: clock.unix_timestamp,
+            });
+        }
     }
     
     // Update pool totals
+    // Pool borrows track principal only; repaid interest stays in the reserve
     debt_pool.total_borrows = debt_pool.total_borrows
-        .checked_sub(params.amount)
+        .checked_sub(principal_repaid)
         .ok_or(ErrorCode::MathOverflow)?;
         
-    collateral_pool.total_deposits = collateral_pool.total_deposits
-        .checked_sub(collateral_to_seize)
-        .ok_or(ErrorCode::MathOverflow)?;
-    
-    // Recalculate health factor after liquidation
-    user_position.calculate_health_factor(&pool_data)?;
+    seizure.apply_to_pool(collateral_pool)?;
+    
+    emit!(LiquidationEvent {
+        liquidator: accounts.liquidator.key(),
+        liquidated: accounts.user.key(),
+        pool: collateral_pool.key(),
+        asset_mint: collateral_pool.asset_mint,
+        collateral_amount: seizure.liquidator_collateral,
+        debt_amount: seizure.debt_covered,
+        liquidation_bonus: seizure.liquidator_bonus(),
+        insurance_fund_cut: seizure.insurance_cut,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    // The liquidator repays debt on the borrower's behalf, so report it with the
+    // same principal/interest breakdown as a regular repayment
+    emit!(RepayEvent {
+        user: accounts.user.key(),
+        pool: debt_pool.key(),
+        asset_mint: debt_pool.asset_mint,
+        amount: liquidation_amount,
+        interest_paid: liquidation_amount
+            .checked_sub(principal_repaid)
+            .ok_or(ErrorCode::MathOverflow)?,
+        principal_paid: principal_repaid,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    // Recalculate health factor after liquidation, clearing the grace period if it recovered
+    user_position.calculate_health_factor_with_rates(&pool_data, &borrow_rates)?;
+    user_position.update_unhealthy_since(clock.unix_timestamp);
     user_position.last_updated = clock.unix_timestamp;
     
-    msg!("Liquidated {} debt tokens for {} collateral tokens", 
-        params.amount, 
-        collateral_to_seize
+    // Emit pool utilization updated events for both pools since their rates advanced
+    for pool in [&debt_pool, &collateral_pool] {
+        emit!(PoolUtilizationUpdatedEvent {
+            pool: pool.key(),
+            asset_mint: pool.asset_mint,
+            utilization_rate: pool.get_utilization_rate(),
+            borrow_interest_rate: pool.get_borrow_rate()?,
+            lending_interest_rate: pool.get_lending_rate()?,
+            cumulative_borrow_rate: pool.cumulative_borrow_rate,
+            cumulative_lending_rate: pool.cumulative_lending_rate,
+            timestamp: clock.unix_timestamp,
+        });
+    }
+    
+    msg!("Liquidated {} debt tokens for {} collateral tokens ({} penalty to reserves, {} to insurance) plus {} extra collateral pools", 
+        liquidation_amount, 
+        seizure.liquidator_collateral,
+        seizure.penalty,
+        seizure.insurance_cut,
+        extra_seizures.len()
     );
     
-    Ok(())
+    Ok(liquidation_amount)
+}
+
+// Helper function to split the collateral seized for repaying `amount` of debt
+// Returns the liquidator's share including the bonus, and the protocol penalty
+// charged to the borrower on top of it, which stays in the collateral reserve
+fn calculate_collateral_to_seize(debt_pool: &Pool, prices: &SeizurePrices, amount: u64) -> Result<(u64, u64)> {
+    // The bonus and penalty apply to the collateral worth the debt, not to the debt's token amount
+    let collateral_value = prices.debt_to_collateral(amount)?;
+    
+    let collateral_to_seize = (collateral_value as u128)
+        .checked_mul(10000 + debt_pool.liquidation_bonus as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::MathOverflow)? as u64;
+    
+    let penalty_to_seize = (collateral_value as u128)
+        .checked_mul(debt_pool.liquidation_penalty as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::MathOverflow)? as u64;
+    
+    Ok((collateral_to_seize, penalty_to_seize))
+}
+
+// A liquidation that passed every check: the debt it may repay in total and the
+// collateral it takes from the named collateral pool
+pub(crate) struct LiquidationPlan {
+    pub amount: u64,             // Debt to repay, within the close factor and the current debt
+    pub seizure: Seizure,        // Seizure from the named collateral pool
+    pub collateral_index: usize, // Index of the named collateral in the position
+}
+
+// Eligibility checks and the named pool's seizure shared by `liquidate` and
+// `simulate_liquidation`, so a simulation fails and pays out exactly like the
+// real liquidation. The position's health factor and unhealthy_since must be
+// up to date. Returns None when a close factor liquidation has nothing to repay
+pub(crate) fn plan_liquidation(
+    user_position: &UserPosition,
+    debt_pool: &Account<Pool>,
+    collateral_pool: &Account<Pool>,
+    pool_data: &HashMap<Pubkey, (u64, u64)>,
+    prices: &SeizurePrices,
+    requested: LiquidationAmount,
+    current_timestamp: i64
+) -> Result<Option<LiquidationPlan>> {
+    // Don't seize collateral on a price jump that trips either pool's circuit breaker
+    debt_pool.ensure_not_paused()?;
+    collateral_pool.ensure_not_paused()?;
+    
+    // Check if position is eligible for liquidation
+    // Liquidating up to the close factor repays nothing on a healthy position
+    let is_liquidatable = user_position.health_factor < UserPosition::LIQUIDATION_THRESHOLD;
+    if !is_liquidatable && matches!(requested, LiquidationAmount::UpToCloseFactor) {
+        return Ok(None);
+    }
+    require!(is_liquidatable, OxygenError::CannotLiquidate);
+    
+    // Give the borrower a window to top up before a newly unhealthy position
+    // can be liquidated, so a brief price spike doesn't trigger a liquidation.
+    // A timestamp stamped by a rejected liquidation is rolled back with it, so
+    // the grace period starts from the one `mark_unhealthy` persisted
+    require!(
+        user_position.liquidation_grace_elapsed(current_timestamp, debt_pool.liquidation_grace_period),
+        OxygenError::LiquidationGracePeriodActive
+    );
+    
+    // A single liquidation may only close up to the close factor of the borrower's debt
+    let max_liquidation_value = LiquidationEngine::calculate_max_liquidation_amount(user_position, pool_data)?;
+    let max_liquidation_amount = max_liquidation_value
+        .checked_div(prices.debt_price)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    // Find user's debt in the specified pool, across all of their borrows there
+    require!(
+        user_position.borrow_index(&debt_pool.key()).is_some(),
+        OxygenError::InvalidParameter
+    );
+    let current_debt = user_position.total_debt_at_rate(&debt_pool.key(), debt_pool.cumulative_borrow_rate)?;
+    
+    let amount = match requested {
+        LiquidationAmount::Exact(amount) => {
+            require!(
+                amount <= max_liquidation_amount,
+                OxygenError::ExceedsCloseFactor
+            );
+            
+            // Check if liquidation amount <= borrow amount including accrued interest
+            require!(
+                amount <= current_debt,
+                OxygenError::InvalidParameter
+            );
+            
+            amount
+        }
+        LiquidationAmount::UpToCloseFactor => std::cmp::min(max_liquidation_amount, current_debt),
+    };
+    if amount == 0 {
+        return Ok(None);
+    }
+    
+    // Find user's collateral in the specified pool
+    let collateral_index = user_position
+        .collateral_index(&collateral_pool.key())
+        .ok_or(OxygenError::InvalidParameter)?;
+    
+    let seizure = plan_seizure(
+        debt_pool,
+        collateral_pool,
+        prices,
+        amount,
+        user_position.collaterals()[collateral_index].amount_deposited
+    )?;
+    
+    Ok(Some(LiquidationPlan { amount, seizure, collateral_index }))
+}
+
+// Plan the seizure of up to `amount` of debt against `deposited` collateral in
+// `collateral_pool`. An underwater collateral can't pay the full bonus and penalty,
+// so only the debt it covers is liquidated and all of it is seized
+fn plan_seizure(
+    debt_pool: &Pool,
+    collateral_pool: &Pool,
+    prices: &SeizurePrices,
+    amount: u64,
+    deposited: u64
+) -> Result<Seizure> {
+    let mut debt_covered = amount;
+    let (mut collateral_to_seize, mut penalty) = calculate_collateral_to_seize(debt_pool, prices, debt_covered)?;
+    
+    if (deposited as u128) < collateral_to_seize as u128 + penalty as u128 {
+        let collateral_value = (deposited as u128)
+            .checked_mul(10000)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(10000 + debt_pool.liquidation_bonus as u128 + debt_pool.liquidation_penalty as u128)
+            .ok_or(ErrorCode::MathOverflow)? as u64;
+        debt_covered = prices.collateral_to_debt(collateral_value)?;
+        require!(debt_covered > 0, OxygenError::InsufficientCollateral);
+        
+        collateral_to_seize = calculate_collateral_to_seize(debt_pool, prices, debt_covered)?.0;
+        penalty = deposited
+            .checked_sub(collateral_to_seize)
+            .ok_or(ErrorCode::MathOverflow)?;
+    }
+    
+    // Part of the liquidator's bonus is paid into the collateral pool's insurance fund
+    let collateral_repaid = prices.debt_to_collateral(debt_covered)?;
+    let bonus = collateral_to_seize.saturating_sub(collateral_repaid);
+    let insurance_cut = (bonus as u128)
+        .checked_mul(collateral_pool.insurance_fund_share as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::MathOverflow)? as u64;
+    
+    Ok(Seizure {
+        debt_covered,
+        collateral_repaid,
+        liquidator_collateral: collateral_to_seize
+            .checked_sub(insurance_cut)
+            .ok_or(ErrorCode::MathOverflow)?,
+        penalty,
+        insurance_cut,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::position::tests::{borrow, collateral};
+    
+    const GRACE_PERIOD: i64 = 600;
+    
+    fn pools() -> (Account<'static, Pool>, Account<'static, Pool>) {
+        let debt_pool = Pool {
+            cumulative_borrow_rate: 1_000_000_000_000,
+            liquidation_threshold: 8000,
+            liquidation_bonus: 500,
+            liquidation_penalty: 100,
+            liquidation_grace_period: GRACE_PERIOD,
+            ..Default::default()
+        };
+        let collateral_pool = Pool {
+            liquidation_threshold: 8000,
+            insurance_fund_share: 2000,
+            ..Default::default()
+        };
+        (debt_pool.into_test_account(), collateral_pool.into_test_account())
+    }
+    
+    // 1000 collateral tokens against 900 owed, both at 1.0, a 0.89 health factor
+    fn unhealthy_position(
+        debt_pool: &Account<Pool>,
+        collateral_pool: &Account<Pool>,
+        unhealthy_since: i64
+    ) -> (UserPosition, HashMap<Pubkey, (u64, u64)>) {
+        let mut position = UserPosition::with_positions(
+            &[collateral(collateral_pool.key(), 1000)],
+            &[borrow(debt_pool.key(), 900)]
+        );
+        let pool_data = HashMap::from([
+            (debt_pool.key(), (10000, 8000)),
+            (collateral_pool.key(), (10000, 8000)),
+        ]);
+        position.calculate_health_factor(&pool_data).unwrap();
+        position.update_unhealthy_since(unhealthy_since);
+        (position, pool_data)
+    }
+    
+    fn prices() -> SeizurePrices {
+        SeizurePrices { debt_price: 10000, debt_decimals: 6, collateral_price: 10000, collateral_decimals: 6 }
+    }
+    
+    #[test]
+    fn liquidation_inside_grace_period_is_rejected() {
+        let (debt_pool, collateral_pool) = pools();
+        let (position, pool_data) = unhealthy_position(&debt_pool, &collateral_pool, 1000);
+        
+        let result = plan_liquidation(
+            &position, &debt_pool, &collateral_pool, &pool_data, &prices(),
+            LiquidationAmount::Exact(100), 1000 + GRACE_PERIOD - 1
+        );
+        assert_eq!(result.err().unwrap(), OxygenError::LiquidationGracePeriodActive.into());
+    }
+    
+    #[test]
+    fn liquidation_after_grace_period_pays_liquidator_net_of_insurance_cut() {
+        let (debt_pool, collateral_pool) = pools();
+        let (position, pool_data) = unhealthy_position(&debt_pool, &collateral_pool, 1000);
+        
+        let plan = plan_liquidation(
+            &position, &debt_pool, &collateral_pool, &pool_data, &prices(),
+            LiquidationAmount::Exact(100), 1000 + GRACE_PERIOD
+        ).unwrap().unwrap();
+        
+        // 100 repaid buys 105 collateral with the 5% bonus, 20% of which goes to insurance
+        assert_eq!(plan.amount, 100);
+        assert_eq!(plan.seizure.debt_covered, 100);
+        assert_eq!(plan.seizure.insurance_cut, 1);
+        assert_eq!(plan.seizure.liquidator_collateral, 104);
+        assert_eq!(plan.seizure.liquidator_bonus(), 4);
+        assert_eq!(plan.seizure.penalty, 1);
+        assert_eq!(plan.seizure.total_seized().unwrap(), 106);
+    }
+    
+    #[test]
+    fn liquidation_is_capped_by_close_factor() {
+        let (debt_pool, collateral_pool) = pools();
+        let (position, pool_data) = unhealthy_position(&debt_pool, &collateral_pool, 1000);
+        let now = 1000 + GRACE_PERIOD;
+        
+        let result = plan_liquidation(
+            &position, &debt_pool, &collateral_pool, &pool_data, &prices(),
+            LiquidationAmount::Exact(451), now
+        );
+        assert_eq!(result.err().unwrap(), OxygenError::ExceedsCloseFactor.into());
+        
+        let plan = plan_liquidation(
+            &position, &debt_pool, &collateral_pool, &pool_data, &prices(),
+            LiquidationAmount::UpToCloseFactor, now
+        ).unwrap().unwrap();
+        assert_eq!(plan.amount, 450);
+    }
+    
+    #[test]
+    fn healthy_position_is_not_liquidated() {
+        let (debt_pool, collateral_pool) = pools();
+        let (mut position, pool_data) = unhealthy_position(&debt_pool, &collateral_pool, 1000);
+        position.borrows_mut()[0].amount_borrowed = 500;
+        position.borrows_mut()[0].amount_scaled = 500;
+        position.calculate_health_factor(&pool_data).unwrap();
+        position.update_unhealthy_since(2000);
+        
+        let plan = plan_liquidation(
+            &position, &debt_pool, &collateral_pool, &pool_data, &prices(),
+            LiquidationAmount::UpToCloseFactor, 5000
+        ).unwrap();
+        assert!(plan.is_none());
+        
+        let result = plan_liquidation(
+            &position, &debt_pool, &collateral_pool, &pool_data, &prices(),
+            LiquidationAmount::Exact(100), 5000
+        );
+        assert_eq!(result.err().unwrap(), OxygenError::CannotLiquidate.into());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+use anchor_lang::prelude::*;
+use std::collections::HashMap;
+use crate::state::{Pool, UserPosition};
+use crate::errors::OxygenError;
+
+// Permissionless crank that records when a position became liquidatable. The
+// timestamp stamped inside a liquidation that is still within the grace period
+// is rolled back with the rejected instruction, so this is how the grace
+// period is started.
+
+#[derive(Accounts)]
+pub struct MarkUnhealthy<'info> {
+    /// CHECK: Only used to derive the position address
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    // Every collateral and borrow pool of the user is passed as a remaining account
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, MarkUnhealthy<'info>>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    
+    // Price the position the same way a liquidation would
+    let mut pool_data = HashMap::new();
+    let mut borrow_rates = HashMap::new();
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        let pool: Account<Pool> = Account::try_from(account_info)?;
+        
+        require!(pool.is_oracle_fresh(now), OxygenError::StaleOracleData);
+        
+        pool_data.insert(pool.key(), (pool.liquidation_price(now)?, pool.liquidation_threshold));
+        borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+    }
+    
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
+    
+    // A missing pool would count as worthless and could mark a healthy position
+    user_position.require_priced_pools(&pool_data)?;
+    
+    // Stamp unhealthy_since the first time health is below 1.0, and clear it once recovered
+    let health_factor = user_position.calculate_health_factor_with_rates(&pool_data, &borrow_rates)?;
+    user_position.update_unhealthy_since(now);
+    user_position.last_updated = now;
+    
+    msg!(
+        "Position {} health factor {}, unhealthy since {}",
+        user_position.owner,
+        health_factor,
+        user_position.unhealthy_since
+    );
+    
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,82 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use anchor_lang::Discriminator;
+use crate::state::{UserPosition, LegacyUserPosition};
+use crate::errors::OxygenError;
+
+// Positions created before UserPosition became zero-copy are Borsh-encoded and
+// sized by their Vec lengths. This rewrites one in place into the fixed
+// zero-copy layout, topping up rent for the larger account from the owner.
+
+#[derive(Accounts)]
+pub struct MigrateUserPosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: Legacy position PDA, decoded and validated in the handler
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump
+    )]
+    pub user_position: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<MigrateUserPosition>) -> Result<()> {
+    let position_info = ctx.accounts.user_position.to_account_info();
+    
+    // Decode the old layout, rejecting accounts that are already migrated
+    let legacy = {
+        let data = position_info.try_borrow_data()?;
+        require!(
+            data.len() >= 8 && data[..8] == UserPosition::discriminator(),
+            OxygenError::InvalidParameter
+        );
+        require!(
+            data.len() != UserPosition::space(),
+            OxygenError::InvalidParameter
+        );
+        
+        let mut legacy_data: &[u8] = &data[8..];
+        LegacyUserPosition::deserialize(&mut legacy_data)?
+    };
+    
+    require!(
+        legacy.owner == ctx.accounts.user.key(),
+        OxygenError::Unauthorized
+    );
+    
+    // Fund the rent difference before growing the account
+    let rent = Rent::get()?;
+    let required_lamports = rent.minimum_balance(UserPosition::space());
+    let current_lamports = position_info.lamports();
+    if required_lamports > current_lamports {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: position_info.clone(),
+                },
+            ),
+            required_lamports - current_lamports,
+        )?;
+    }
+    
+    position_info.realloc(UserPosition::space(), false)?;
+    
+    // Clear the old Borsh bytes so every unused slot reads as empty
+    {
+        let mut data = position_info.try_borrow_mut_data()?;
+        data[8..].fill(0);
+    }
+    
+    let loader: AccountLoader<UserPosition> = AccountLoader::try_from(&position_info)?;
+    loader.load_mut()?.load_legacy(&legacy)?;
+    
+    msg!("Migrated position for {}", legacy.owner);
+    
+    Ok(())
+}
\ No newline at end of file
@@ -1,18 +1,50 @@
 pub mod init_pool;
+pub mod init_market;
+pub mod init_user_position;
 pub mod deposit;
 pub mod withdraw;
 pub mod borrow;
 pub mod repay;
 pub mod trade;
 pub mod liquidate;
+pub mod simulate_liquidation;
 pub mod claim_yield;
+pub mod flash_loan;
+pub mod health_breakdown;
+pub mod transfer_position;
+pub mod set_collateral_status;
+pub mod update_lending_status;
+pub mod withdraw_protocol_fees;
+pub mod deposit_to_insurance_fund;
+pub mod get_account_health;
+pub mod migrate_user_position;
+pub mod get_borrow_debt;
+pub mod refresh_pool_price;
+pub mod account_liquidation;
+pub mod mark_unhealthy;
 
 // Re-exports
 pub use init_pool::*;
+pub use init_market::*;
+pub use init_user_position::*;
 pub use deposit::*;
 pub use withdraw::*;
 pub use borrow::*;
 pub use repay::*;
 pub use trade::*;
 pub use liquidate::*;
-pub use claim_yield::*;
\ No newline at end of file
+pub use simulate_liquidation::*;
+pub use claim_yield::*;
+pub use flash_loan::*;
+pub use health_breakdown::*;
+pub use transfer_position::*;
+pub use set_collateral_status::*;
+pub use update_lending_status::*;
+pub use withdraw_protocol_fees::*;
+pub use deposit_to_insurance_fund::*;
+pub use get_account_health::*;
+pub use migrate_user_position::*;
+pub use get_borrow_debt::*;
+pub use refresh_pool_price::*;
+pub use account_liquidation::*;
+pub use mark_unhealthy::*;
\ No newline at end of file
@@ -0,0 +1,32 @@
+use anchor_lang::prelude::*;
+use crate::state::Pool;
+use crate::errors::OxygenError;
+use crate::modules::oracle::OracleModule;
+
+// Permissionless crank that records a pool's oracle price. A price jump that
+// trips the circuit breaker inside a borrow or withdrawal is rolled back with
+// the rejected instruction, so this is how the pause is persisted.
+
+#[derive(Accounts)]
+pub struct RefreshPoolPrice<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    /// CHECK: Pyth price account, validated against the pool's configured oracle
+    #[account(constraint = price_oracle.key() == pool.price_oracle @ OxygenError::InvalidOracleConfig)]
+    pub price_oracle: UncheckedAccount<'info>,
+}
+
+pub fn handler(ctx: Context<RefreshPoolPrice>) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    
+    OracleModule::refresh_pool_price(pool, Some(&ctx.accounts.price_oracle))?;
+    
+    msg!("Pool {} price refreshed to {}", pool.key(), pool.last_oracle_price);
+    
+    Ok(())
+}
\ No newline at end of file
@@ -1,8 +1,8 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 use crate::state::{Pool, UserPosition};
 use crate::errors::OxygenError;
-use crate::events::{RepayEvent, PoolUtilizationUpdatedEvent};
+use crate::events::{RepayEvent, PoolUtilizationUpdatedEvent, PoolAccountingDriftEvent};
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct RepayParams {
@@ -18,95 +18,116 @@ pub struct Repay<'info> {
         mut,
         seeds = [b"pool", pool.asset_mint.as_ref()],
         bump = pool.bump,
+        has_one = asset_mint,
     )]
     pub pool: Account<'info, Pool>,
     
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
     #[account(
         mut,
         constraint = user_token_account.mint == pool.asset_mint,
         constraint = user_token_account.owner == user.key(),
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"reserve", pool.key().as_ref()],
         bump,
         constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
     )]
-    pub asset_reserve: Account<'info, TokenAccount>,
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"position", user.key().as_ref()],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key(),
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
     )]
-    pub user_position: Account<'info, UserPosition>,
+    pub user_position: AccountLoader<'info, UserPosition>,
     
-    pub token_program: Program<'info, anchor_spl::token::Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 pub fn handler(ctx: Context<Repay>, params: RepayParams) -> Result<()> {
-    let amount = params.amount;
-    require!(amount > 0, OxygenError::InvalidParameter);
+    require!(params.amount > 0, OxygenError::InvalidParameter);
     
+    process_repay(ctx, params.amount)
+}
+
+/// Repay the full interest-inclusive debt, or as much of it as the user's balance covers
+/// The borrow entry is removed once the debt is cleared
+pub fn repay_all(ctx: Context<Repay>) -> Result<()> {
+    let balance = ctx.accounts.user_token_account.amount;
+    require!(balance > 0, OxygenError::InsufficientBalance);
+    
+    // The repayment is capped at the current debt, so offering the whole balance
+    // repays exactly what is owed after interest accrues
+    process_repay(ctx, balance)
+}
+
+fn process_repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
     let pool = &mut ctx.accounts.pool;
-    let user_position = &mut ctx.accounts.user_position;
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
     let clock = Clock::get()?;
     
+    // Reject repayments while the pool is paused
+    pool.ensure_not_paused()?;
+    
     // Update pool rates
     pool.update_rates(clock.unix_timestamp)?;
     
-    // Find the borrow position
-    let mut found_index = None;
-    let mut current_borrowed_amount = 0;
-    
-    for (i, borrow) in user_position.borrows.iter().enumerate() {
-        if borrow.pool == pool.key() {
-            found_index = Some(i);
-            current_borrowed_amount = borrow.amount_borrowed;
-            break;
-        }
-    }
-    
-    require!(found_index.is_some(), OxygenError::BorrowNotFound);
-    
-    let borrow_index = found_index.unwrap();
-    
-    // Calculate actual repayable amount (can't repay more than owed)
-    let repay_amount = std::cmp::min(amount, current_borrowed_amount);
+    // Cap how often the position can be changed
+    user_position.record_action(clock.unix_timestamp)?;
     
-    // Calculate how much borrow to remove (in scaled units)
-    let borrow = &mut user_position.borrows[borrow_index];
-    let scaled_amount_to_remove = (repay_amount as u128)
-        .checked_mul(borrow.amount_scaled)
-        .ok_or(ErrorCode::MathOverflow)?
-        .checked_div(borrow.amount_borrowed as u128)
-        .ok_or(ErrorCode::MathOverflow)?;
-    
-    // Update borrow values
-    borrow.amount_borrowed = borrow.amount_borrowed
-        .checked_sub(repay_amount)
-        .ok_or(ErrorCode::MathOverflow)?;
+    // Find the borrow positions
+    require!(
+        user_position.borrow_index(&pool.key()).is_some(),
+        OxygenError::BorrowNotFound
+    );
     
-    borrow.amount_scaled = borrow.amount_scaled
-        .checked_sub(scaled_amount_to_remove)
+    // Pay down the highest-rate borrows first, splitting the payment into principal
+    // and interest. Repayment is capped at the interest-inclusive debt, so overpaying
+    // to be safe charges exactly what is owed; cleared entries are removed
+    let (repay_amount, principal_to_remove) = user_position.reduce_pool_debt(
+        &pool.key(),
+        amount,
+        pool.cumulative_borrow_rate
+    )?;
+    let interest_paid = repay_amount
+        .checked_sub(principal_to_remove)
         .ok_or(ErrorCode::MathOverflow)?;
     
-    // Handle removal of the borrow entry if zero balance
-    if borrow.amount_borrowed == 0 {
-        user_position.borrows.remove(borrow_index);
+    // Update pool totals
+    // Pool borrows track principal only; the interest portion of the payment stays
+    // in the reserve for lenders. The pool-wide total is clamped so that any
+    // accounting drift can't underflow it
+    let pool_borrow_decrement = std::cmp::min(principal_to_remove, pool.total_borrows);
+    if pool_borrow_decrement != principal_to_remove {
+        emit!(PoolAccountingDriftEvent {
+            pool: pool.key(),
+            asset_mint: pool.asset_mint,
+            expected_amount: principal_to_remove,
+            recorded_amount: pool.total_borrows,
+            timestamp: clock.unix_timestamp,
+        });
     }
     
-    // Update pool totals
     pool.total_borrows = pool.total_borrows
-        .checked_sub(repay_amount)
+        .checked_sub(pool_borrow_decrement)
         .ok_or(ErrorCode::MathOverflow)?;
     
+    // Repaid funds become lendable again, but never more than lenders supplied
+    pool.available_lending_supply = pool.available_lending_supply
+        .saturating_add(pool_borrow_decrement)
+        .min(pool.total_lent);
+    
     // Transfer tokens from user to pool reserve
-    let cpi_accounts = Transfer {
+    let cpi_accounts = TransferChecked {
         from: ctx.accounts.user_token_account.to_account_info(),
+        mint: ctx.accounts.asset_mint.to_account_info(),
         to: ctx.accounts.asset_reserve.to_account_info(),
         authority: ctx.accounts.user.to_account_info(),
     };
@@ -116,12 +137,12 @@ pub fn handler(ctx: Context<Repay>, params: RepayParams) -> Result<()> {
         cpi_accounts,
     );
     
-    token::transfer(cpi_context, repay_amount)?;
+    token_interface::transfer_checked(cpi_context, repay_amount, ctx.accounts.asset_mint.decimals)?;
     
     // Update health factor
     // This is technically not necessary for repayments as they only improve health,
     // but it's good to keep the position's data accurate
-    if (!user_position.borrows.is_empty()) {
+    if (!user_position.borrows().is_empty()) {
         // Mock price data for simplistic health calculation
         // In a real implementation, this would involve fetching oracle prices
         let mut pool_data = std::collections::HashMap::new();
@@ -130,34 +151,24 @@ pub fn handler(ctx: Context<Repay>, params: RepayParams) -> Result<()> {
         // For simplicity, we'll use a 1:1 price and the pool's liquidation threshold
         pool_data.insert(pool.key(), (10000, pool.liquidation_threshold));
         
+        let mut borrow_rates = std::collections::HashMap::new();
+        borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+        
         // Recalculate health factor
-        let _ = user_position.calculate_health_factor(&pool_data)?;
+        let _ = user_position.calculate_health_factor_with_rates(&pool_data, &borrow_rates)?;
     } else {
         // No borrows, so perfectly healthy
         user_position.health_factor = u64::MAX;
     }
     
-    // Calculate the interest portion of the payment
-    // Interest is the difference between the current value of the borrowed amount
-    // and the original borrowed amount (if we knew it)
-    // For simplicity, we'll estimate it based on the current utilization
-    let interest_rate = pool.get_borrow_rate()?;
-    let interest_portion = (repay_amount as u128)
-        .checked_mul(interest_rate as u128)
-        .ok_or(ErrorCode::MathOverflow)?
-        .checked_div(10000)
-        .ok_or(ErrorCode::MathOverflow)? as u64;
-    
-    let principal_portion = repay_amount.checked_sub(interest_portion).unwrap_or(repay_amount);
-    
     // Emit repay event
     emit!(RepayEvent {
         user: ctx.accounts.user.key(),
         pool: pool.key(), 
         asset_mint: pool.asset_mint,
         amount: repay_amount,
-        interest_paid: interest_portion,
-        principal_paid: principal_portion,
+        interest_paid,
+        principal_paid: principal_to_remove,
         timestamp: clock.unix_timestamp,
     });
     
@@ -169,6 +180,8 @@ pub fn handler(ctx: Context<Repay>, params: RepayParams) -> Result<()> {
         utilization_rate,
         borrow_interest_rate: pool.get_borrow_rate()?,
         lending_interest_rate: pool.get_lending_rate()?,
+        cumulative_borrow_rate: pool.cumulative_borrow_rate,
+        cumulative_lending_rate: pool.cumulative_lending_rate,
         timestamp: clock.unix_timestamp,
     });
     
@@ -0,0 +1,115 @@
+use anchor_lang::prelude::*;
+use std::collections::HashMap;
+use crate::state::{Pool, UserPosition};
+use crate::modules::collateral::CollateralManager;
+use crate::modules::wallet_integration::WalletIntegration;
+use crate::errors::OxygenError;
+use crate::events::CollateralFlagChangedEvent;
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetCollateralStatusParams {
+    pub use_as_collateral: bool,      // Whether the deposit backs the user's borrows
+}
+
+#[derive(Accounts)]
+pub struct SetCollateralStatus<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    // The user's other collateral and borrow pools are passed as remaining accounts
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetCollateralStatus<'info>>,
+    params: SetCollateralStatusParams
+) -> Result<()> {
+    let pool = &ctx.accounts.pool;
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
+    let clock = Clock::get()?;
+    
+    pool.ensure_not_paused()?;
+    
+    // NON-CUSTODIAL: Validate that the user is signing their own change
+    WalletIntegration::validate_owner_signed(
+        &user_position.owner,
+        &ctx.accounts.user
+    )?;
+    
+    // Price the pool and every other pool of the user for the health check
+    pool.validate_risk_params()?;
+    let mut pool_data = HashMap::new();
+    pool_data.insert(pool.key(), (pool.health_price(clock.unix_timestamp)?, pool.liquidation_threshold));
+    
+    let mut borrow_rates = HashMap::new();
+    borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        let other_pool: Account<Pool> = Account::try_from(account_info)?;
+        if other_pool.key() == pool.key() {
+            continue;
+        }
+        
+        other_pool.validate_risk_params()?;
+        pool_data.insert(
+            other_pool.key(),
+            (other_pool.health_price(clock.unix_timestamp)?, other_pool.liquidation_threshold)
+        );
+        borrow_rates.insert(other_pool.key(), other_pool.cumulative_borrow_rate);
+    }
+    
+    // A missing pool would count as worthless, so disabling collateral could pass
+    // the health check while the user's real debt is left uncovered
+    user_position.require_priced_pools(&pool_data)?;
+    
+    let was_collateral = user_position.collaterals()
+        .iter()
+        .find(|collateral| collateral.pool == pool.key())
+        .ok_or(OxygenError::CollateralNotFound)?
+        .is_collateral;
+    
+    CollateralManager::set_collateral_status(
+        user_position,
+        &pool.key(),
+        params.use_as_collateral,
+        &pool_data,
+        &borrow_rates
+    )?;
+    
+    user_position.last_updated = clock.unix_timestamp;
+    
+    msg!(
+        "Collateral status for pool {} set to {}",
+        pool.key(),
+        params.use_as_collateral
+    );
+    
+    if was_collateral != params.use_as_collateral {
+        let collateral = user_position.collaterals()
+            .iter()
+            .find(|collateral| collateral.pool == pool.key())
+            .ok_or(OxygenError::CollateralNotFound)?;
+        
+        emit!(CollateralFlagChangedEvent {
+            user: ctx.accounts.user.key(),
+            pool: pool.key(),
+            is_collateral: collateral.is_collateral,
+            is_lending: collateral.is_lending,
+            timestamp: clock.unix_timestamp,
+        });
+    }
+    
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,149 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+use std::collections::HashMap;
+use crate::state::{Pool, UserPosition};
+use crate::errors::OxygenError;
+use crate::instructions::liquidate::{plan_liquidation, LiquidationAmount, LiquidationPlan, SeizurePrices};
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SimulateLiquidationParams {
+    pub amount: u64,                 // Amount of debt the liquidator would repay
+}
+
+/// Outcome of a liquidation, as it would be applied by `liquidate`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct LiquidationSimulation {
+    pub debt_repaid: u64,              // Debt tokens the liquidator would pay in
+    pub collateral_seized: u64,        // Collateral tokens the liquidator would receive, net of the insurance cut
+    pub bonus_value: u64,              // Portion of the received collateral that is bonus
+    pub insurance_fund_cut: u64,       // Collateral tokens of the bonus paid into the insurance fund
+    pub resulting_health_factor: u64,  // User's health factor after the liquidation
+}
+
+#[derive(Accounts)]
+pub struct SimulateLiquidation<'info> {
+    /// CHECK: Only used to derive the position address
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        seeds = [b"pool", debt_pool.asset_mint.as_ref()],
+        bump = debt_pool.bump,
+    )]
+    pub debt_pool: Account<'info, Pool>,
+    
+    #[account(address = debt_pool.asset_mint)]
+    pub debt_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        seeds = [b"pool", collateral_pool.asset_mint.as_ref()],
+        bump = collateral_pool.bump,
+    )]
+    pub collateral_pool: Account<'info, Pool>,
+    
+    #[account(address = collateral_pool.asset_mint)]
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    // The user's other collateral and borrow pools are passed as remaining accounts
+}
+
+/// Simulate a liquidation without mutating any state
+/// Applies the same checks and seizure math as `liquidate` through `plan_liquidation`,
+/// so bots can compute profitability before sending the real transaction.
+/// Only the named collateral pool is seized from; `liquidate` may continue into
+/// extra collateral pools for any debt it doesn't cover
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, SimulateLiquidation<'info>>,
+    params: SimulateLiquidationParams
+) -> Result<LiquidationSimulation> {
+    require!(params.amount > 0, OxygenError::InvalidParameter);
+    
+    let debt_pool = &ctx.accounts.debt_pool;
+    let collateral_pool = &ctx.accounts.collateral_pool;
+    let clock = Clock::get()?;
+    
+    // Mirror the liquidation preconditions so the simulation fails the same way
+    require!(
+        debt_pool.is_oracle_fresh(clock.unix_timestamp)
+            && collateral_pool.is_oracle_fresh(clock.unix_timestamp),
+        OxygenError::StaleOracleData
+    );
+    
+    // Work on a copy of the position so the account itself is left untouched
+    let mut position: UserPosition = *ctx.accounts.user_position.load()?;
+    
+    // Price every pool the way `liquidate` does, from their stored oracle prices
+    let mut pool_data = HashMap::new();
+    pool_data.insert(debt_pool.key(), (debt_pool.liquidation_price(clock.unix_timestamp)?, debt_pool.liquidation_threshold));
+    pool_data.insert(collateral_pool.key(), (collateral_pool.liquidation_price(clock.unix_timestamp)?, collateral_pool.liquidation_threshold));
+    
+    let mut borrow_rates = HashMap::new();
+    borrow_rates.insert(debt_pool.key(), debt_pool.cumulative_borrow_rate);
+    borrow_rates.insert(collateral_pool.key(), collateral_pool.cumulative_borrow_rate);
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        let pool: Account<Pool> = Account::try_from(account_info)?;
+        
+        let expected_pool = Pubkey::create_program_address(
+            &[b"pool", pool.asset_mint.as_ref(), &[pool.bump]],
+            ctx.program_id
+        ).map_err(|_| OxygenError::InvalidParameter)?;
+        
+        // Each pool may only appear once, including the named debt and collateral pools
+        require!(
+            pool.key() == expected_pool && !pool_data.contains_key(&pool.key()),
+            OxygenError::InvalidParameter
+        );
+        
+        pool_data.insert(pool.key(), (pool.liquidation_price(clock.unix_timestamp)?, pool.liquidation_threshold));
+        borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+    }
+    
+    position.require_priced_pools(&pool_data)?;
+    position.calculate_health_factor_with_rates(&pool_data, &borrow_rates)?;
+    position.update_unhealthy_since(clock.unix_timestamp);
+    
+    let prices = SeizurePrices {
+        debt_price: pool_data[&debt_pool.key()].0,
+        debt_decimals: ctx.accounts.debt_mint.decimals,
+        collateral_price: pool_data[&collateral_pool.key()].0,
+        collateral_decimals: ctx.accounts.collateral_mint.decimals,
+    };
+    
+    let LiquidationPlan { seizure, collateral_index, .. } = plan_liquidation(
+        &position,
+        debt_pool,
+        collateral_pool,
+        &pool_data,
+        &prices,
+        LiquidationAmount::Exact(params.amount),
+        clock.unix_timestamp
+    )?.ok_or(OxygenError::InvalidParameter)?;
+    
+    // Apply the seizure to the copy and measure the resulting health
+    let collateral_position = &mut position.collaterals_mut()[collateral_index];
+    collateral_position.amount_deposited = collateral_position.amount_deposited
+        .checked_sub(seizure.total_seized()?)
+        .ok_or(ErrorCode::MathOverflow)?;
+    if collateral_position.amount_deposited == 0 {
+        position.remove_collateral(collateral_index);
+    }
+    
+    position.reduce_pool_debt(&debt_pool.key(), seizure.debt_covered, debt_pool.cumulative_borrow_rate)?;
+    
+    let resulting_health_factor = position.calculate_health_factor_with_rates(&pool_data, &borrow_rates)?;
+    
+    Ok(LiquidationSimulation {
+        debt_repaid: seizure.debt_covered,
+        collateral_seized: seizure.liquidator_collateral,
+        bonus_value: seizure.liquidator_bonus(),
+        insurance_fund_cut: seizure.insurance_cut,
+        resulting_health_factor,
+    })
+}
\ No newline at end of file
@@ -1,9 +1,22 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use anchor_spl::dex::Dex;
 use std::collections::HashMap;
-use crate::state::{Pool, UserPosition, MarketInfo};
+use crate::state::{Pool, UserPosition, MarketInfo, PositionView, MarginMode};
 use crate::errors::OxygenError;
 use crate::modules::trading::TradingModule;
+use crate::events::LeveragedPositionLiquidatedEvent;
+
+/// Maximum number of extra pool accounts accepted by a single monitor call
+/// Each pool adds an account load and a health-factor input. Not yet measured:
+/// build with `--features cu-trace` and read the per-pool compute units off a
+/// program-test run before relying on this against the 200k budget
+pub const MAX_MONITOR_BATCH_SIZE: usize = 16;
+
+/// Maximum number of user positions a batch monitor call will process
+/// Every liquidation recomputes the user's health, so a batch stays small.
+/// Not yet measured either; `cu-trace` logs the compute units each user takes
+pub const MAX_MONITOR_USERS: usize = 4;
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct TradeParams {
@@ -13,15 +26,32 @@ pub struct TradeParams {
     pub order_type: OrderType,   // Limit or market
     pub leverage: u64,           // Leverage multiplier (e.g. 20000 = 2x)
     pub client_id: u64,          // Client order ID for tracking
+    pub stop_loss_price: u64,    // Stop-loss trigger price (0 = unset)
+    pub take_profit_price: u64,  // Take-profit trigger price (0 = unset)
+    pub margin_mode: MarginMode, // Cross or isolated margin
+    pub reduce_only: bool,       // Only net against opposing positions, never add exposure
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct ClosePositionParams {
     pub position_id: u64,        // ID of the position to close
-    pub price: u64,              // Execution price
+    pub close_size: u64,         // Size to close (0 = entire position)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AdjustPositionMarginParams {
+    pub position_id: u64,        // ID of the position to adjust
+    pub amount: u64,             // Margin to add or remove
+    pub add_margin: bool,        // True to add margin, false to remove it
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LiquidateLeveragedPositionParams {
+    pub position_id: u64,        // ID of the position to liquidate
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+#[repr(u8)]
 pub enum OrderSide {
     #[default]
     Buy,
@@ -42,6 +72,7 @@ pub struct TradeWithLeverage<'info> {
     pub user: Signer<'info>,
     
     #[account(
+        mut,
         seeds = [b"market", market_info.serum_market.as_ref()],
         bump = market_info.bump,
     )]
@@ -51,6 +82,7 @@ pub struct TradeWithLeverage<'info> {
         mut,
         seeds = [b"pool", base_asset_pool.asset_mint.as_ref()],
         bump = base_asset_pool.bump,
+        constraint = base_asset_pool.asset_mint == market_info.asset_mint @ OxygenError::SettlementPoolMismatch,
     )]
     pub base_asset_pool: Account<'info, Pool>,
     
@@ -58,6 +90,7 @@ pub struct TradeWithLeverage<'info> {
         mut,
         seeds = [b"pool", quote_asset_pool.asset_mint.as_ref()],
         bump = quote_asset_pool.bump,
+        constraint = quote_asset_pool.asset_mint == market_info.quote_mint @ OxygenError::SettlementPoolMismatch,
     )]
     pub quote_asset_pool: Account<'info, Pool>,
     
@@ -66,38 +99,77 @@ pub struct TradeWithLeverage<'info> {
         seeds = [b"reserve", base_asset_pool.key().as_ref()],
         bump,
         constraint = base_asset_reserve.mint == base_asset_pool.asset_mint,
+        constraint = base_asset_reserve.key() == base_asset_pool.asset_reserve,
     )]
-    pub base_asset_reserve: Account<'info, TokenAccount>,
+    pub base_asset_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"reserve", quote_asset_pool.key().as_ref()],
         bump,
         constraint = quote_asset_reserve.mint == quote_asset_pool.asset_mint,
+        constraint = quote_asset_reserve.key() == quote_asset_pool.asset_reserve,
     )]
-    pub quote_asset_reserve: Account<'info, TokenAccount>,
+    pub quote_asset_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"position", user.key().as_ref()],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key(),
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    /// CHECK: Serum market, validated against the market info and by the DEX program
+    #[account(
+        mut,
+        constraint = serum_market.key() == market_info.serum_market @ OxygenError::InvalidSerumMarket,
+    )]
+    pub serum_market: UncheckedAccount<'info>,
+    
+    /// CHECK: User's open orders PDA for this market, created on the first trade
+    #[account(
+        mut,
+        seeds = [b"open_orders", market_info.serum_market.as_ref(), user.key().as_ref()],
+        bump,
     )]
-    pub user_position: Account<'info, UserPosition>,
-    
-    // In a full implementation, we would include these Serum market accounts:
-    // pub serum_market: Account<'info, serum_dex::Market>,
-    // pub serum_request_queue: Account<'info, serum_dex::RequestQueue>,
-    // pub serum_event_queue: Account<'info, serum_dex::EventQueue>,
-    // pub serum_bids: Account<'info, serum_dex::Bids>,
-    // pub serum_asks: Account<'info, serum_dex::Asks>,
-    // pub serum_coin_vault: Account<'info, TokenAccount>,
-    // pub serum_pc_vault: Account<'info, TokenAccount>,
-    // #[account(mut)]
-    // pub open_orders: Account<'info, serum_dex::OpenOrders>,
-    
-    pub token_program: Program<'info, anchor_spl::token::Token>,
-    // pub dex_program: Program<'info, serum_dex::Dex>,
+    pub open_orders: UncheckedAccount<'info>,
+    
+    /// CHECK: Serum request queue, validated by the DEX program
+    #[account(mut)]
+    pub serum_request_queue: UncheckedAccount<'info>,
+    
+    /// CHECK: Serum event queue, validated by the DEX program
+    #[account(mut)]
+    pub serum_event_queue: UncheckedAccount<'info>,
+    
+    /// CHECK: Serum bids, validated by the DEX program
+    #[account(mut)]
+    pub serum_bids: UncheckedAccount<'info>,
+    
+    /// CHECK: Serum asks, validated by the DEX program
+    #[account(mut)]
+    pub serum_asks: UncheckedAccount<'info>,
+    
+    /// CHECK: Serum base (coin) vault, validated by the DEX program
+    #[account(mut)]
+    pub serum_coin_vault: UncheckedAccount<'info>,
+    
+    /// CHECK: Serum quote (pc) vault, validated by the DEX program
+    #[account(mut)]
+    pub serum_pc_vault: UncheckedAccount<'info>,
+    
+    // Pays for the order: quote tokens for a buy, base tokens for a sell
+    #[account(
+        mut,
+        constraint = order_payer_token_account.owner == user.key(),
+    )]
+    pub order_payer_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub dex_program: Program<'info, Dex>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
     pub clock: Sysvar<'info, Clock>,
 }
 
@@ -109,12 +181,13 @@ pub struct CloseTradePosition<'info> {
     #[account(
         mut,
         seeds = [b"position", user.key().as_ref()],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key(),
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
     )]
-    pub user_position: Account<'info, UserPosition>,
+    pub user_position: AccountLoader<'info, UserPosition>,
     
     #[account(
+        mut,
         seeds = [b"market", market_info.serum_market.as_ref()],
         bump = market_info.bump,
     )]
@@ -124,6 +197,7 @@ pub struct CloseTradePosition<'info> {
         mut,
         seeds = [b"pool", base_asset_pool.asset_mint.as_ref()],
         bump = base_asset_pool.bump,
+        constraint = base_asset_pool.asset_mint == market_info.asset_mint @ OxygenError::SettlementPoolMismatch,
     )]
     pub base_asset_pool: Account<'info, Pool>,
     
@@ -131,38 +205,175 @@ pub struct CloseTradePosition<'info> {
         mut,
         seeds = [b"pool", quote_asset_pool.asset_mint.as_ref()],
         bump = quote_asset_pool.bump,
+        constraint = quote_asset_pool.asset_mint == market_info.quote_mint @ OxygenError::SettlementPoolMismatch,
     )]
     pub quote_asset_pool: Account<'info, Pool>,
     
     // Similar to open trade, we would include Serum market accounts here
     // for a complete implementation
     
-    pub token_program: Program<'info, anchor_spl::token::Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct SettleFunding<'info> {
+    pub keeper: Signer<'info>,
+    
+    /// CHECK: Only used to derive the position address
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_info.serum_market.as_ref()],
+        bump = market_info.bump,
+    )]
+    pub market_info: Account<'info, MarketInfo>,
+    
+    #[account(
+        seeds = [b"pool", base_asset_pool.asset_mint.as_ref()],
+        bump = base_asset_pool.bump,
+        constraint = base_asset_pool.asset_mint == market_info.asset_mint @ OxygenError::SettlementPoolMismatch,
+    )]
+    pub base_asset_pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", quote_asset_pool.asset_mint.as_ref()],
+        bump = quote_asset_pool.bump,
+        constraint = quote_asset_pool.asset_mint == market_info.quote_mint @ OxygenError::SettlementPoolMismatch,
+    )]
+    pub quote_asset_pool: Account<'info, Pool>,
+    
     pub clock: Sysvar<'info, Clock>,
 }
 
-pub fn open_trade(ctx: Context<TradeWithLeverage>, params: TradeParams) -> Result<()> {
+#[derive(Accounts)]
+pub struct MonitorPositionsBatch<'info> {
+    pub keeper: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_info.serum_market.as_ref()],
+        bump = market_info.bump,
+    )]
+    pub market_info: Account<'info, MarketInfo>,
+    
+    #[account(
+        seeds = [b"pool", base_asset_pool.asset_mint.as_ref()],
+        bump = base_asset_pool.bump,
+        constraint = base_asset_pool.asset_mint == market_info.asset_mint @ OxygenError::SettlementPoolMismatch,
+    )]
+    pub base_asset_pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", quote_asset_pool.asset_mint.as_ref()],
+        bump = quote_asset_pool.bump,
+        constraint = quote_asset_pool.asset_mint == market_info.quote_mint @ OxygenError::SettlementPoolMismatch,
+    )]
+    pub quote_asset_pool: Account<'info, Pool>,
+    
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidateLeveragedPosition<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+    
+    /// CHECK: Only used to derive the position address
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_info.serum_market.as_ref()],
+        bump = market_info.bump,
+    )]
+    pub market_info: Account<'info, MarketInfo>,
+    
+    #[account(
+        seeds = [b"pool", base_asset_pool.asset_mint.as_ref()],
+        bump = base_asset_pool.bump,
+        constraint = base_asset_pool.asset_mint == market_info.asset_mint @ OxygenError::SettlementPoolMismatch,
+    )]
+    pub base_asset_pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", quote_asset_pool.asset_mint.as_ref()],
+        bump = quote_asset_pool.bump,
+        constraint = quote_asset_pool.asset_mint == market_info.quote_mint @ OxygenError::SettlementPoolMismatch,
+    )]
+    pub quote_asset_pool: Account<'info, Pool>,
+    
+    #[account(address = quote_asset_pool.asset_mint)]
+    pub quote_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"reserve", quote_asset_pool.key().as_ref()],
+        bump,
+        constraint = quote_asset_reserve.mint == quote_asset_pool.asset_mint,
+        constraint = quote_asset_reserve.key() == quote_asset_pool.asset_reserve,
+    )]
+    pub quote_asset_reserve: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = keeper_quote_token_account.mint == quote_asset_pool.asset_mint,
+        constraint = keeper_quote_token_account.owner == keeper.key(),
+    )]
+    pub keeper_quote_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+pub fn open_trade<'info>(mut ctx: Context<'_, '_, '_, 'info, TradeWithLeverage<'info>>, params: TradeParams) -> Result<()> {
     // Validate parameters
     require!(params.size > 0, OxygenError::InvalidParameter);
     require!(params.price > 0, OxygenError::InvalidParameter);
     require!(params.leverage >= 10000, OxygenError::InvalidParameter); // Min 1x leverage
     
-    let market_info = &ctx.accounts.market_info;
-    let user_position = &mut ctx.accounts.user_position;
+    // Enforce the market's leverage cap before any state is touched, and before
+    // leverage is used as a divisor where a huge value would round margin to nothing
+    require!(
+        ctx.accounts.market_info.is_leverage_valid(params.leverage),
+        OxygenError::MaxLeverageExceeded
+    );
+    
+    let market_info = &mut ctx.accounts.market_info;
+    let serum_market = market_info.serum_market;
+    
+    // Reject new trades while either pool is paused
+    ctx.accounts.base_asset_pool.ensure_not_paused()?;
+    ctx.accounts.quote_asset_pool.ensure_not_paused()?;
+    
     let base_pool = &ctx.accounts.base_asset_pool;
-    let quote_pool = &ctx.accounts.quote_asset_pool;
+    let quote_pool = &mut ctx.accounts.quote_asset_pool;
     
-    // Calculate the notional value of the position
-    let position_value = (params.size as u128)
-        .checked_mul(params.price as u128)
-        .ok_or(ErrorCode::MathOverflow)? as u64;
-        
-    // Calculate required margin
-    let required_margin = position_value
-        .checked_mul(10000) // Base scale factor (10000 = 1x)
-        .ok_or(ErrorCode::MathOverflow)?
-        .checked_div(params.leverage)
-        .ok_or(ErrorCode::MathOverflow)?;
+    // Positions are marked at the oracle price rather than the order's limit price,
+    // so PnL is always measured between two oracle prices. The limit price must
+    // still be close to it for the order to make sense
+    let mark_price = TradingModule::mark_price(base_pool, ctx.accounts.clock.unix_timestamp)?;
+    TradingModule::require_within_deviation(params.price, mark_price, market_info.max_oracle_deviation)?;
     
     // Mock price data for health factor calculation
     // In a real implementation, this would come from oracles
@@ -170,39 +381,41 @@ pub fn open_trade(ctx: Context<TradeWithLeverage>, params: TradeParams) -> Resul
     pool_data.insert(base_pool.key(), (10000, base_pool.liquidation_threshold));
     pool_data.insert(quote_pool.key(), (10000, quote_pool.liquidation_threshold));
     
-    // Create open orders account if it doesn't exist yet
-    // In a real implementation, we would check if the user already has an open orders account
-    // for this market and create one if needed
-    let _ = TradingModule::initialize_open_orders_account(&ctx)?;
-    
-    // 1. Lock the required margin from the user's collateral
-    TradingModule::lock_margin_from_collateral(
-        user_position,
-        required_margin,
-        &pool_data
-    )?;
-    
-    // Create the order on Serum DEX
-    let position_id = TradingModule::create_order(
-        &ctx.accounts.user.key(),
-        &market_info.serum_market,
-        market_info,
-        base_pool,
-        quote_pool,
-        user_position,
-        params.side,
-        params.order_type,
-        params.size,
-        params.price,
-        params.leverage,
-        params.client_id,
-        &pool_data
-    )?;
+    // 1. Validate the trade, then lock its margin and record the position
+    // Margin is only locked once the trade has passed validation
+    // The position is released afterwards so the open orders setup can record to it
+    let position_id = {
+        let user_position = &mut ctx.accounts.user_position.load_mut()?;
+        
+        // Cap how often the position can be changed
+        user_position.record_action(ctx.accounts.clock.unix_timestamp)?;
+        
+        TradingModule::create_order(
+            &ctx.accounts.user.key(),
+            &serum_market,
+            market_info,
+            base_pool,
+            quote_pool,
+            user_position,
+            params.side,
+            params.order_type,
+            params.size,
+            mark_price,
+            params.leverage,
+            params.stop_loss_price,
+            params.take_profit_price,
+            params.margin_mode,
+            params.reduce_only,
+            params.client_id,
+            &pool_data
+        )?
+    };
+    
+    // 2. Place the actual order on Serum DEX, creating the open orders account first if needed
+    TradingModule::initialize_open_orders_account(&mut ctx)?;
     
-    // 2. Place the actual order on Serum DEX
     TradingModule::place_serum_dex_order(
         &ctx,
-        market_info,
         params.side,
         params.order_type,
         params.size,
@@ -214,6 +427,7 @@ pub fn open_trade(ctx: Context<TradeWithLeverage>, params: TradeParams) -> Resul
     // Note: This is already done inside the create_order function
     
     // Update the user's health factor with the new position
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
     user_position.calculate_health_factor(&pool_data)?;
     user_position.last_updated = ctx.accounts.clock.unix_timestamp;
     
@@ -232,7 +446,28 @@ pub fn open_trade(ctx: Context<TradeWithLeverage>, params: TradeParams) -> Resul
 }
 
 pub fn close_position(ctx: Context<CloseTradePosition>, params: ClosePositionParams) -> Result<()> {
-    let user_position = &mut ctx.accounts.user_position;
+    // Reject closing trades while either pool is paused
+    ctx.accounts.base_asset_pool.ensure_not_paused()?;
+    ctx.accounts.quote_asset_pool.ensure_not_paused()?;
+    
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
+    
+    // Cap how often the position can be changed
+    user_position.record_action(ctx.accounts.clock.unix_timestamp)?;
+    
+    // PnL is only ever realized at the oracle price
+    let execution_price = TradingModule::mark_price(
+        &ctx.accounts.base_asset_pool,
+        ctx.accounts.clock.unix_timestamp
+    )?;
+    
+    // Funding owed up to now is paid before the position can be closed
+    TradingModule::settle_funding(
+        user_position,
+        &mut ctx.accounts.market_info,
+        &mut ctx.accounts.quote_asset_pool,
+        execution_price
+    )?;
     
     // Mock price data for health factor calculation
     let mut pool_data = HashMap::new();
@@ -244,49 +479,135 @@ pub fn close_position(ctx: Context<CloseTradePosition>, params: ClosePositionPar
     // Close the position
     TradingModule::close_position(
         user_position,
+        &mut ctx.accounts.market_info,
+        &mut ctx.accounts.quote_asset_pool,
         params.position_id,
-        params.price,
+        params.close_size,
+        execution_price,
         &pool_data
     )?;
     
-    // In a full implementation, we would:
-    // 1. Place a counter order on Serum DEX to close the position
-    // 2. Return the locked margin to the user's available collateral
-    // 3. Apply the PnL to the user's balance
+    // In a full implementation, we would also place a counter order on Serum DEX to close the position
     
     // Update user position's health factor
     user_position.calculate_health_factor(&pool_data)?;
     user_position.last_updated = ctx.accounts.clock.unix_timestamp;
     
-    msg!("Closed leveraged position {} at price {}", params.position_id, params.price);
+    msg!("Closed leveraged position {} at price {}", params.position_id, execution_price);
+    
+    Ok(())
+}
+
+/// Add margin to or remove margin from an open leveraged position
+/// Health is checked against the pools' oracle prices, and the user's other
+/// collateral or borrow pools are passed as remaining accounts
+pub fn adjust_position_margin<'info>(
+    ctx: Context<'_, '_, '_, 'info, CloseTradePosition<'info>>,
+    params: AdjustPositionMarginParams
+) -> Result<()> {
+    // Reject margin changes while either pool is paused
+    ctx.accounts.base_asset_pool.ensure_not_paused()?;
+    ctx.accounts.quote_asset_pool.ensure_not_paused()?;
+    
+    // Build health factor inputs from each pool's own oracle price and liquidation threshold
+    let now = ctx.accounts.clock.unix_timestamp;
+    let mut pool_data = HashMap::new();
+    for pool in [&ctx.accounts.base_asset_pool, &ctx.accounts.quote_asset_pool] {
+        pool_data.insert(pool.key(), (pool.health_price(now)?, pool.liquidation_threshold));
+    }
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        let pool: Account<Pool> = Account::try_from(account_info)?;
+        pool_data.insert(pool.key(), (pool.health_price(now)?, pool.liquidation_threshold));
+    }
+    
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
+    let market_info = &ctx.accounts.market_info;
+    
+    user_position.require_priced_pools(&pool_data)?;
+    
+    // Cap how often the position can be changed
+    user_position.record_action(now)?;
+    
+    if params.add_margin {
+        TradingModule::add_position_margin(
+            user_position,
+            market_info,
+            params.position_id,
+            params.amount,
+            &pool_data
+        )?;
+    } else {
+        TradingModule::remove_position_margin(
+            user_position,
+            market_info,
+            params.position_id,
+            params.amount,
+            &pool_data
+        )?;
+    }
+    
+    // Update user position's health factor
+    user_position.calculate_health_factor(&pool_data)?;
+    user_position.last_updated = now;
     
     Ok(())
 }
 
 /// Monitor open leveraged positions and liquidate if necessary
+/// Positions are checked against the market's oracle mark price, and the user's
+/// other collateral or borrow pools are passed as remaining accounts
 pub fn monitor_positions_for_liquidation<'info>(
-    ctx: Context<'_, '_, '_, 'info>, 
-    current_prices: HashMap<Pubkey, u64>
+    ctx: Context<'_, '_, '_, 'info, CloseTradePosition<'info>>
 ) -> Result<()> {
-    // Extract the user position to monitor
-    let user_position = &mut ctx.accounts.user_position;
+    // Reject oversized batches up front rather than running out of compute midway
+    check_monitor_batch_size(1, ctx.remaining_accounts.len())?;
+    
+    // Reject keeper liquidations while either pool is paused
+    ctx.accounts.base_asset_pool.ensure_not_paused()?;
+    ctx.accounts.quote_asset_pool.ensure_not_paused()?;
+    
+    // Leveraged positions must not be liquidated against a stale market price
+    let now = ctx.accounts.clock.unix_timestamp;
+    require!(
+        ctx.accounts.base_asset_pool.is_oracle_fresh(now)
+            && ctx.accounts.quote_asset_pool.is_oracle_fresh(now),
+        OxygenError::StaleOracleData
+    );
     
-    // Mock price data for health factor calculation
-    let mut pool_data = HashMap::new();
+    let mark_price = TradingModule::mark_price(&ctx.accounts.base_asset_pool, now)?;
     
-    // In a real implementation, we would:
-    // 1. Add all pool data from oracles
-    // 2. Monitor positions across multiple users
+    // Build health factor inputs from each pool's own oracle price and liquidation threshold
+    let mut pool_data = HashMap::new();
+    for pool in [&ctx.accounts.base_asset_pool, &ctx.accounts.quote_asset_pool] {
+        pool_data.insert(pool.key(), (pool.health_price(now)?, pool.liquidation_threshold));
+    }
     
-    // Add some mock data for the example
-    for (market, price) in &current_prices {
-        pool_data.insert(*market, (*price, 8000)); // 80% liquidation threshold
+    for account_info in ctx.remaining_accounts.iter() {
+        let pool: Account<Pool> = Account::try_from(account_info)?;
+        pool_data.insert(pool.key(), (pool.health_price(now)?, pool.liquidation_threshold));
+        log_compute_units("monitor: extra pool loaded");
     }
     
+    // Extract the user position to monitor
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
+    
+    user_position.require_priced_pools(&pool_data)?;
+    
+    // Funding owed up to now is paid before any position is closed
+    TradingModule::settle_funding(
+        user_position,
+        &mut ctx.accounts.market_info,
+        &mut ctx.accounts.quote_asset_pool,
+        mark_price
+    )?;
+    
     // Monitor and potentially liquidate positions
     TradingModule::monitor_positions(
         user_position,
-        &current_prices,
+        &mut ctx.accounts.market_info,
+        &mut ctx.accounts.quote_asset_pool,
+        mark_price,
         &pool_data
     )?;
     
@@ -296,54 +617,250 @@ pub fn monitor_positions_for_liquidation<'info>(
     Ok(())
 }
 
-/// Process the funding rate adjustments for open leveraged positions
-pub fn process_funding_rates<'info>(
-    ctx: Context<'_, '_, '_, 'info>,
-    funding_rates: HashMap<Pubkey, i64>  // Positive = longs pay shorts, negative = shorts pay longs
+/// Monitor the leveraged positions of several users on one market in a single call
+/// Remaining accounts hold the user positions to check, followed by any other
+/// collateral or borrow pools those users hold. Anyone may call this, so every
+/// price comes from the pools' own oracle prices
+pub fn monitor_positions_batch<'info>(
+    ctx: Context<'_, '_, '_, 'info, MonitorPositionsBatch<'info>>
 ) -> Result<()> {
-    // Extract the user position to monitor
-    let user_position = &mut ctx.accounts.user_position;
-    
-    // Process funding payments for each open position
-    for position in &mut user_position.leveraged_positions {
-        if let Some(&rate) = funding_rates.get(&position.market) {
-            // Skip closed positions
-            if position.status != crate::state::PositionStatus::Open {
-                continue;
-            }
-            
-            // Calculate funding amount based on position size and rate
-            // rate is in basis points per hour (e.g. 1 = 0.01% per hour)
-            let funding_amount = (position.position_value as i128)
-                .checked_mul(rate as i128)
-                .ok_or(ErrorCode::MathOverflow)?
-                .checked_div(1_000_000) // 10000 (bps) * 100 (percent)
-                .ok_or(ErrorCode::MathOverflow)? as i64;
-            
-            // Apply funding
-            // Positive funding: longs pay shorts
-            // Negative funding: shorts pay longs
-            let funding_direction = match position.side {
-                OrderSide::Buy => -funding_amount, // Longs pay when positive rate
-                OrderSide::Sell => funding_amount, // Shorts receive when positive rate
+    // Reject keeper liquidations while either pool is paused
+    ctx.accounts.base_asset_pool.ensure_not_paused()?;
+    ctx.accounts.quote_asset_pool.ensure_not_paused()?;
+    
+    // Leveraged positions must not be liquidated against a stale market price
+    let now = ctx.accounts.clock.unix_timestamp;
+    require!(
+        ctx.accounts.base_asset_pool.is_oracle_fresh(now)
+            && ctx.accounts.quote_asset_pool.is_oracle_fresh(now),
+        OxygenError::StaleOracleData
+    );
+    
+    let mark_price = TradingModule::mark_price(&ctx.accounts.base_asset_pool, now)?;
+    
+    let mut pool_data = HashMap::new();
+    for pool in [&ctx.accounts.base_asset_pool, &ctx.accounts.quote_asset_pool] {
+        pool_data.insert(pool.key(), (pool.health_price(now)?, pool.liquidation_threshold));
+    }
+    
+    // Split the remaining accounts into user positions and the extra pools they need
+    let mut user_positions: Vec<AccountLoader<'info, UserPosition>> = Vec::new();
+    let mut extra_pools = 0;
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        if let Ok(user_position) = AccountLoader::<UserPosition>::try_from(account_info) {
+            let (owner, bump) = {
+                let position = user_position.load()?;
+                (position.owner, position.bump)
             };
+            let expected_position = Pubkey::create_program_address(
+                &[b"position", owner.as_ref(), &[bump]],
+                ctx.program_id
+            ).map_err(|_| OxygenError::InvalidParameter)?;
             
-            msg!("Position {} funding payment: {}", position.id, funding_direction);
+            // Each user may only be monitored once per call
+            require!(
+                user_position.key() == expected_position
+                    && !user_positions.iter().any(|other| other.key() == expected_position),
+                OxygenError::InvalidParameter
+            );
             
-            // In a real implementation, we would actually transfer the funds
-            // between longs and shorts in the protocol
+            user_positions.push(user_position);
+        } else {
+            let pool: Account<Pool> = Account::try_from(account_info)?;
+            pool_data.insert(pool.key(), (pool.health_price(now)?, pool.liquidation_threshold));
+            extra_pools += 1;
+            log_compute_units("monitor batch: extra pool loaded");
         }
     }
     
+    // Reject oversized batches up front rather than running out of compute midway
+    check_monitor_batch_size(user_positions.len(), extra_pools)?;
+    
+    for user_position in user_positions.iter() {
+        // Zero-copy accounts are written in place, so nothing needs persisting afterwards
+        let user_position = &mut user_position.load_mut()?;
+        
+        user_position.require_priced_pools(&pool_data)?;
+        
+        TradingModule::settle_funding(
+            user_position,
+            &mut ctx.accounts.market_info,
+            &mut ctx.accounts.quote_asset_pool,
+            mark_price
+        )?;
+        
+        TradingModule::monitor_positions(
+            user_position,
+            &mut ctx.accounts.market_info,
+            &mut ctx.accounts.quote_asset_pool,
+            mark_price,
+            &pool_data
+        )?;
+        
+        user_position.calculate_health_factor(&pool_data)?;
+        log_compute_units("monitor batch: user processed");
+    }
+    
+    Ok(())
+}
+
+// A monitor call must cover at least one user and stay within both batch limits
+fn check_monitor_batch_size(users: usize, extra_pools: usize) -> Result<()> {
+    require!(
+        users > 0 && users <= MAX_MONITOR_USERS && extra_pools <= MAX_MONITOR_BATCH_SIZE,
+        OxygenError::TransactionSizeExceeded
+    );
+    
+    Ok(())
+}
+
+// Log the compute units left after each batch item, so MAX_MONITOR_BATCH_SIZE and
+// MAX_MONITOR_USERS can be sized from a program-test run built with `cu-trace`
+#[cfg(feature = "cu-trace")]
+fn log_compute_units(item: &str) {
+    msg!(item);
+    anchor_lang::solana_program::log::sol_log_compute_units();
+}
+
+#[cfg(not(feature = "cu-trace"))]
+fn log_compute_units(_item: &str) {}
+
+/// Liquidate a single named leveraged position on behalf of a keeper
+/// The keeper is paid the market's liquidation fee out of the position's remaining margin
+/// Eligibility and settlement use the oracle mark price, never a keeper-supplied one
+pub fn liquidate_leveraged_position(
+    ctx: Context<LiquidateLeveragedPosition>,
+    params: LiquidateLeveragedPositionParams
+) -> Result<()> {
+    let now = ctx.accounts.clock.unix_timestamp;
+    
+    // Reject liquidations while either pool is paused
+    ctx.accounts.base_asset_pool.ensure_not_paused()?;
+    ctx.accounts.quote_asset_pool.ensure_not_paused()?;
+    
+    // Leveraged positions must not be liquidated against a stale market price
+    require!(
+        ctx.accounts.base_asset_pool.is_oracle_fresh(now)
+            && ctx.accounts.quote_asset_pool.is_oracle_fresh(now),
+        OxygenError::StaleOracleData
+    );
+    
+    let execution_price = TradingModule::mark_price(&ctx.accounts.base_asset_pool, now)?;
+    
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
+    
+    let position_value = user_position.leveraged_positions()
+        .iter()
+        .find(|position| position.id == params.position_id)
+        .map(|position| position.position_value)
+        .ok_or(OxygenError::PositionNotFound)?;
+    
+    let mut pool_data = HashMap::new();
+    for pool in [&ctx.accounts.base_asset_pool, &ctx.accounts.quote_asset_pool] {
+        pool_data.insert(
+            pool.key(),
+            (pool.health_price(now)?, pool.liquidation_threshold)
+        );
+    }
+    
+    // Eligibility is checked by comparing the mark price to the position's stored
+    // liquidation price
+    let remaining_margin = TradingModule::liquidate_position(
+        user_position,
+        &mut ctx.accounts.market_info,
+        &mut ctx.accounts.quote_asset_pool,
+        params.position_id,
+        execution_price,
+        &pool_data
+    )?;
+    
+    // The keeper's bonus can never exceed what's left of the user's margin
+    let fee_on_value = (position_value as u128)
+        .checked_mul(ctx.accounts.market_info.liquidation_fee as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let fee_on_value = u64::try_from(fee_on_value).map_err(|_| ErrorCode::MathOverflow)?;
+    
+    let keeper_bonus = TradingModule::forfeit_margin(
+        user_position,
+        &mut ctx.accounts.quote_asset_pool,
+        std::cmp::min(fee_on_value, remaining_margin)
+    )?;
+    
+    if keeper_bonus > 0 {
+        let quote_pool = &ctx.accounts.quote_asset_pool;
+        let pool_seeds = &[
+            b"pool".as_ref(),
+            quote_pool.asset_mint.as_ref(),
+            &[quote_pool.bump],
+        ];
+        
+        let pool_signer = &[&pool_seeds[..]];
+        
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.quote_asset_reserve.to_account_info(),
+            mint: ctx.accounts.quote_mint.to_account_info(),
+            to: ctx.accounts.keeper_quote_token_account.to_account_info(),
+            authority: quote_pool.to_account_info(),
+        };
+        
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            pool_signer,
+        );
+        
+        token_interface::transfer_checked(cpi_context, keeper_bonus, ctx.accounts.quote_mint.decimals)?;
+    }
+    
+    user_position.last_updated = now;
+    
+    emit!(LeveragedPositionLiquidatedEvent {
+        keeper: ctx.accounts.keeper.key(),
+        user: ctx.accounts.user.key(),
+        position_id: params.position_id,
+        market: ctx.accounts.market_info.serum_market,
+        execution_price,
+        keeper_bonus,
+        timestamp: now,
+    });
+    
+    msg!("Keeper liquidated leveraged position {} for a {} bonus", params.position_id, keeper_bonus);
+    
+    Ok(())
+}
+
+/// Settle a user's funding on one market on behalf of a keeper
+/// The rate comes from the market's own funding index, so anyone may settle any position
+pub fn process_funding_rates(ctx: Context<SettleFunding>) -> Result<()> {
+    // Reject funding settlement while the funding pool is paused
+    ctx.accounts.quote_asset_pool.ensure_not_paused()?;
+    
+    // Positions that can't cover their funding are closed at the oracle mark price
+    let mark_price = TradingModule::mark_price(
+        &ctx.accounts.base_asset_pool,
+        ctx.accounts.clock.unix_timestamp
+    )?;
+    
+    // Funding settles against this market's funding pool in its quote asset
+    TradingModule::settle_funding(
+        &mut *ctx.accounts.user_position.load_mut()?,
+        &mut ctx.accounts.market_info,
+        &mut ctx.accounts.quote_asset_pool,
+        mark_price
+    )?;
+    
     Ok(())
 }
 
 /// Get user's open leveraged positions
 pub fn get_open_positions<'info>(ctx: Context<'_, '_, '_, 'info>) -> Result<Vec<u64>> {
-    let user_position = &ctx.accounts.user_position;
+    let user_position = &ctx.accounts.user_position.load()?;
     
     let mut open_positions = Vec::new();
-    for position in &user_position.leveraged_positions {
+    for position in user_position.leveraged_positions() {
         if position.status == crate::state::PositionStatus::Open {
             open_positions.push(position.id);
         }
@@ -352,4 +869,61 @@ pub fn get_open_positions<'info>(ctx: Context<'_, '_, '_, 'info>) -> Result<Vec<
     msg!("User has {} open positions", open_positions.len());
     
     Ok(open_positions)
+}
+
+/// Return every open leveraged position with its unrealized PnL
+/// Mark prices are keyed by market and must cover every market with an open position,
+/// since valuing a position at its entry price would report a misleading zero PnL
+pub fn get_position_details(
+    ctx: Context<CloseTradePosition>,
+    mark_prices: HashMap<Pubkey, u64>
+) -> Result<Vec<PositionView>> {
+    let user_position = &ctx.accounts.user_position.load()?;
+    
+    let mut positions = Vec::new();
+    for position in user_position.leveraged_positions() {
+        if position.status != crate::state::PositionStatus::Open {
+            continue;
+        }
+        
+        let mark_price = mark_prices
+            .get(&position.market)
+            .copied()
+            .ok_or(OxygenError::InvalidParameter)?;
+        
+        positions.push(PositionView {
+            id: position.id,
+            market: position.market,
+            side: position.side,
+            size: position.size,
+            entry_price: position.entry_price,
+            leverage: position.leverage,
+            margin_used: position.margin_used,
+            liquidation_price: position.liquidation_price,
+            mark_price,
+            unrealized_pnl: TradingModule::calculate_unrealized_pnl(position, mark_price)?,
+        });
+    }
+    
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn monitor_batch_at_the_limits_is_accepted() {
+        assert!(check_monitor_batch_size(1, 0).is_ok());
+        assert!(check_monitor_batch_size(MAX_MONITOR_USERS, MAX_MONITOR_BATCH_SIZE).is_ok());
+    }
+    
+    #[test]
+    fn monitor_batch_over_the_limits_is_rejected() {
+        let too_large: Error = OxygenError::TransactionSizeExceeded.into();
+        
+        assert_eq!(check_monitor_batch_size(MAX_MONITOR_USERS + 1, 0).unwrap_err(), too_large);
+        assert_eq!(check_monitor_batch_size(1, MAX_MONITOR_BATCH_SIZE + 1).unwrap_err(), too_large);
+        assert_eq!(check_monitor_batch_size(0, 0).unwrap_err(), too_large);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+use anchor_lang::prelude::*;
+use crate::state::UserPosition;
+use crate::errors::OxygenError;
+use crate::events::PositionTransferredEvent;
+use crate::modules::wallet_integration::WalletIntegration;
+
+// Position PDAs are seeded by the owner's wallet, so ownership can't simply be
+// reassigned in place: every other instruction would still derive the old
+// address from the old owner. Instead the whole position (collaterals, borrows
+// and leveraged trades) is copied into a freshly created PDA for the new owner,
+// and the old account is closed with its rent refunded to the previous owner.
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Only used as the seed and owner of the new position
+    pub new_owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"position", owner.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == owner.key(),
+        close = owner,
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = UserPosition::space(),
+        seeds = [b"position", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_user_position: AccountLoader<'info, UserPosition>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<TransferPosition>) -> Result<()> {
+    let clock = Clock::get()?;
+    let new_owner = ctx.accounts.new_owner.key();
+
+    let user_position = &ctx.accounts.user_position.load()?;
+    
+    // NON-CUSTODIAL: Only the current owner can hand over their position
+    WalletIntegration::validate_owner_signed(
+        &user_position.owner,
+        &ctx.accounts.owner
+    )?;
+
+    require!(
+        new_owner != ctx.accounts.owner.key(),
+        OxygenError::InvalidParameter
+    );
+
+    // Migrate the full position state, then re-point it at the new owner's PDA
+    let migrated = &mut ctx.accounts.new_user_position.load_init()?;
+    **migrated = **user_position;
+    migrated.owner = new_owner;
+    migrated.bump = *ctx.bumps.get("new_user_position").unwrap();
+    migrated.last_updated = clock.unix_timestamp;
+    
+    // Open orders accounts are derived from and owned by the previous wallet,
+    // so the new owner creates their own on their next trade
+    migrated.clear_open_orders_accounts();
+
+    let collateral_count = migrated.collateral_count;
+    let borrow_count = migrated.borrow_count;
+    let leveraged_position_count = migrated.leveraged_position_count;
+
+    emit!(PositionTransferredEvent {
+        previous_owner: ctx.accounts.owner.key(),
+        new_owner,
+        collateral_count,
+        borrow_count,
+        leveraged_position_count,
+        timestamp: clock.unix_timestamp,
+    });
+
+    msg!(
+        "Transferred position from {} to {}",
+        ctx.accounts.owner.key(),
+        new_owner
+    );
+
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,150 @@
+use anchor_lang::prelude::*;
+use std::collections::HashMap;
+use crate::state::{Pool, UserPosition};
+use crate::errors::OxygenError;
+use crate::modules::yield_generation::YieldModule;
+use crate::modules::wallet_integration::WalletIntegration;
+use crate::events::{LendingEnabledEvent, LendingDisabledEvent, CollateralFlagChangedEvent};
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpdateLendingStatusParams {
+    pub enable_lending: bool,         // Whether to lend the deposit to other users
+}
+
+#[derive(Accounts)]
+pub struct UpdateLendingStatus<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+}
+
+pub fn handler(ctx: Context<UpdateLendingStatus>, params: UpdateLendingStatusParams) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    let user_position = &mut ctx.accounts.user_position.load_mut()?;
+    let clock = Clock::get()?;
+    
+    // Reject changes while the pool is paused
+    pool.ensure_not_paused()?;
+    
+    // NON-CUSTODIAL: Validate that the user is signing their own change
+    WalletIntegration::validate_owner_signed(
+        &user_position.owner,
+        &ctx.accounts.user
+    )?;
+    
+    // Update pool rates before any operations
+    pool.update_rates(clock.unix_timestamp)?;
+    
+    let collateral = user_position.collaterals()
+        .iter()
+        .find(|collateral| collateral.pool == pool.key())
+        .ok_or(OxygenError::CollateralNotFound)?;
+    
+    require!(
+        collateral.is_lending != params.enable_lending,
+        OxygenError::InvalidParameter
+    );
+    
+    let amount = collateral.amount_deposited;
+    
+    if params.enable_lending {
+        require!(pool.lending_enabled, OxygenError::LendingNotEnabled);
+        
+        // Calculate the maximum lending capacity based on the max_lending_ratio
+        let total_after_enable = pool.total_lent
+            .checked_add(amount)
+            .ok_or(OxygenError::MathOverflow)?;
+        
+        let max_lending_capacity = (pool.total_deposits as u128)
+            .checked_mul(pool.max_lending_ratio as u128)
+            .ok_or(OxygenError::MathOverflow)?
+            .checked_div(10000)
+            .ok_or(OxygenError::MathOverflow)? as u64;
+        
+        require!(
+            total_after_enable <= max_lending_capacity,
+            OxygenError::MaxLendingCapacityReached
+        );
+        
+        pool.total_lent = total_after_enable;
+        pool.available_lending_supply = pool.available_lending_supply
+            .checked_add(amount)
+            .ok_or(OxygenError::MathOverflow)?;
+    } else {
+        // Lent deposits must stay lent for the pool's minimum duration
+        require!(
+            pool.min_lending_duration == 0
+                || clock.unix_timestamp - collateral.deposit_timestamp >= pool.min_lending_duration as i64,
+            OxygenError::MinLendingDurationNotMet
+        );
+        
+        // Only the part of the deposit that isn't currently borrowed can be taken back
+        require!(
+            pool.available_lending_supply >= amount,
+            OxygenError::InsufficientLiquidity
+        );
+        
+        pool.total_lent = pool.total_lent.saturating_sub(amount);
+        pool.available_lending_supply -= amount;
+    }
+    
+    pool.validate_risk_params()?;
+    let mut pool_data = HashMap::new();
+    pool_data.insert(pool.key(), (pool.health_price(clock.unix_timestamp)?, pool.liquidation_threshold));
+    
+    YieldModule::set_lending_status(
+        user_position,
+        &pool.key(),
+        params.enable_lending,
+        &pool_data
+    )?;
+    
+    user_position.last_updated = clock.unix_timestamp;
+    
+    if params.enable_lending {
+        emit!(LendingEnabledEvent {
+            user: ctx.accounts.user.key(),
+            pool: pool.key(),
+            asset_mint: pool.asset_mint,
+            amount,
+            timestamp: clock.unix_timestamp,
+        });
+    } else {
+        emit!(LendingDisabledEvent {
+            user: ctx.accounts.user.key(),
+            pool: pool.key(),
+            asset_mint: pool.asset_mint,
+            amount,
+            timestamp: clock.unix_timestamp,
+        });
+    }
+    
+    let collateral = user_position.collaterals()
+        .iter()
+        .find(|collateral| collateral.pool == pool.key())
+        .ok_or(OxygenError::CollateralNotFound)?;
+    
+    emit!(CollateralFlagChangedEvent {
+        user: ctx.accounts.user.key(),
+        pool: pool.key(),
+        is_collateral: collateral.is_collateral,
+        is_lending: collateral.is_lending,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    Ok(())
+}
\ No newline at end of file
@@ -1,8 +1,9 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked};
 use std::collections::HashMap;
 use crate::state::{Pool, UserPosition};
 use crate::errors::OxygenError;
+use crate::modules::oracle::OracleModule;
 use crate::events::{WithdrawEvent, LendingDisabledEvent, PoolUtilizationUpdatedEvent};
 // Import the wallet integration module
 use crate::modules::wallet_integration::WalletIntegration;
@@ -11,6 +12,7 @@ use crate::modules::wallet_integration::WalletIntegration;
 pub struct WithdrawParams {
     pub amount: u64,  // Amount to withdraw
     pub is_lending_withdrawal: bool, // Flag to indicate if this is a lending position withdrawal
+    pub withdraw_max: bool, // Ignore amount and withdraw the largest amount that keeps the position healthy
 }
 
 #[derive(Accounts)]
@@ -22,41 +24,168 @@ pub struct Withdraw<'info> {
         mut,
         seeds = [b"pool", pool.asset_mint.as_ref()],
         bump = pool.bump,
+        has_one = asset_mint,
     )]
     pub pool: Account<'info, Pool>,
     
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
     #[account(
         mut,
         constraint = user_token_account.mint == pool.asset_mint,
         constraint = user_token_account.owner == user.key(),
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"reserve", pool.key().as_ref()],
         bump,
         constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
     )]
-    pub asset_reserve: Account<'info, TokenAccount>,
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
     
     #[account(
         mut,
         seeds = [b"position", user.key().as_ref()],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key(),
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
     )]
-    pub user_position: Account<'info, UserPosition>,
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    /// CHECK: Pyth price account, validated against the pool's configured oracle
+    #[account(constraint = price_oracle.key() == pool.price_oracle @ OxygenError::InvalidOracleConfig)]
+    pub price_oracle: Option<UncheckedAccount<'info>>,
     
-    pub token_program: Program<'info, anchor_spl::token::Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    
+    // The user's other collateral and borrow pools are passed as remaining accounts
 }
 
-pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
-    let amount = params.amount;
-    require!(amount > 0, OxygenError::InvalidParameter);
+#[derive(Accounts)]
+pub struct WithdrawSol<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+        has_one = asset_mint,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
+    /// Temporary wSOL account the withdrawal is paid into, closed to unwrap it
+    #[account(
+        init,
+        payer = user,
+        token::mint = asset_mint,
+        token::authority = user,
+        seeds = [b"wsol", user.key().as_ref()],
+        bump
+    )]
+    pub wsol_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"reserve", pool.key().as_ref()],
+        bump,
+        constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
+    )]
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref()],
+        bump = user_position.load()?.bump,
+        constraint = user_position.load()?.owner == user.key(),
+    )]
+    pub user_position: AccountLoader<'info, UserPosition>,
+    
+    /// CHECK: Pyth price account, validated against the pool's configured oracle
+    #[account(constraint = price_oracle.key() == pool.price_oracle @ OxygenError::InvalidOracleConfig)]
+    pub price_oracle: Option<UncheckedAccount<'info>>,
+    
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    
+    // The user's other collateral and borrow pools are passed as remaining accounts
+}
+
+// Accounts the withdrawal itself works on, so token and native SOL withdrawals
+// can share it with a different destination account
+struct WithdrawAccounts<'a, 'info> {
+    user: &'a Signer<'info>,
+    pool: &'a mut Account<'info, Pool>,
+    asset_mint: &'a InterfaceAccount<'info, Mint>,
+    destination_token_account: &'a InterfaceAccount<'info, TokenAccount>,
+    asset_reserve: &'a InterfaceAccount<'info, TokenAccount>,
+    user_position: &'a AccountLoader<'info, UserPosition>,
+    price_oracle: Option<&'a AccountInfo<'info>>,
+    token_program: &'a Interface<'info, TokenInterface>,
+    remaining_accounts: &'a [AccountInfo<'info>],
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, Withdraw<'info>>, params: WithdrawParams) -> Result<()> {
+    let accounts = ctx.accounts;
+    
+    process_withdraw(
+        WithdrawAccounts {
+            user: &accounts.user,
+            pool: &mut accounts.pool,
+            asset_mint: &accounts.asset_mint,
+            destination_token_account: &accounts.user_token_account,
+            asset_reserve: &accounts.asset_reserve,
+            user_position: &accounts.user_position,
+            price_oracle: accounts.price_oracle.as_deref(),
+            token_program: &accounts.token_program,
+            remaining_accounts: ctx.remaining_accounts,
+        },
+        params,
+    )
+}
+
+/// Withdraw from a wrapped-SOL pool as native SOL
+/// The tokens are paid into a temporary wSOL account, which is then closed to
+/// unwrap them, so the user receives the lamports plus the account's rent back
+pub fn withdraw_sol<'info>(ctx: Context<'_, '_, '_, 'info, WithdrawSol<'info>>, params: WithdrawParams) -> Result<()> {
+    require!(ctx.accounts.pool.is_native_sol(), OxygenError::NotNativeSolPool);
+    
+    let accounts = &mut *ctx.accounts;
+    process_withdraw(
+        WithdrawAccounts {
+            user: &accounts.user,
+            pool: &mut accounts.pool,
+            asset_mint: &accounts.asset_mint,
+            destination_token_account: &accounts.wsol_account,
+            asset_reserve: &accounts.asset_reserve,
+            user_position: &accounts.user_position,
+            price_oracle: accounts.price_oracle.as_deref(),
+            token_program: &accounts.token_program,
+            remaining_accounts: ctx.remaining_accounts,
+        },
+        params,
+    )?;
     
-    let pool = &mut ctx.accounts.pool;
-    let user_position = &mut ctx.accounts.user_position;
+    // Closing a wSOL account hands its lamports, rent included, to the destination.
+    // If any step above fails the whole transaction reverts, so it never outlives the call
+    token_interface::close_account(CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.wsol_account.to_account_info(),
+            destination: ctx.accounts.user.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        },
+    ))
+}
+
+fn process_withdraw(accounts: WithdrawAccounts<'_, '_>, params: WithdrawParams) -> Result<()> {
+    let pool = &mut *accounts.pool;
+    let user_position = &mut accounts.user_position.load_mut()?;
     let clock = Clock::get()?;
     
     // NON-CUSTODIAL: Ensure the pool is immutable and admin-less
@@ -66,13 +195,11 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
     // NON-CUSTODIAL: Validate that the user is signing their own withdrawal
     WalletIntegration::validate_owner_signed(
         &user_position.owner,
-        &ctx.accounts.user
+        &accounts.user
     )?;
     
     // Check if operations are currently paused - should never happen in admin-less mode
-    if pool.operation_state_flags & 0x1 != 0 {
-        return Err(OxygenError::OperationPaused.into());
-    }
+    pool.ensure_not_paused()?;
     
     // For lending withdrawals, verify lending is enabled for this pool
     if params.is_lending_withdrawal && !pool.lending_enabled {
@@ -83,35 +210,78 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
     if clock.unix_timestamp - user_position.last_updated < 10 { // 10 second cooldown
         return Err(OxygenError::PositionModificationCooldown.into());
     }
+    user_position.record_action(clock.unix_timestamp)?;
     
     // Update pool rates
     pool.update_rates(clock.unix_timestamp)?;
     
-    // Find the collateral position
-    let mut found_index = None;
-    let mut current_deposited_amount = 0;
-    let mut position_start_timestamp = 0;
-    
-    for (i, collateral) in user_position.collaterals.iter().enumerate() {
-        if collateral.pool == pool.key() {
-            // For lending withdrawals, ensure the position is marked as lending
-            if params.is_lending_withdrawal && !collateral.is_lending {
-                continue;
-            }
-            
-            // For collateral withdrawals, ensure the position is marked as collateral
-            if !params.is_lending_withdrawal && !collateral.is_collateral {
-                continue;
-            }
-            
-            found_index = Some(i);
-            current_deposited_amount = collateral.amount_deposited;
-            position_start_timestamp = collateral.deposit_timestamp;
-            break;
+    // Pull the latest oracle price so health checks use live collateral values
+    OracleModule::refresh_pool_price(pool, accounts.price_oracle)?;
+    
+    // A price jump in this update trips the circuit breaker, so no withdrawal goes through on it
+    pool.ensure_not_paused()?;
+    
+    // Find the collateral position, which must be marked as lending for lending
+    // withdrawals and as collateral for collateral withdrawals
+    let collateral_index = user_position.collateral_index(&pool.key())
+        .filter(|&i| {
+            let collateral = &user_position.collaterals()[i];
+            if params.is_lending_withdrawal { collateral.is_lending } else { collateral.is_collateral }
+        })
+        .ok_or(OxygenError::CollateralNotFound)?;
+    
+    let current_deposited_amount = user_position.collaterals()[collateral_index].amount_deposited;
+    let position_start_timestamp = user_position.collaterals()[collateral_index].deposit_timestamp;
+    
+    // Pools without an oracle fall back to a 1:1 price ratio
+    // The withdrawn pool is always included, and the user's other collateral and
+    // borrow pools are passed as remaining accounts
+    pool.validate_risk_params()?;
+    let mut pool_data = HashMap::new();
+    pool_data.insert(pool.key(), (pool.health_price(clock.unix_timestamp)?, pool.liquidation_threshold));
+    
+    // Value borrows at their interest-inclusive debt
+    let mut borrow_rates = HashMap::new();
+    borrow_rates.insert(pool.key(), pool.cumulative_borrow_rate);
+    
+    for account_info in accounts.remaining_accounts.iter() {
+        let other_pool: Account<Pool> = Account::try_from(account_info)?;
+        if other_pool.key() == pool.key() {
+            continue;
         }
+        
+        other_pool.validate_risk_params()?;
+        pool_data.insert(
+            other_pool.key(),
+            (other_pool.health_price(clock.unix_timestamp)?, other_pool.liquidation_threshold)
+        );
+        borrow_rates.insert(other_pool.key(), other_pool.cumulative_borrow_rate);
     }
     
-    require!(found_index.is_some(), OxygenError::CollateralNotFound);
+    // Lent and collateral balances share one entry, so a lending withdrawal from a
+    // deposit that is also collateral shrinks the collateral behind the user's borrows
+    let backs_borrows = user_position.collaterals()[collateral_index].is_collateral
+        && !user_position.borrows().is_empty();
+    
+    // A missing pool would count as worthless and misstate the health factor
+    if backs_borrows {
+        user_position.require_priced_pools(&pool_data)?;
+    }
+    
+    let amount = if params.withdraw_max {
+        max_withdraw_amount(
+            user_position,
+            collateral_index,
+            params.is_lending_withdrawal,
+            &pool_data,
+            &borrow_rates,
+            pool.min_withdraw_health_factor
+        )?
+    } else {
+        params.amount
+    };
+    
+    require!(amount > 0, OxygenError::InvalidParameter);
     require!(current_deposited_amount >= amount, OxygenError::InsufficientBalance);
     
     // Check for minimum lending duration if this is a lending withdrawal
@@ -121,21 +291,19 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
         return Err(OxygenError::MinLendingDurationNotMet.into());
     }
     
-    let collateral_index = found_index.unwrap();
-    
     // Calculate how much collateral to remove (in scaled units)
-    let collateral = &mut user_position.collaterals[collateral_index];
-    
-    // Guard against divide-by-zero
-    if collateral.amount_deposited == 0 {
-        return Err(OxygenError::MathOverflow.into());
-    }
+    let collateral = &mut user_position.collaterals_mut()[collateral_index];
     
+    // Remove the scaled balance the withdrawn tokens are worth at the current lending
+    // rate, rounded up, so yield accrued on the deposit stays behind to be claimed
     let scaled_amount_to_remove = (amount as u128)
-        .checked_mul(collateral.amount_scaled)
+        .checked_mul(1_000_000_000_000) // 10^12 precision
         .ok_or(OxygenError::MathOverflow)?
-        .checked_div(collateral.amount_deposited as u128)
+        .checked_add(pool.cumulative_lending_rate - 1)
+        .ok_or(OxygenError::MathOverflow)?
+        .checked_div(pool.cumulative_lending_rate)
         .ok_or(OxygenError::MathOverflow)?;
+    let scaled_amount_to_remove = std::cmp::min(scaled_amount_to_remove, collateral.amount_scaled);
     
     // Update collateral values
     collateral.amount_deposited = collateral.amount_deposited
@@ -146,8 +314,15 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
         .checked_sub(scaled_amount_to_remove)
         .ok_or(OxygenError::MathOverflow)?;
     
+    // Yield still owed on a fully withdrawn deposit keeps it lending until it's claimed
+    let unclaimed_yield = collateral.amount_scaled
+        .checked_mul(pool.cumulative_lending_rate)
+        .ok_or(OxygenError::MathOverflow)?
+        .checked_div(1_000_000_000_000)
+        .ok_or(OxygenError::MathOverflow)?;
+    
     // If lending withdrawal, check if we need to update the is_lending flag
-    if params.is_lending_withdrawal && collateral.amount_deposited == 0 {
+    if params.is_lending_withdrawal && collateral.amount_deposited == 0 && unclaimed_yield == 0 {
         collateral.is_lending = false;
     }
     
@@ -158,38 +333,12 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
     
     // Handle removal of the collateral entry if zero balance and neither lending nor collateral
     if collateral.amount_deposited == 0 && !collateral.is_lending && !collateral.is_collateral {
-        user_position.collaterals.remove(collateral_index);
+        user_position.remove_collateral(collateral_index);
     }
     
-    // If the position has any borrows and this is a collateral withdrawal, verify the withdrawal doesn't break health factor
-    if !params.is_lending_withdrawal && !user_position.borrows.is_empty() {
-        // Create pool data map for health factor calculation
-        let mut pool_data = HashMap::new();
-        
-        // Check if we should use oracle prices
-        if pool.price_oracle != Pubkey::default() {
-            // In a real implementation, fetch the oracle price
-            // Here we're just using a placeholder implementation
-            if !verify_oracle_freshness(pool) {
-                return Err(OxygenError::StaleOracleData.into());
-            }
-            
-            // Add the pool with oracle price and liquidation threshold
-            pool_data.insert(pool.key(), (pool.last_oracle_price, pool.liquidation_threshold));
-        } else {
-            // Fallback to a 1:1 price ratio
-            pool_data.insert(pool.key(), (10000, pool.liquidation_threshold));
-        }
-        
-        // Calculate health factor with the updated collateral
-        let health_factor = user_position.calculate_health_factor(&pool_data)?;
-        
-        // Check if health factor is still above minimum threshold
-        const MIN_HEALTH_FACTOR: u64 = 10000; // 1.0 in scaled form
-        require!(
-            health_factor >= MIN_HEALTH_FACTOR,
-            OxygenError::HealthFactorTooLow
-        );
+    // If the withdrawn deposit backs any borrows, verify the withdrawal doesn't break health factor
+    if backs_borrows {
+        require_withdraw_health(user_position, &pool_data, &borrow_rates, pool.min_withdraw_health_factor)?;
     }
     
     // If this is a lending withdrawal, perform additional checks
@@ -205,7 +354,7 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
         );
         
         // Check if there are enough reserves to cover the withdrawal
-        let reserve_balance = ctx.accounts.asset_reserve.amount;
+        let reserve_balance = accounts.asset_reserve.amount;
         if reserve_balance < amount {
             return Err(OxygenError::InsufficientReserves.into());
         }
@@ -225,6 +374,10 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
         pool.total_lent = pool.total_lent
             .checked_sub(amount)
             .ok_or(OxygenError::MathOverflow)?;
+        
+        // The withdrawn funds are no longer lendable either; saturate since borrows
+        // may already have drawn the available supply below this amount
+        pool.available_lending_supply = pool.available_lending_supply.saturating_sub(amount);
     } else {
         // For regular withdrawals
         pool.total_deposits = pool.total_deposits
@@ -232,7 +385,10 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
             .ok_or(OxygenError::MathOverflow)?;
     }
     
-    // Transfer tokens from reserve to user
+    // Lending withdrawals pay a fee into the insurance fund
+    let (withdrawal_fee, amount_after_fee) = split_withdrawal_fee(pool, amount, params.is_lending_withdrawal)?;
+    
+    // Transfer tokens from the reserve to the destination account
     let pool_seeds = &[
         b"pool".as_ref(),
         pool.asset_mint.as_ref(),
@@ -251,28 +407,30 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
         &[0u8, 0u8, 0u8, 0u8] // Placeholder for actual instruction data
     )?;
     
-    let cpi_accounts = Transfer {
-        from: ctx.accounts.asset_reserve.to_account_info(),
-        to: ctx.accounts.user_token_account.to_account_info(),
-        authority: ctx.accounts.pool.to_account_info(),
+    let cpi_accounts = TransferChecked {
+        from: accounts.asset_reserve.to_account_info(),
+        mint: accounts.asset_mint.to_account_info(),
+        to: accounts.destination_token_account.to_account_info(),
+        authority: pool.to_account_info(),
     };
     
     let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.token_program.to_account_info(),
+        accounts.token_program.to_account_info(),
         cpi_accounts,
         pool_signer,
     );
     
-    token::transfer(cpi_context, amount)?;
+    token_interface::transfer_checked(cpi_context, amount_after_fee, accounts.asset_mint.decimals)?;
     
     user_position.last_updated = clock.unix_timestamp;
     
     // Emit withdraw event with appropriate flags based on the withdrawal type
     emit!(WithdrawEvent {
-        user: ctx.accounts.user.key(),
+        user: accounts.user.key(),
         pool: pool.key(),
         asset_mint: pool.asset_mint,
         amount,
+        fee: withdrawal_fee,
         from_collateral: !params.is_lending_withdrawal,
         from_lending: params.is_lending_withdrawal,
         timestamp: clock.unix_timestamp,
@@ -281,7 +439,7 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
     // If this is a lending withdrawal, also emit a lending disabled event
     if params.is_lending_withdrawal {
         emit!(LendingDisabledEvent {
-            user: ctx.accounts.user.key(),
+            user: accounts.user.key(),
             pool: pool.key(),
             asset_mint: pool.asset_mint,
             amount,
@@ -297,6 +455,8 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
         utilization_rate,
         borrow_interest_rate: pool.get_borrow_rate()?,
         lending_interest_rate: pool.get_lending_rate()?,
+        cumulative_borrow_rate: pool.cumulative_borrow_rate,
+        cumulative_lending_rate: pool.cumulative_lending_rate,
         timestamp: clock.unix_timestamp,
     });
     
@@ -310,16 +470,144 @@ pub fn handler(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
     Ok(())
 }
 
-// Helper function to verify oracle price freshness
-fn verify_oracle_freshness(pool: &Pool) -> bool {
-    if pool.price_oracle == Pubkey::default() {
-        return false;
+// Charge the withdrawal fee, if any, and return it with the amount the user receives
+fn split_withdrawal_fee(pool: &mut Pool, amount: u64, is_lending_withdrawal: bool) -> Result<(u64, u64)> {
+    let withdrawal_fee = if is_lending_withdrawal {
+        pool.charge_lending_withdrawal_fee(amount)?
+    } else {
+        0
+    };
+    
+    let amount_after_fee = amount
+        .checked_sub(withdrawal_fee)
+        .ok_or(OxygenError::MathOverflow)?;
+    
+    Ok((withdrawal_fee, amount_after_fee))
+}
+
+// Amount `withdraw_max` takes from the deposit at `collateral_index`: lent funds that
+// don't back any borrows can all go, anything else only down to the health buffer
+fn max_withdraw_amount(
+    user_position: &UserPosition,
+    collateral_index: usize,
+    is_lending_withdrawal: bool,
+    pool_data: &HashMap<Pubkey, (u64, u64)>,
+    borrow_rates: &HashMap<Pubkey, u128>,
+    min_withdraw_health_factor: u64
+) -> Result<u64> {
+    let collateral = &user_position.collaterals()[collateral_index];
+    let backs_borrows = collateral.is_collateral && !user_position.borrows().is_empty();
+    
+    if is_lending_withdrawal && !backs_borrows {
+        return Ok(collateral.amount_deposited);
     }
     
-    // In a production implementation, this would check if the oracle
-    // price update is within an acceptable time window
-    let max_oracle_staleness = 300; // 5 minutes in seconds
-    let clock = Clock::get().unwrap();
+    user_position.max_withdrawable(&collateral.pool, pool_data, borrow_rates, min_withdraw_health_factor)
+}
+
+// Withdrawals must leave the pool's buffer above the 1.0 liquidation point,
+// so a position isn't liquidatable right after withdrawing
+fn require_withdraw_health(
+    user_position: &mut UserPosition,
+    pool_data: &HashMap<Pubkey, (u64, u64)>,
+    borrow_rates: &HashMap<Pubkey, u128>,
+    min_withdraw_health_factor: u64
+) -> Result<()> {
+    // Calculate health factor with the updated collateral
+    let health_factor = user_position.calculate_health_factor_with_rates(pool_data, borrow_rates)?;
+    
+    require!(
+        health_factor >= min_withdraw_health_factor,
+        OxygenError::HealthFactorTooLow
+    );
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::position::tests::{borrow, collateral};
+    
+    fn lent_collateral(pool: Pubkey, amount: u64) -> crate::state::CollateralPosition {
+        let mut lent = collateral(pool, amount);
+        lent.is_lending = true;
+        lent
+    }
+    
+    #[test]
+    fn lending_max_without_borrows_is_whole_balance() {
+        let pool = Pubkey::new_unique();
+        let position = UserPosition::with_positions(&[lent_collateral(pool, 1000)], &[]);
+        let pool_data = HashMap::from([(pool, (10000, 8000))]);
+        
+        assert_eq!(max_withdraw_amount(&position, 0, true, &pool_data, &HashMap::new(), 11000).unwrap(), 1000);
+    }
+    
+    #[test]
+    fn lending_max_of_collateral_backing_borrows_keeps_min_health() {
+        let pool = Pubkey::new_unique();
+        let debt_pool = Pubkey::new_unique();
+        let position = UserPosition::with_positions(&[lent_collateral(pool, 1000)], &[borrow(debt_pool, 400)]);
+        let pool_data = HashMap::from([(pool, (10000, 8000)), (debt_pool, (10000, 8000))]);
+        
+        // Same limit as a collateral withdrawal: 550 left is 1.1x the 400 owed
+        assert_eq!(max_withdraw_amount(&position, 0, true, &pool_data, &HashMap::new(), 11000).unwrap(), 450);
+        assert_eq!(max_withdraw_amount(&position, 0, false, &pool_data, &HashMap::new(), 11000).unwrap(), 450);
+    }
+    
+    #[test]
+    fn lending_max_of_non_collateral_deposit_ignores_borrows() {
+        let pool = Pubkey::new_unique();
+        let debt_pool = Pubkey::new_unique();
+        let mut lent = lent_collateral(pool, 1000);
+        lent.is_collateral = false;
+        let position = UserPosition::with_positions(&[lent], &[borrow(debt_pool, 400)]);
+        let pool_data = HashMap::from([(pool, (10000, 8000)), (debt_pool, (10000, 8000))]);
+        
+        assert_eq!(max_withdraw_amount(&position, 0, true, &pool_data, &HashMap::new(), 11000).unwrap(), 1000);
+    }    
+    #[test]
+    fn withdrawal_inside_health_buffer_is_rejected() {
+        let pool = Pubkey::new_unique();
+        let debt_pool = Pubkey::new_unique();
+        let pool_data = HashMap::from([(pool, (10000, 8000)), (debt_pool, (10000, 8000))]);
+        
+        // 525 left backs 420 of weighted value against 400 owed, a 1.05 health factor
+        let mut position = UserPosition::with_positions(&[collateral(pool, 525)], &[borrow(debt_pool, 400)]);
+        
+        let result = require_withdraw_health(&mut position, &pool_data, &HashMap::new(), 11000);
+        assert_eq!(result.unwrap_err(), OxygenError::HealthFactorTooLow.into());
+        assert_eq!(position.health_factor, 10500);
+        
+        // Still healthy, so it passes without the buffer
+        assert!(require_withdraw_health(&mut position, &pool_data, &HashMap::new(), 10000).is_ok());
+    }
     
-    clock.unix_timestamp - pool.last_oracle_update < max_oracle_staleness
+    #[test]
+    fn withdrawal_leaving_exact_buffer_is_allowed() {
+        let pool = Pubkey::new_unique();
+        let debt_pool = Pubkey::new_unique();
+        let pool_data = HashMap::from([(pool, (10000, 8000)), (debt_pool, (10000, 8000))]);
+        let mut position = UserPosition::with_positions(&[collateral(pool, 550)], &[borrow(debt_pool, 400)]);
+        
+        assert!(require_withdraw_health(&mut position, &pool_data, &HashMap::new(), 11000).is_ok());
+    }
+    
+    #[test]
+    fn lending_withdrawal_fee_goes_to_insurance_fund() {
+        let mut pool = Pool { lending_withdrawal_fee: 50, insurance_fund: 1_000, ..Pool::default() };
+        
+        // 0.5% of 10_000 is kept back and credited to the fund
+        assert_eq!(split_withdrawal_fee(&mut pool, 10_000, true).unwrap(), (50, 9_950));
+        assert_eq!(pool.insurance_fund, 1_050);
+    }
+    
+    #[test]
+    fn collateral_withdrawal_pays_no_fee() {
+        let mut pool = Pool { lending_withdrawal_fee: 50, ..Pool::default() };
+        
+        assert_eq!(split_withdrawal_fee(&mut pool, 10_000, false).unwrap(), (0, 10_000));
+        assert_eq!(pool.insurance_fund, 0);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::state::Pool;
+use crate::errors::OxygenError;
+use crate::events::ProtocolFeesWithdrawnEvent;
+
+#[derive(Accounts)]
+pub struct WithdrawProtocolFees<'info> {
+    /// Anyone may trigger the withdrawal, since fees can only go to the pool's fee account
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool.asset_mint.as_ref()],
+        bump = pool.bump,
+        has_one = asset_mint,
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    pub asset_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"reserve", pool.key().as_ref()],
+        bump,
+        constraint = asset_reserve.mint == pool.asset_mint,
+        constraint = asset_reserve.key() == pool.asset_reserve,
+    )]
+    pub asset_reserve: InterfaceAccount<'info, TokenAccount>,
+    
+    /// Protocol fee account derived from the pool
+    #[account(
+        init_if_needed,
+        payer = payer,
+        token::mint = asset_mint,
+        token::authority = pool,
+        seeds = [b"protocol_fees", pool.key().as_ref()],
+        bump
+    )]
+    pub fee_receiver: InterfaceAccount<'info, TokenAccount>,
+    
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+pub fn handler(ctx: Context<WithdrawProtocolFees>) -> Result<()> {
+    let pool = &ctx.accounts.pool;
+    let clock = Clock::get()?;
+    
+    // Reject fee withdrawals while the pool is paused
+    pool.ensure_not_paused()?;
+    
+    let amount = pool.accrued_protocol_fees;
+    require!(amount > 0, OxygenError::InvalidParameter);
+    require!(
+        ctx.accounts.asset_reserve.amount >= amount,
+        OxygenError::InsufficientReserves
+    );
+    
+    let pool_seeds = &[
+        b"pool".as_ref(),
+        pool.asset_mint.as_ref(),
+        &[pool.bump],
+    ];
+    
+    let pool_signer = &[&pool_seeds[..]];
+    
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.asset_reserve.to_account_info(),
+        mint: ctx.accounts.asset_mint.to_account_info(),
+        to: ctx.accounts.fee_receiver.to_account_info(),
+        authority: ctx.accounts.pool.to_account_info(),
+    };
+    
+    let cpi_context = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        cpi_accounts,
+        pool_signer,
+    );
+    
+    token_interface::transfer_checked(cpi_context, amount, ctx.accounts.asset_mint.decimals)?;
+    
+    let pool = &mut ctx.accounts.pool;
+    pool.accrued_protocol_fees = 0;
+    
+    emit!(ProtocolFeesWithdrawnEvent {
+        pool: pool.key(),
+        asset_mint: pool.asset_mint,
+        recipient: ctx.accounts.fee_receiver.key(),
+        amount,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    msg!("Withdrew {} protocol fees from pool {}", amount, pool.key());
+    
+    Ok(())
+}
\ No newline at end of file
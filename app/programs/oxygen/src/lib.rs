@@ -20,28 +20,63 @@ pub mod oxygen {
         instructions::init_pool::handler(ctx, params)
     }
 
+    /// Create the market account that leveraged trading on a Serum market requires
+    pub fn initialize_market(ctx: Context<InitializeMarket>, params: InitializeMarketParams) -> Result<()> {
+        instructions::init_market::handler(ctx, params)
+    }
+
+    /// Create the caller's position account, required before any deposit
+    pub fn init_user_position(ctx: Context<InitializeUserPosition>) -> Result<()> {
+        instructions::init_user_position::handler(ctx)
+    }
+
     /// Deposit tokens into a lending pool
     pub fn deposit(ctx: Context<Deposit>, params: DepositParams) -> Result<()> {
         instructions::deposit::handler(ctx, params)
     }
 
+    /// Deposit native SOL into a wrapped-SOL pool
+    pub fn deposit_sol(ctx: Context<DepositSol>, params: DepositParams) -> Result<()> {
+        instructions::deposit::deposit_sol(ctx, params)
+    }
+
     /// Withdraw tokens from a lending pool
-    pub fn withdraw(ctx: Context<Withdraw>, params: WithdrawParams) -> Result<()> {
+    pub fn withdraw<'info>(ctx: Context<'_, '_, '_, 'info, Withdraw<'info>>, params: WithdrawParams) -> Result<()> {
         instructions::withdraw::handler(ctx, params)
     }
 
+    /// Withdraw from a wrapped-SOL pool as native SOL
+    pub fn withdraw_sol<'info>(ctx: Context<'_, '_, '_, 'info, WithdrawSol<'info>>, params: WithdrawParams) -> Result<()> {
+        instructions::withdraw::withdraw_sol(ctx, params)
+    }
+
+    /// Enable or disable a deposit as collateral for borrowing
+    pub fn set_collateral_status<'info>(ctx: Context<'_, '_, '_, 'info, SetCollateralStatus<'info>>, params: SetCollateralStatusParams) -> Result<()> {
+        instructions::set_collateral_status::handler(ctx, params)
+    }
+
     /// Borrow tokens from a lending pool using cross-collateralization
-    pub fn borrow(ctx: Context<Borrow>, params: BorrowParams) -> Result<()> {
+    pub fn borrow<'info>(ctx: Context<'_, '_, '_, 'info, Borrow<'info>>, params: BorrowParams) -> Result<()> {
         instructions::borrow::handler(ctx, params)
     }
 
+    /// Borrow as much as keeps the position at a target health factor
+    pub fn borrow_to_health<'info>(ctx: Context<'_, '_, '_, 'info, Borrow<'info>>, params: BorrowToHealthParams) -> Result<()> {
+        instructions::borrow::borrow_to_health(ctx, params)
+    }
+
     /// Repay borrowed tokens to a lending pool
     pub fn repay(ctx: Context<Repay>, params: RepayParams) -> Result<()> {
         instructions::repay::handler(ctx, params)
     }
 
+    /// Repay a borrow in full, including accrued interest
+    pub fn repay_all(ctx: Context<Repay>) -> Result<()> {
+        instructions::repay::repay_all(ctx)
+    }
+
     /// Open a leveraged trade position using Serum DEX
-    pub fn open_trade(ctx: Context<TradeWithLeverage>, params: TradeParams) -> Result<()> {
+    pub fn open_trade<'info>(ctx: Context<'_, '_, '_, 'info, TradeWithLeverage<'info>>, params: TradeParams) -> Result<()> {
         instructions::trade::open_trade(ctx, params)
     }
     
@@ -50,28 +85,118 @@ pub mod oxygen {
         instructions::trade::close_position(ctx, params)
     }
     
+    /// Add or remove margin on an open leveraged position
+    pub fn adjust_position_margin<'info>(ctx: Context<'_, '_, '_, 'info, CloseTradePosition<'info>>, params: AdjustPositionMarginParams) -> Result<()> {
+        instructions::trade::adjust_position_margin(ctx, params)
+    }
+    
+    /// Liquidate a single underwater leveraged position
+    pub fn liquidate_leveraged_position(ctx: Context<LiquidateLeveragedPosition>, params: LiquidateLeveragedPositionParams) -> Result<()> {
+        instructions::trade::liquidate_leveraged_position(ctx, params)
+    }
+    
     /// Monitor and liquidate positions if necessary
-    pub fn monitor_positions(ctx: Context<CloseTradePosition>, current_prices: HashMap<Pubkey, u64>) -> Result<()> {
-        instructions::trade::monitor_positions_for_liquidation(ctx, current_prices)
+    pub fn monitor_positions<'info>(ctx: Context<'_, '_, '_, 'info, CloseTradePosition<'info>>) -> Result<()> {
+        instructions::trade::monitor_positions_for_liquidation(ctx)
+    }
+    
+    /// Monitor several users' positions on one market and liquidate any that are underwater
+    pub fn monitor_positions_batch<'info>(ctx: Context<'_, '_, '_, 'info, MonitorPositionsBatch<'info>>) -> Result<()> {
+        instructions::trade::monitor_positions_batch(ctx)
     }
     
-    /// Process funding rates for open leveraged positions
-    pub fn process_funding(ctx: Context<CloseTradePosition>, funding_rates: HashMap<Pubkey, i64>) -> Result<()> {
-        instructions::trade::process_funding_rates(ctx, funding_rates)
+    /// Settle a user's funding on one market at the market's own funding rate
+    pub fn process_funding(ctx: Context<SettleFunding>) -> Result<()> {
+        instructions::trade::process_funding_rates(ctx)
     }
     
     /// Get user's open leveraged positions
     pub fn get_open_positions(ctx: Context<CloseTradePosition>) -> Result<Vec<u64>> {
         instructions::trade::get_open_positions(ctx)
     }
+    
+    /// Get details and unrealized PnL of user's open leveraged positions
+    pub fn get_position_details(ctx: Context<CloseTradePosition>, mark_prices: HashMap<Pubkey, u64>) -> Result<Vec<state::PositionView>> {
+        instructions::trade::get_position_details(ctx, mark_prices)
+    }
 
     /// Liquidate an undercollateralized position
-    pub fn liquidate(ctx: Context<Liquidate>, params: LiquidateParams) -> Result<()> {
+    pub fn liquidate<'info>(ctx: Context<'_, '_, '_, 'info, Liquidate<'info>>, params: LiquidateParams) -> Result<()> {
         instructions::liquidate::handler(ctx, params)
     }
 
+    /// Preview a liquidation's outcome without changing any state
+    pub fn simulate_liquidation<'info>(ctx: Context<'_, '_, '_, 'info, SimulateLiquidation<'info>>, params: SimulateLiquidationParams) -> Result<LiquidationSimulation> {
+        instructions::simulate_liquidation::handler(ctx, params)
+    }
+
     /// Claim yield generated from lending
     pub fn claim_yield(ctx: Context<ClaimYield>, params: ClaimYieldParams) -> Result<()> {
         instructions::claim_yield::handler(ctx, params)
     }
+
+    /// Compound accrued lending yield into the deposit without moving tokens
+    pub fn compound_yield(ctx: Context<CompoundYield>) -> Result<()> {
+        instructions::claim_yield::compound_yield(ctx)
+    }
+
+    /// Start or stop lending an existing deposit to earn yield
+    pub fn update_lending_status(ctx: Context<UpdateLendingStatus>, params: UpdateLendingStatusParams) -> Result<()> {
+        instructions::update_lending_status::handler(ctx, params)
+    }
+
+    /// Move accrued protocol borrow fees out of the reserve to the pool's fee account
+    pub fn withdraw_protocol_fees(ctx: Context<WithdrawProtocolFees>) -> Result<()> {
+        instructions::withdraw_protocol_fees::handler(ctx)
+    }
+
+    /// Top up a pool's insurance fund, which covers bad debt before lenders do
+    pub fn deposit_to_insurance_fund(ctx: Context<DepositToInsuranceFund>, params: DepositToInsuranceFundParams) -> Result<()> {
+        instructions::deposit_to_insurance_fund::handler(ctx, params)
+    }
+
+    /// Borrow and repay pool liquidity within a single transaction
+    pub fn flash_loan<'info>(ctx: Context<'_, '_, '_, 'info, FlashLoan<'info>>, params: FlashLoanParams) -> Result<()> {
+        instructions::flash_loan::handler(ctx, params)
+    }
+
+    /// Get each component of a user's health factor for debugging
+    pub fn compute_health_breakdown<'info>(ctx: Context<'_, '_, '_, 'info, ComputeHealthBreakdown<'info>>) -> Result<state::HealthBreakdown> {
+        instructions::health_breakdown::handler(ctx)
+    }
+
+    /// Read a user's health factor and liquidation status without changing state
+    pub fn get_account_health<'info>(ctx: Context<'_, '_, '_, 'info, GetAccountHealth<'info>>) -> Result<AccountHealth> {
+        instructions::get_account_health::handler(ctx)
+    }
+
+    /// Read the principal, current debt and accrued interest of each of a user's borrows
+    pub fn get_borrow_debt<'info>(ctx: Context<'_, '_, '_, 'info, GetBorrowDebt<'info>>) -> Result<Vec<BorrowDebt>> {
+        instructions::get_borrow_debt::handler(ctx)
+    }
+
+    /// Transfer the caller's entire position to a new owner
+    pub fn transfer_position(ctx: Context<TransferPosition>) -> Result<()> {
+        instructions::transfer_position::handler(ctx)
+    }
+
+    /// Convert a position created before the zero-copy layout into the current format
+    pub fn migrate_user_position(ctx: Context<MigrateUserPosition>) -> Result<()> {
+        instructions::migrate_user_position::handler(ctx)
+    }
+
+    /// Record a pool's latest oracle price, tripping its circuit breaker on a large move
+    pub fn refresh_pool_price(ctx: Context<RefreshPoolPrice>) -> Result<()> {
+        instructions::refresh_pool_price::handler(ctx)
+    }
+
+    /// Record when a user's position became liquidatable, starting its liquidation grace period
+    pub fn mark_unhealthy<'info>(ctx: Context<'_, '_, '_, 'info, MarkUnhealthy<'info>>) -> Result<()> {
+        instructions::mark_unhealthy::handler(ctx)
+    }
+
+    /// Close an underwater account's liquidatable leveraged positions, then liquidate its debt up to the close factor
+    pub fn account_liquidation<'info>(ctx: Context<'_, '_, '_, 'info, AccountLiquidation<'info>>) -> Result<()> {
+        instructions::account_liquidation::handler(ctx)
+    }
 }
\ No newline at end of file
@@ -14,7 +14,7 @@ impl CollateralManager {
     ) -> Result<u128> {
         let mut total_collateral_value = 0u128;
         
-        for collateral in &user_position.collaterals {
+        for collateral in user_position.collaterals() {
             if !collateral.is_collateral {
                 continue; // Skip non-collateral deposits
             }
@@ -24,12 +24,12 @@ impl CollateralManager {
                 // Calculate collateral value: amount * price
                 let value = (collateral.amount_deposited as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
                     
                 // Add to total
                 total_collateral_value = total_collateral_value
                     .checked_add(value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
             }
         }
         
@@ -43,7 +43,7 @@ impl CollateralManager {
     ) -> Result<u128> {
         let mut total_weighted_value = 0u128;
         
-        for collateral in &user_position.collaterals {
+        for collateral in user_position.collaterals() {
             if !collateral.is_collateral {
                 continue; // Skip non-collateral deposits
             }
@@ -53,19 +53,19 @@ impl CollateralManager {
                 // Calculate base value: amount * price
                 let value = (collateral.amount_deposited as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
                     
                 // Apply liquidation threshold to get weighted value
                 let weighted_value = value
                     .checked_mul(*liquidation_threshold as u128)
-                    .ok_or(ErrorCode::MathOverflow)?
+                    .ok_or(OxygenError::CollateralValueOverflow)?
                     .checked_div(10000) // Assuming liquidation threshold is in basis points
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
                     
                 // Add to total
                 total_weighted_value = total_weighted_value
                     .checked_add(weighted_value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
             }
         }
         
@@ -79,18 +79,18 @@ impl CollateralManager {
     ) -> Result<u128> {
         let mut total_borrow_value = 0u128;
         
-        for borrow in &user_position.borrows {
+        for borrow in user_position.borrows() {
             // Get price for this asset
             if let Some((price, _)) = pool_data.get(&borrow.pool) {
                 // Calculate borrow value: amount * price
                 let value = (borrow.amount_borrowed as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::BorrowValueOverflow)?;
                     
                 // Add to total
                 total_borrow_value = total_borrow_value
                     .checked_add(value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::BorrowValueOverflow)?;
             }
         }
         
@@ -198,4 +198,33 @@ impl CollateralManager {
         // Position is liquidatable if health factor is below threshold
         Ok(health_factor < liquidation_threshold)
     }
+    
+    /// Enable or disable a deposit as collateral
+    /// Disabling is rejected if it would leave the user's borrows below a 1.0 health factor
+    pub fn set_collateral_status(
+        user_position: &mut UserPosition,
+        pool_key: &Pubkey,
+        use_as_collateral: bool,
+        pool_data: &HashMap<Pubkey, (u64, u64)>,
+        borrow_rates: &HashMap<Pubkey, u128>
+    ) -> Result<()> {
+        let collateral_index = user_position
+            .collateral_index(pool_key)
+            .ok_or(OxygenError::CollateralNotFound)?;
+        
+        user_position.collaterals_mut()[collateral_index].is_collateral = use_as_collateral;
+        
+        // Recalculate health factor since borrowing capacity may have changed
+        let health_factor = user_position.calculate_health_factor_with_rates(pool_data, borrow_rates)?;
+        
+        if !use_as_collateral && !user_position.borrows().is_empty() {
+            const MIN_HEALTH_FACTOR: u64 = 10000; // 1.0 in scaled form
+            require!(
+                health_factor >= MIN_HEALTH_FACTOR,
+                OxygenError::HealthFactorTooLow
+            );
+        }
+        
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -1,13 +1,89 @@
 use anchor_lang::prelude::*;
-use crate::state::Pool;
+use crate::state::{Pool, InterestRateModelKind};
 use crate::errors::OxygenError;
 
 /// Module for managing interest rate models and calculations
 pub struct InterestRateModel;
 
 impl InterestRateModel {
-    /// Calculate borrow interest rate based on pool utilization
+    /// Calculate borrow interest rate based on pool utilization, following the pool's rate model
     pub fn calculate_borrow_rate(
+        pool: &Pool,
+        utilization_rate: u64
+    ) -> Result<u64> {
+        match pool.interest_rate_model {
+            InterestRateModelKind::Fixed => Ok(pool.base_rate),
+            InterestRateModelKind::TwoSlope => Self::calculate_kinked_rate(
+                utilization_rate,
+                pool.optimal_utilization,
+                pool.base_rate,
+                pool.slope1,
+                pool.slope2
+            ),
+            InterestRateModelKind::ThreeSlope => {
+                let optimal_utilization = pool.optimal_utilization;
+                let second_optimal_utilization = pool.second_optimal_utilization;
+                
+                let borrow_rate = if utilization_rate <= optimal_utilization {
+                    // Below optimal: Use slope1
+                    pool.base_rate
+                        .checked_add(Self::segment_rate(utilization_rate, 0, optimal_utilization, pool.slope1)?)
+                        .ok_or(ErrorCode::MathOverflow)?
+                } else if utilization_rate <= second_optimal_utilization {
+                    // Between the kinks: Use slope2 and add the first part
+                    let base_part = pool.base_rate.checked_add(pool.slope1).ok_or(ErrorCode::MathOverflow)?;
+                    
+                    let excess_rate = Self::segment_rate(
+                        utilization_rate,
+                        optimal_utilization,
+                        second_optimal_utilization,
+                        pool.slope2
+                    )?;
+                    
+                    base_part.checked_add(excess_rate).ok_or(ErrorCode::MathOverflow)?
+                } else {
+                    // Above the second kink: Use slope3 and add the first two parts
+                    let base_part = pool.base_rate
+                        .checked_add(pool.slope1)
+                        .ok_or(ErrorCode::MathOverflow)?
+                        .checked_add(pool.slope2)
+                        .ok_or(ErrorCode::MathOverflow)?;
+                    
+                    let excess_rate = Self::segment_rate(
+                        utilization_rate,
+                        second_optimal_utilization,
+                        10000,
+                        pool.slope3
+                    )?;
+                    
+                    base_part.checked_add(excess_rate).ok_or(ErrorCode::MathOverflow)?
+                };
+                
+                Ok(borrow_rate)
+            }
+        }
+    }
+    
+    /// Rate added by one linear segment of a kinked curve, from start to utilization_rate
+    fn segment_rate(
+        utilization_rate: u64,
+        start: u64,
+        end: u64,
+        slope: u64
+    ) -> Result<u64> {
+        let span = end.checked_sub(start).ok_or(ErrorCode::MathOverflow)?;
+        
+        Ok(utilization_rate
+            .checked_sub(start)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_mul(slope)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(span)
+            .ok_or(ErrorCode::MathOverflow)?)
+    }
+    
+    /// Two-slope curve with a single kink at optimal_utilization
+    fn calculate_kinked_rate(
         utilization_rate: u64,
         optimal_utilization: u64,
         base_rate: u64,
@@ -76,30 +152,12 @@ impl InterestRateModel {
         pool: &mut Pool,
         current_timestamp: i64
     ) -> Result<()> {
-        if pool.total_deposits == 0 || pool.last_updated == current_timestamp {
+        if pool.total_deposits == 0 || pool.last_updated >= current_timestamp {
             return Ok(());
         }
         
-        // Calculate utilization rate
-        let utilization_rate = (pool.total_borrows as u128)
-            .checked_mul(10000)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(pool.total_deposits as u128)
-            .unwrap_or(0) as u64;
-            
-        // Calculate borrow rate using standard parameters
-        // These parameters could be customized per pool in a full implementation
-        let base_rate = 200; // 2% base rate
-        let slope1 = 800;    // 8% slope up to optimal utilization
-        let slope2 = 3000;   // 30% slope beyond optimal utilization
-        
-        let borrow_rate = Self::calculate_borrow_rate(
-            utilization_rate,
-            pool.optimal_utilization,
-            base_rate,
-            slope1,
-            slope2
-        )?;
+        // Calculate borrow rate using the pool's configured parameters
+        let borrow_rate = pool.get_borrow_rate()?;
         
         // Calculate time elapsed since last update (in seconds)
         let time_elapsed = (current_timestamp - pool.last_updated) as u128;
@@ -108,25 +166,172 @@ impl InterestRateModel {
         // Formula: previous_rate * (1 + borrow_rate * time_elapsed / SECONDS_PER_YEAR)
         const SECONDS_PER_YEAR: u128 = 31536000; // 365 * 24 * 60 * 60
         
+        // borrow_rate * time_elapsed is kept unscaled until it multiplies the
+        // cumulative rate, so short intervals don't truncate to zero interest
         let borrow_rate_factor = (borrow_rate as u128)
             .checked_mul(time_elapsed)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(SECONDS_PER_YEAR)
-            .ok_or(ErrorCode::MathOverflow)?;
+            .ok_or(OxygenError::RateAccrualOverflow)?;
             
-        let borrow_rate_multipler = 10000u128
-            .checked_add(borrow_rate_factor)
-            .ok_or(ErrorCode::MathOverflow)?;
+        let rate_increase = pool.cumulative_borrow_rate
+            .checked_mul(borrow_rate_factor)
+            .ok_or(OxygenError::RateAccrualOverflow)?
+            .checked_div(SECONDS_PER_YEAR * 10000)
+            .ok_or(OxygenError::RateAccrualOverflow)?;
             
         // Apply the compound interest
-        pool.cumulative_borrow_rate = (pool.cumulative_borrow_rate)
-            .checked_mul(borrow_rate_multipler)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::MathOverflow)?;
+        pool.cumulative_borrow_rate = pool.cumulative_borrow_rate
+            .checked_add(rate_increase)
+            .ok_or(OxygenError::RateAccrualOverflow)?;
+        
+        // Lent balances grow over the same interval, at the lenders' share of the
+        // borrow rate scaled by how much of the lent supply is actually borrowed
+        let lending_rate_factor = (pool.get_lending_rate()? as u128)
+            .checked_mul(pool.get_lending_utilization_rate() as u128)
+            .ok_or(OxygenError::RateAccrualOverflow)?
+            .checked_mul(time_elapsed)
+            .ok_or(OxygenError::RateAccrualOverflow)?;
+        
+        let lending_rate_increase = pool.cumulative_lending_rate
+            .checked_mul(lending_rate_factor)
+            .ok_or(OxygenError::RateAccrualOverflow)?
+            .checked_div(SECONDS_PER_YEAR * 10000 * 10000)
+            .ok_or(OxygenError::RateAccrualOverflow)?;
+        
+        pool.cumulative_lending_rate = pool.cumulative_lending_rate
+            .checked_add(lending_rate_increase)
+            .ok_or(OxygenError::RateAccrualOverflow)?;
+        
+        // Whatever borrowers accrued over the interval beyond what lenders earned
+        // is the protocol's share, kept in the reserve as protocol fees
+        let borrow_interest = (pool.total_borrows as u128)
+            .checked_mul(borrow_rate_factor)
+            .ok_or(OxygenError::RateAccrualOverflow)?
+            .checked_div(SECONDS_PER_YEAR * 10000)
+            .ok_or(OxygenError::RateAccrualOverflow)?;
+        
+        let lender_interest = (pool.total_lent as u128)
+            .checked_mul(lending_rate_factor)
+            .ok_or(OxygenError::RateAccrualOverflow)?
+            .checked_div(SECONDS_PER_YEAR * 10000 * 10000)
+            .ok_or(OxygenError::RateAccrualOverflow)?;
+        
+        let protocol_interest = u64::try_from(borrow_interest.saturating_sub(lender_interest))
+            .map_err(|_| OxygenError::RateAccrualOverflow)?;
+        
+        pool.accrued_protocol_fees = pool.accrued_protocol_fees
+            .checked_add(protocol_interest)
+            .ok_or(OxygenError::RateAccrualOverflow)?;
             
         pool.last_updated = current_timestamp;
         
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const SECONDS_PER_YEAR: i64 = 31536000;
+    const RATE_PRECISION: u128 = 1_000_000_000_000;
+    
+    // Half of a fully lent 1,000,000 token pool is borrowed at a fixed 10% a year
+    fn half_borrowed_pool(lending_interest_share: u64) -> Pool {
+        Pool {
+            interest_rate_model: InterestRateModelKind::Fixed,
+            base_rate: 1000,
+            lending_interest_share,
+            total_deposits: 1_000_000,
+            total_borrows: 500_000,
+            total_lent: 1_000_000,
+            available_lending_supply: 500_000,
+            cumulative_borrow_rate: RATE_PRECISION,
+            cumulative_lending_rate: RATE_PRECISION,
+            last_updated: 1,
+            ..Default::default()
+        }
+    }
+    
+    #[test]
+    fn protocol_keeps_the_interest_lenders_dont_earn() {
+        let mut pool = half_borrowed_pool(5000);
+        
+        InterestRateModel::update_cumulative_rate(&mut pool, 1 + SECONDS_PER_YEAR).unwrap();
+        
+        // Borrowers accrue 50,000 over the year; lenders earn half of it on the 1,000,000 lent
+        assert_eq!(pool.cumulative_borrow_rate, RATE_PRECISION * 110 / 100);
+        assert_eq!(pool.cumulative_lending_rate, RATE_PRECISION * 1025 / 1000);
+        assert_eq!(pool.accrued_protocol_fees, 25_000);
+        assert_eq!(pool.last_updated, 1 + SECONDS_PER_YEAR);
+    }
+    
+    #[test]
+    fn protocol_keeps_all_interest_without_lenders() {
+        let mut pool = half_borrowed_pool(5000);
+        pool.total_lent = 0;
+        pool.available_lending_supply = 0;
+        
+        InterestRateModel::update_cumulative_rate(&mut pool, 1 + SECONDS_PER_YEAR).unwrap();
+        
+        assert_eq!(pool.cumulative_lending_rate, RATE_PRECISION);
+        assert_eq!(pool.accrued_protocol_fees, 50_000);
+    }
+    
+    #[test]
+    fn accrual_is_skipped_without_elapsed_time() {
+        let mut pool = half_borrowed_pool(5000);
+        
+        InterestRateModel::update_cumulative_rate(&mut pool, 1).unwrap();
+        
+        assert_eq!(pool.cumulative_borrow_rate, RATE_PRECISION);
+        assert_eq!(pool.accrued_protocol_fees, 0);
+    }
+    
+    #[test]
+    fn short_intervals_still_accrue_interest() {
+        let mut pool = half_borrowed_pool(5000);
+        
+        InterestRateModel::update_cumulative_rate(&mut pool, 2).unwrap();
+        
+        assert!(pool.cumulative_borrow_rate > RATE_PRECISION);
+    }
+    
+    #[test]
+    fn two_slope_rate_follows_the_kink() {
+        // 2% base, +8% up to 80% utilization, +50% beyond it
+        let rate = |utilization| InterestRateModel::calculate_kinked_rate(utilization, 8000, 200, 800, 5000).unwrap();
+        
+        assert_eq!(rate(0), 200);
+        assert_eq!(rate(4000), 600);
+        assert_eq!(rate(8000), 1000);
+        assert_eq!(rate(9000), 3500);
+        assert_eq!(rate(10000), 6000);
+    }
+    
+    #[test]
+    fn three_slope_rate_adds_each_segment() {
+        let pool = Pool {
+            interest_rate_model: InterestRateModelKind::ThreeSlope,
+            optimal_utilization: 6000,
+            second_optimal_utilization: 9000,
+            base_rate: 100,
+            slope1: 600,
+            slope2: 1500,
+            slope3: 10000,
+            ..Default::default()
+        };
+        let rate = |utilization| InterestRateModel::calculate_borrow_rate(&pool, utilization).unwrap();
+        
+        assert_eq!(rate(3000), 400);
+        assert_eq!(rate(6000), 700);
+        assert_eq!(rate(7500), 1450);
+        assert_eq!(rate(9000), 2200);
+        assert_eq!(rate(10000), 12200);
+    }
+    
+    #[test]
+    fn supply_rate_takes_out_reserve_factor() {
+        // 10% borrow rate at 50% utilization, 20% kept as reserves
+        assert_eq!(InterestRateModel::calculate_supply_rate(1000, 5000, 2000).unwrap(), 400);
+    }
 }
\ No newline at end of file
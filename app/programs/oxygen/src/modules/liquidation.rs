@@ -56,7 +56,7 @@ impl LiquidationEngine {
         max_liquidation_value: u64,
         pool_data: &HashMap<Pubkey, (u64, u64)>
     ) -> Result<Option<(usize, u64)>> {
-        if user_position.borrows.is_empty() {
+        if user_position.borrows().is_empty() {
             return Ok(None);
         }
         
@@ -64,7 +64,7 @@ impl LiquidationEngine {
         let mut highest_value: u64 = 0;
         
         // Find the debt position with highest value that's under the max liquidation value
-        for (i, borrow) in user_position.borrows.iter().enumerate() {
+        for (i, borrow) in user_position.borrows().iter().enumerate() {
             if let Some((price, _)) = pool_data.get(&borrow.pool) {
                 let value = (borrow.amount_borrowed as u128)
                     .checked_mul(*price as u128)
@@ -103,7 +103,7 @@ impl LiquidationEngine {
         collateral_position_idx: usize
     ) -> Result<()> {
         // Update user's debt position
-        let debt_position = &mut user_position.borrows[debt_position_idx];
+        let debt_position = &mut user_position.borrows_mut()[debt_position_idx];
         
         debt_position.amount_borrowed = debt_position.amount_borrowed
             .checked_sub(debt_amount)
@@ -111,11 +111,11 @@ impl LiquidationEngine {
             
         // Remove debt position if fully repaid
         if debt_position.amount_borrowed == 0 {
-            user_position.borrows.remove(debt_position_idx);
+            user_position.remove_borrow(debt_position_idx);
         }
         
         // Update user's collateral position
-        let collateral_position = &mut user_position.collaterals[collateral_position_idx];
+        let collateral_position = &mut user_position.collaterals_mut()[collateral_position_idx];
         
         collateral_position.amount_deposited = collateral_position.amount_deposited
             .checked_sub(collateral_amount)
@@ -123,7 +123,7 @@ impl LiquidationEngine {
             
         // Remove collateral position if fully liquidated
         if collateral_position.amount_deposited == 0 {
-            user_position.collaterals.remove(collateral_position_idx);
+            user_position.remove_collateral(collateral_position_idx);
         }
         
         // Update pool totals
@@ -138,6 +138,57 @@ impl LiquidationEngine {
         Ok(())
     }
     
+    /// Write off debt left behind once a borrower has no collateral to seize
+    /// The pool's insurance fund pays first and whatever it can't cover is
+    /// socialized across depositors. Returns (covered by insurance, socialized)
+    pub fn settle_bad_debt(debt_pool: &mut Pool, principal: u64) -> Result<(u64, u64)> {
+        // The written-off principal will never return to the reserve
+        debt_pool.total_borrows = debt_pool.total_borrows.saturating_sub(principal);
+        
+        // Insurance fund tokens already sit in the reserve, so covering the loss
+        // just hands them back to depositors
+        let covered = std::cmp::min(principal, debt_pool.insurance_fund);
+        debt_pool.insurance_fund = debt_pool.insurance_fund
+            .checked_sub(covered)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let socialized = principal
+            .checked_sub(covered)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        if socialized > 0 {
+            debt_pool.total_deposits = debt_pool.total_deposits.saturating_sub(socialized);
+            debt_pool.bad_debt = debt_pool.bad_debt
+                .checked_add(socialized)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        
+        Ok((covered, socialized))
+    }
+    
+    /// Pick the user's collateral with the highest value among the pools in `pool_data`
+    /// Seizing the largest collateral first leaves the fewest pools to touch
+    /// Amounts are compared in whole tokens, using each pool's mint decimals
+    pub fn select_best_collateral(
+        user_position: &UserPosition,
+        pool_data: &HashMap<Pubkey, (u64, u64)>,
+        decimals: &HashMap<Pubkey, u8>
+    ) -> Option<Pubkey> {
+        user_position.collaterals()
+            .iter()
+            .filter(|collateral| collateral.is_collateral && collateral.amount_deposited > 0)
+            .filter_map(|collateral| {
+                let (price, _) = pool_data.get(&collateral.pool)?;
+                let decimals = decimals.get(&collateral.pool)?;
+                let value = (collateral.amount_deposited as u128)
+                    .saturating_mul(*price as u128)
+                    / 10u128.pow(*decimals as u32);
+                Some((value, collateral.pool))
+            })
+            .max_by_key(|(value, _)| *value)
+            .map(|(_, pool)| pool)
+    }
+    
     /// Calculate the max amount that can be liquidated at once
     pub fn calculate_max_liquidation_amount(
         user_position: &UserPosition,
@@ -5,6 +5,7 @@ pub mod yield_generation;
 pub mod interest;
 pub mod liquidation;
 pub mod wallet_integration;
+pub mod oracle;
 
 pub use lending::*;
 pub use collateral::*;
@@ -12,4 +13,5 @@ pub use trading::*;
 pub use yield_generation::*;
 pub use interest::*;
 pub use liquidation::*;
-pub use wallet_integration::*;
\ No newline at end of file
+pub use wallet_integration::*;
+pub use oracle::*;
\ No newline at end of file
@@ -0,0 +1,169 @@
+use anchor_lang::prelude::*;
+use crate::state::Pool;
+use crate::errors::OxygenError;
+use crate::events::CircuitBreakerTrippedEvent;
+
+// Pyth v2 price account layout (little endian)
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_VERSION: u32 = 2;
+const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const ACCOUNT_TYPE_OFFSET: usize = 8;
+const EXPONENT_OFFSET: usize = 20;
+const PUBLISH_TIME_OFFSET: usize = 96;
+const AGGREGATE_PRICE_OFFSET: usize = 208;
+const AGGREGATE_CONFIDENCE_OFFSET: usize = 216;
+const AGGREGATE_STATUS_OFFSET: usize = 224;
+
+/// Aggregate price read from a Pyth price account, scaled to basis points (10000 = 1.0)
+pub struct OraclePrice {
+    pub price: u64,
+    pub confidence: u64,
+    pub publish_time: i64,
+}
+
+/// Module for reading external price oracles
+pub struct OracleModule;
+
+impl OracleModule {
+    /// Load a Pyth price as `(price, confidence)` in basis points,
+    /// rejecting prices published more than `max_staleness` seconds ago
+    pub fn load_pyth_price(account: &AccountInfo, max_staleness: i64) -> Result<(u64, u64)> {
+        let oracle_price = Self::parse_pyth_price(account)?;
+        
+        Self::require_fresh(&oracle_price, max_staleness)?;
+        
+        Ok((oracle_price.price, oracle_price.confidence))
+    }
+    
+    /// Refresh a pool's stored oracle price from its configured Pyth account
+    /// Pools without an oracle keep using the 1:1 fallback price
+    pub fn refresh_pool_price(pool: &mut Pool, oracle: Option<&AccountInfo>) -> Result<()> {
+        if pool.price_oracle == Pubkey::default() {
+            return Ok(());
+        }
+        
+        let oracle = oracle.ok_or(OxygenError::InvalidOracleConfig)?;
+        require!(
+            oracle.key() == pool.price_oracle,
+            OxygenError::InvalidOracleConfig
+        );
+        
+        let oracle_price = Self::parse_pyth_price(oracle)?;
+        Self::require_fresh(&oracle_price, pool.max_oracle_staleness)?;
+        Self::require_confident(oracle_price.price, oracle_price.confidence, pool.max_confidence_bps)?;
+        
+        let current_timestamp = Clock::get()?.unix_timestamp;
+        pool.clear_expired_pause(current_timestamp);
+        
+        // A price jump beyond the pool's limit pauses the pool for the cooldown. The new
+        // price is still recorded so the next move is measured from it once trading resumes
+        if pool.exceeds_price_change_limit(oracle_price.price) {
+            pool.trip_circuit_breaker(current_timestamp)?;
+            
+            emit!(CircuitBreakerTrippedEvent {
+                asset_mint: pool.asset_mint,
+                previous_price: pool.last_oracle_price,
+                new_price: oracle_price.price,
+                paused_until: pool.paused_until,
+                timestamp: current_timestamp,
+            });
+        }
+        
+        // Only newly published prices are sampled, so re-reading the same update
+        // can't weight the TWAP towards it
+        if oracle_price.publish_time > pool.last_oracle_update {
+            pool.record_price_sample(oracle_price.price);
+        }
+        
+        pool.last_oracle_price = oracle_price.price;
+        pool.last_oracle_update = oracle_price.publish_time;
+        
+        Ok(())
+    }
+    
+    /// Parse the aggregate price out of a Pyth v2 price account
+    pub fn parse_pyth_price(account: &AccountInfo) -> Result<OraclePrice> {
+        let data = account.try_borrow_data()?;
+        
+        require!(
+            u32::from_le_bytes(read_bytes(&data, MAGIC_OFFSET)?) == PYTH_MAGIC
+                && u32::from_le_bytes(read_bytes(&data, VERSION_OFFSET)?) == PYTH_VERSION
+                && u32::from_le_bytes(read_bytes(&data, ACCOUNT_TYPE_OFFSET)?) == PYTH_PRICE_ACCOUNT_TYPE,
+            OxygenError::InvalidOracleConfig
+        );
+        
+        // Only prices from an actively trading feed are meaningful
+        require!(
+            u32::from_le_bytes(read_bytes(&data, AGGREGATE_STATUS_OFFSET)?) == PYTH_STATUS_TRADING,
+            OxygenError::InvalidOracleData
+        );
+        
+        let exponent = i32::from_le_bytes(read_bytes(&data, EXPONENT_OFFSET)?);
+        let price = i64::from_le_bytes(read_bytes(&data, AGGREGATE_PRICE_OFFSET)?);
+        let confidence = u64::from_le_bytes(read_bytes(&data, AGGREGATE_CONFIDENCE_OFFSET)?);
+        let publish_time = i64::from_le_bytes(read_bytes(&data, PUBLISH_TIME_OFFSET)?);
+        
+        require!(price > 0, OxygenError::InvalidOracleData);
+        
+        Ok(OraclePrice {
+            price: scale_to_basis_points(price as u64, exponent)?,
+            confidence: scale_to_basis_points(confidence, exponent)?,
+            publish_time,
+        })
+    }
+    
+    /// Reject prices whose confidence interval is too wide relative to the price
+    pub fn require_confident(price: u64, confidence: u64, max_confidence_bps: u64) -> Result<()> {
+        let confidence_bps = (confidence as u128)
+            .checked_mul(10000)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(price as u128)
+            .ok_or(OxygenError::InvalidOracleData)?;
+        
+        require!(
+            confidence_bps <= max_confidence_bps as u128,
+            OxygenError::InvalidOracleData
+        );
+        
+        Ok(())
+    }
+    
+    fn require_fresh(oracle_price: &OraclePrice, max_staleness: i64) -> Result<()> {
+        let clock = Clock::get()?;
+        
+        require!(
+            clock.unix_timestamp.saturating_sub(oracle_price.publish_time) <= max_staleness,
+            OxygenError::StaleOracleData
+        );
+        
+        Ok(())
+    }
+}
+
+// Helper function to read a fixed-size field out of raw account data
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(OxygenError::InvalidOracleConfig.into())
+}
+
+// Helper function to convert `value * 10^exponent` into basis points
+fn scale_to_basis_points(value: u64, exponent: i32) -> Result<u64> {
+    let scale_exponent = exponent
+        .checked_add(4) // 10000 = 1.0
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    let factor = 10u64
+        .checked_pow(scale_exponent.unsigned_abs())
+        .ok_or(OxygenError::InvalidOracleData)?;
+    
+    if scale_exponent >= 0 {
+        Ok(value.checked_mul(factor).ok_or(ErrorCode::MathOverflow)?)
+    } else {
+        Ok(value / factor)
+    }
+}
\ No newline at end of file
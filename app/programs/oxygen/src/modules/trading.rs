@@ -1,14 +1,31 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Transfer};
-use crate::state::{MarketInfo, UserPosition, Pool, LeveragedPosition};
+use anchor_lang::system_program;
+use crate::state::{MarketInfo, UserPosition, Pool, LeveragedPosition, OpenOrdersAccount, MarginMode, ClosedPosition};
 use crate::errors::OxygenError;
-use crate::instructions::{OrderSide, OrderType};
+use crate::events::{PositionCreatedEvent, PositionLiquidatedEvent, FundingPaidEvent, FundingLiquidationEvent};
+use crate::instructions::{OrderSide, OrderType, TradeWithLeverage};
+use anchor_spl::dex::{self, InitOpenOrders, NewOrderV3};
+use anchor_spl::dex::serum_dex::instruction::SelfTradeBehavior;
+use anchor_spl::dex::serum_dex::matching::{OrderType as SerumOrderType, Side};
+use anchor_spl::dex::serum_dex::fees::FeeTier;
+use anchor_spl::dex::serum_dex::state::{Market, OpenOrders};
 use std::collections::HashMap;
+use std::num::NonZeroU64;
+
+/// Size of a Serum open orders account: 5 bytes of head padding, the
+/// `OpenOrders` struct and 7 bytes of tail padding
+const OPEN_ORDERS_ACCOUNT_SIZE: usize = 12 + std::mem::size_of::<OpenOrders>();
+
+/// Max number of resting orders a single Serum order may match against
+const SERUM_MATCH_LIMIT: u16 = 65535;
 
 /// Module for handling trading operations with Serum DEX
 pub struct TradingModule;
 
 impl TradingModule {
+    /// Min health factor with leveraged positions, higher than regular lending (1.2 in basis points)
+    pub const MIN_LEVERAGE_HEALTH_FACTOR: u64 = 12000;
+    
     /// Validate if a trade can be executed with given leverage
     pub fn validate_leveraged_trade(
         user_position: &UserPosition,
@@ -26,6 +43,9 @@ impl TradingModule {
             OxygenError::LeverageExceedsMaximum
         );
         
+        // Margin math is only sound if the market and the pool price the base asset alike
+        Self::validate_oracle_consistency(market_info, base_pool)?;
+        
         // Calculate position value
         let position_value = (size as u128)
             .checked_mul(price as u128)
@@ -36,7 +56,16 @@ impl TradingModule {
             .checked_mul(10000) // Base scale factor
             .ok_or(ErrorCode::MathOverflow)?
             .checked_div(leverage as u128)
-            .ok_or(ErrorCode::MathOverflow)? as u64;
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let required_margin = u64::try_from(required_margin)
+            .map_err(|_| ErrorCode::MathOverflow)?;
+            
+        // Positions with near-zero margin cost more to monitor and liquidate than they're worth
+        require!(
+            required_margin >= market_info.min_margin,
+            OxygenError::InsufficientCollateral
+        );
             
         // Check if user has enough collateral to support this position
         let collateral_value = Self::calculate_user_available_collateral(
@@ -50,8 +79,6 @@ impl TradingModule {
         );
         
         // Additional checks for liquidation risk
-        const MIN_LEVERAGE_HEALTH_FACTOR: u64 = 12000; // 1.2 in basis points, higher than regular lending
-        
         // Simulate health factor with this position
         let health_factor = Self::simulate_position_health_factor(
             user_position,
@@ -61,13 +88,74 @@ impl TradingModule {
         )?;
         
         require!(
-            health_factor >= MIN_LEVERAGE_HEALTH_FACTOR,
+            health_factor >= Self::MIN_LEVERAGE_HEALTH_FACTOR,
             OxygenError::HealthFactorTooLow
         );
         
         Ok(())
     }
     
+    /// Validate that the market oracle and the base pool oracle agree on the base asset price
+    pub fn validate_oracle_consistency(market_info: &MarketInfo, base_pool: &Pool) -> Result<()> {
+        // Nothing to compare unless both sides price the base asset from an oracle
+        if market_info.oracle == Pubkey::default() || base_pool.price_oracle == Pubkey::default() {
+            return Ok(());
+        }
+        
+        require!(
+            market_info.last_oracle_price > 0 && base_pool.last_oracle_price > 0,
+            OxygenError::InvalidOracleData
+        );
+        
+        Self::require_within_deviation(
+            market_info.last_oracle_price,
+            base_pool.last_oracle_price,
+            market_info.max_oracle_deviation
+        )
+    }
+    
+    /// Oracle price a market's positions are opened, closed and liquidated at
+    /// This is the base pool's stored oracle price, rejected once stale, or its TWAP
+    /// where the pool enables one; the confidence interval was checked when it was
+    /// recorded. Caller-supplied prices are never used to realize PnL
+    pub fn mark_price(base_pool: &Pool, current_timestamp: i64) -> Result<u64> {
+        require!(
+            base_pool.price_oracle != Pubkey::default(),
+            OxygenError::InvalidOracleConfig
+        );
+        
+        let price = base_pool.liquidation_price(current_timestamp)?;
+        require!(price > 0, OxygenError::InvalidOracleData);
+        
+        Ok(price)
+    }
+    
+    /// Reject a price that deviates from a reference price by more than `max_deviation` (bps)
+    pub fn require_within_deviation(price: u64, reference_price: u64, max_deviation: u64) -> Result<()> {
+        let price = price as u128;
+        let reference_price = reference_price as u128;
+        
+        // Deviation relative to the reference price, in basis points
+        let price_difference = if price > reference_price {
+            price - reference_price
+        } else {
+            reference_price - price
+        };
+        
+        let deviation = price_difference
+            .checked_mul(10000)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(reference_price)
+            .ok_or(OxygenError::InvalidOracleData)?;
+        
+        require!(
+            deviation <= max_deviation as u128,
+            OxygenError::InvalidOracleData
+        );
+        
+        Ok(())
+    }
+    
     /// Calculate user's available collateral for trading
     pub fn calculate_user_available_collateral(
         user_position: &UserPosition,
@@ -75,7 +163,7 @@ impl TradingModule {
     ) -> Result<u128> {
         let mut total_available = 0u128;
         
-        for collateral in &user_position.collaterals {
+        for collateral in user_position.collaterals() {
             if !collateral.is_collateral {
                 continue;
             }
@@ -83,32 +171,32 @@ impl TradingModule {
             if let Some((price, _)) = pool_data.get(&collateral.pool) {
                 let value = (collateral.amount_deposited as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
                     
                 total_available = total_available
                     .checked_add(value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
             }
         }
         
         // Subtract any amounts already being used as collateral for loans
         let mut borrowed_value = 0u128;
         
-        for borrow in &user_position.borrows {
+        for borrow in user_position.borrows() {
             if let Some((price, _)) = pool_data.get(&borrow.pool) {
                 let value = (borrow.amount_borrowed as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::BorrowValueOverflow)?;
                     
                 borrowed_value = borrowed_value
                     .checked_add(value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::BorrowValueOverflow)?;
             }
         }
         
         // Also subtract margin already committed to leveraged positions
         let mut leveraged_margin_used = 0u128;
-        for position in &user_position.leveraged_positions {
+        for position in user_position.leveraged_positions() {
             leveraged_margin_used = leveraged_margin_used
                 .checked_add(position.margin_used as u128)
                 .ok_or(ErrorCode::MathOverflow)?;
@@ -128,10 +216,14 @@ impl TradingModule {
             .checked_sub(total_used)
             .ok_or(ErrorCode::MathOverflow)?;
             
-        let trading_available = excess_collateral
-            .checked_mul(80)
+        // Divide before multiplying so a huge aggregate value can't overflow,
+        // then add back the remainder's share so the result stays exact
+        const TRADING_MARGIN_PERCENT: u128 = 80;
+        
+        let trading_available = (excess_collateral / 100)
+            .checked_mul(TRADING_MARGIN_PERCENT)
             .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(100)
+            .checked_add((excess_collateral % 100) * TRADING_MARGIN_PERCENT / 100)
             .ok_or(ErrorCode::MathOverflow)?;
             
         Ok(trading_available)
@@ -147,7 +239,7 @@ impl TradingModule {
         // Calculate current weighted collateral value
         let mut weighted_collateral_value = 0u128;
         
-        for collateral in &user_position.collaterals {
+        for collateral in user_position.collaterals() {
             if !collateral.is_collateral {
                 continue;
             }
@@ -155,42 +247,42 @@ impl TradingModule {
             if let Some((price, liquidation_threshold)) = pool_data.get(&collateral.pool) {
                 let value = (collateral.amount_deposited as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
                     
                 let weighted_value = value
                     .checked_mul(*liquidation_threshold as u128)
-                    .ok_or(ErrorCode::MathOverflow)?
+                    .ok_or(OxygenError::CollateralValueOverflow)?
                     .checked_div(10000)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
                     
                 weighted_collateral_value = weighted_collateral_value
                     .checked_add(weighted_value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
             }
         }
         
         // Calculate current borrowed value
         let mut borrowed_value = 0u128;
         
-        for borrow in &user_position.borrows {
+        for borrow in user_position.borrows() {
             if let Some((price, _)) = pool_data.get(&borrow.pool) {
                 let value = (borrow.amount_borrowed as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::BorrowValueOverflow)?;
                     
                 borrowed_value = borrowed_value
                     .checked_add(value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::BorrowValueOverflow)?;
             }
         }
         
         // Include existing leveraged positions risk
-        for position in &user_position.leveraged_positions {
+        for position in user_position.leveraged_positions() {
             borrowed_value = borrowed_value
                 .checked_add(position.position_value as u128)
-                .ok_or(ErrorCode::MathOverflow)?
+                .ok_or(OxygenError::BorrowValueOverflow)?
                 .checked_sub(position.margin_used as u128) // Margin is already in collateral
-                .ok_or(ErrorCode::MathOverflow)?;
+                .ok_or(OxygenError::BorrowValueOverflow)?;
         }
         
         // Adjust collateral for margin used
@@ -216,14 +308,17 @@ impl TradingModule {
             .checked_mul(10000)
             .ok_or(ErrorCode::MathOverflow)?
             .checked_div(total_risk_value)
-            .ok_or(ErrorCode::MathOverflow)? as u64;
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let health_factor = u64::try_from(health_factor)
+            .map_err(|_| ErrorCode::MathOverflow)?;
             
         Ok(health_factor)
     }
     
     /// Lock margin from user's collateral for a leveraged trade
     pub fn lock_margin_from_collateral<'a>(
-        user_position: &mut Account<'a, UserPosition>,
+        user_position: &mut UserPosition,
         required_margin: u64,
         pool_data: &HashMap<Pubkey, (u64, u64)>
     ) -> Result<()> {
@@ -250,73 +345,173 @@ impl TradingModule {
         Ok(())
     }
     
+    /// Release margin previously locked for a leveraged trade
+    pub fn release_margin(user_position: &mut UserPosition, margin: u64) {
+        // Saturate so positions opened before margin was tracked can't underflow
+        user_position.locked_trading_margin = user_position.locked_trading_margin
+            .saturating_sub(margin);
+        
+        msg!("Released {} margin from leveraged trading", margin);
+    }
+    
+    /// Take margin lost on a liquidated position out of the user's quote collateral
+    /// The tokens stay in the quote reserve but no longer belong to the user, so
+    /// the pool's deposits shrink with them. Returns the amount actually taken,
+    /// which is capped by what the user has deposited
+    pub fn forfeit_margin<'a>(
+        user_position: &mut UserPosition,
+        quote_pool: &mut Account<'a, Pool>,
+        margin_lost: u64
+    ) -> Result<u64> {
+        if margin_lost == 0 {
+            return Ok(0);
+        }
+        
+        let collateral_index = user_position
+            .collateral_index(&quote_pool.key())
+            .ok_or(OxygenError::CollateralNotFound)?;
+            
+        let collateral = &mut user_position.collaterals_mut()[collateral_index];
+        let forfeited = std::cmp::min(margin_lost, collateral.amount_deposited);
+        
+        // Scaled balance shrinks in proportion to the deposit
+        let scaled_to_remove = if forfeited == collateral.amount_deposited {
+            collateral.amount_scaled
+        } else {
+            (forfeited as u128)
+                .checked_mul(collateral.amount_scaled)
+                .ok_or(ErrorCode::MathOverflow)?
+                .checked_div(collateral.amount_deposited as u128)
+                .ok_or(ErrorCode::MathOverflow)?
+        };
+        
+        collateral.amount_deposited -= forfeited;
+        collateral.amount_scaled = collateral.amount_scaled.saturating_sub(scaled_to_remove);
+        
+        quote_pool.total_deposits = quote_pool.total_deposits.saturating_sub(forfeited);
+        
+        Ok(forfeited)
+    }
+    
+    /// Credit realized profit to the user's quote collateral
+    /// The profit is paid out of the quote reserve, so the pool's deposits grow with it.
+    /// Callers must only credit tokens already taken from other users' collateral, such
+    /// as collected trading losses or paid-in funding, so no deposit claim is minted
+    pub fn credit_profit<'a>(
+        user_position: &mut UserPosition,
+        quote_pool: &mut Account<'a, Pool>,
+        profit: u64
+    ) -> Result<()> {
+        if profit == 0 {
+            return Ok(());
+        }
+        
+        let collateral_index = user_position
+            .collateral_index(&quote_pool.key())
+            .ok_or(OxygenError::CollateralNotFound)?;
+            
+        let scaled_profit = quote_pool.deposit_to_scaled(profit)?;
+        let collateral = &mut user_position.collaterals_mut()[collateral_index];
+        
+        collateral.amount_deposited = collateral.amount_deposited
+            .checked_add(profit)
+            .ok_or(ErrorCode::MathOverflow)?;
+        collateral.amount_scaled = collateral.amount_scaled
+            .checked_add(scaled_profit)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        quote_pool.total_deposits = quote_pool.total_deposits
+            .checked_add(profit)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        Ok(())
+    }
+    
     /// Place an order on Serum DEX
-    pub fn place_serum_dex_order<'a, 'info>(
-        ctx: &Context<'_, '_, '_, 'info>,
-        market_info: &Account<'a, MarketInfo>,
+    /// Size and price are forwarded to the DEX as base and quote lots
+    pub fn place_serum_dex_order<'info>(
+        ctx: &Context<'_, '_, '_, 'info, TradeWithLeverage<'info>>,
         side: OrderSide,
         order_type: OrderType,
         size: u64,
         price: u64,
         client_id: u64
     ) -> Result<()> {
-        // Convert our OrderSide to Serum OrderSide
-        let serum_side = match side {
+        // Convert our OrderSide to Serum OrderSide, and make sure the payer
+        // holds the asset the order spends
+        let market_info = &ctx.accounts.market_info;
+        let (serum_side, payer_mint) = match side {
             OrderSide::Buy => {
                 msg!("Placing BUY order on Serum DEX");
-                // serum_dex::matching::Side::Bid
-                0 // Using 0 to represent Bid since we don't have direct Serum types
+                (Side::Bid, market_info.quote_mint)
             },
             OrderSide::Sell => {
                 msg!("Placing SELL order on Serum DEX");
-                // serum_dex::matching::Side::Ask
-                1 // Using 1 to represent Ask since we don't have direct Serum types
+                (Side::Ask, market_info.asset_mint)
             }
         };
         
+        require!(
+            ctx.accounts.order_payer_token_account.mint == payer_mint,
+            OxygenError::SettlementPoolMismatch
+        );
+        
         // Convert our OrderType to Serum OrderType
         let serum_order_type = match order_type {
             OrderType::Limit => {
                 msg!("Order type: LIMIT at price {}", price);
-                // serum_dex::matching::OrderType::Limit
-                0 // Using 0 to represent Limit order
+                SerumOrderType::Limit
             },
             OrderType::Market => {
                 msg!("Order type: MARKET");
-                // serum_dex::matching::OrderType::ImmediateOrCancel
-                1 // Using 1 to represent IoC (market) order
+                // Market orders take liquidity up to the given price and never rest on the book
+                SerumOrderType::ImmediateOrCancel
             }
         };
         
-        // For a real implementation, we would:
-        // 1. Get all required Serum DEX accounts from ctx
-        // 2. Create a CPI call to the Serum DEX program
-        // 3. Pass all required accounts and parameters
-
-        // Example of what the actual code would look like:
-        // let serum_accounts = SerumDEXAccounts {
-        //     market: ctx.accounts.serum_market.to_account_info(),
-        //     open_orders: ctx.accounts.open_orders.to_account_info(),
-        //     request_queue: ctx.accounts.serum_request_queue.to_account_info(),
-        //     event_queue: ctx.accounts.serum_event_queue.to_account_info(),
-        //     bids: ctx.accounts.serum_bids.to_account_info(),
-        //     asks: ctx.accounts.serum_asks.to_account_info(),
-        //     coin_vault: ctx.accounts.serum_coin_vault.to_account_info(),
-        //     pc_vault: ctx.accounts.serum_pc_vault.to_account_info(),
-        //     // other required accounts...
-        // };
-        //
-        // serum_dex::new_order(
-        //     CpiContext::new(
-        //         ctx.accounts.dex_program.to_account_info(),
-        //         serum_accounts
-        //     ),
-        //     serum_side,
-        //     price,
-        //     size,
-        //     serum_order_type,
-        //     client_id
-        // )?;
+        // Serum rejects zero amounts, so surface that as a parameter error first
+        let limit_price = NonZeroU64::new(price).ok_or(OxygenError::InvalidParameter)?;
+        let max_coin_qty = NonZeroU64::new(size).ok_or(OxygenError::InvalidParameter)?;
+        
+        // The quote cap is in native units and must leave room for the taker fee,
+        // charged at the base tier since that is the highest the DEX applies
+        let serum_market = ctx.accounts.serum_market.to_account_info();
+        let pc_lot_size = Market::load(&serum_market, &ctx.accounts.dex_program.key(), false)
+            .map_err(|_| OxygenError::InvalidSerumMarket)?
+            .pc_lot_size;
+        let max_native_pc_qty = NonZeroU64::new(
+            Self::native_pc_qty_with_taker_fee(size, price, pc_lot_size)?
+        ).ok_or(OxygenError::InvalidParameter)?;
+        
+        let serum_accounts = NewOrderV3 {
+            market: ctx.accounts.serum_market.to_account_info(),
+            open_orders: ctx.accounts.open_orders.to_account_info(),
+            request_queue: ctx.accounts.serum_request_queue.to_account_info(),
+            event_queue: ctx.accounts.serum_event_queue.to_account_info(),
+            market_bids: ctx.accounts.serum_bids.to_account_info(),
+            market_asks: ctx.accounts.serum_asks.to_account_info(),
+            order_payer_token_account: ctx.accounts.order_payer_token_account.to_account_info(),
+            open_orders_authority: ctx.accounts.user.to_account_info(),
+            coin_vault: ctx.accounts.serum_coin_vault.to_account_info(),
+            pc_vault: ctx.accounts.serum_pc_vault.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            rent: ctx.accounts.rent.to_account_info(),
+        };
+        
+        dex::new_order_v3(
+            CpiContext::new(
+                ctx.accounts.dex_program.to_account_info(),
+                serum_accounts
+            ),
+            serum_side,
+            limit_price,
+            max_coin_qty,
+            max_native_pc_qty,
+            SelfTradeBehavior::DecrementTake,
+            serum_order_type,
+            client_id,
+            SERUM_MATCH_LIMIT
+        )?;
 
         msg!(
             "Order placed on Serum DEX: Market={}, Size={}, Price={}, ClientID={}",
@@ -329,6 +524,20 @@ impl TradingModule {
         Ok(())
     }
 
+    /// Native quote amount a bid of `size` base lots at `price` quote lots per
+    /// base lot may spend, including the base tier taker fee
+    pub fn native_pc_qty_with_taker_fee(size: u64, price: u64, pc_lot_size: u64) -> Result<u64> {
+        let native_pc_qty = size
+            .checked_mul(price)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_mul(pc_lot_size)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        native_pc_qty
+            .checked_add(FeeTier::Base.taker_fee(native_pc_qty))
+            .ok_or(ErrorCode::MathOverflow.into())
+    }
+    
     /// Set up monitoring for a position's health
     pub fn setup_position_monitoring<'a>(
         position_id: u64,
@@ -366,22 +575,60 @@ impl TradingModule {
     pub fn create_order<'a, 'info>(
         user: &Pubkey,
         market: &Pubkey,
-        market_info: &Account<'a, MarketInfo>,
+        market_info: &mut Account<'a, MarketInfo>,
         base_pool: &Account<'a, Pool>,
-        quote_pool: &Account<'a, Pool>,
-        user_position: &mut Account<'a, UserPosition>,
+        quote_pool: &mut Account<'a, Pool>,
+        user_position: &mut UserPosition,
         side: OrderSide,
         order_type: OrderType,
         size: u64,
         price: u64,
         leverage: u64,
+        stop_loss_price: u64,
+        take_profit_price: u64,
+        margin_mode: MarginMode,
+        reduce_only: bool,
         client_id: u64,
         pool_data: &HashMap<Pubkey, (u64, u64)>,
     ) -> Result<u64> {
+        // Leverage is used as a divisor below, so reject anything under 1x before doing any work
+        require!(leverage >= 10000, OxygenError::InvalidParameter);
+        
+        // Reduce-only orders net against opposing positions instead of opening a new one
+        if reduce_only {
+            return Self::reduce_opposing_positions(
+                user_position,
+                market_info,
+                quote_pool,
+                side,
+                size,
+                price,
+                pool_data
+            );
+        }
+        
+        // The position account only has room for a fixed number of leveraged positions
+        require!(
+            user_position.leveraged_positions().len() < UserPosition::MAX_LEVERAGED_POSITIONS,
+            OxygenError::MaxPositionsReached
+        );
+        
+        // Exit triggers must sit on the right side of the entry price
+        Self::validate_exit_triggers(side, price, stop_loss_price, take_profit_price)?;
+        
         // Calculate position value and required margin
         let position_value = (size as u128)
             .checked_mul(price as u128)
-            .ok_or(ErrorCode::MathOverflow)? as u64;
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let position_value = u64::try_from(position_value)
+            .map_err(|_| ErrorCode::MathOverflow)?;
+        
+        // Dust positions cost keepers more to liquidate than they are worth
+        require!(
+            position_value >= market_info.min_position_value,
+            OxygenError::PositionTooSmall
+        );
             
         let required_margin = position_value
             .checked_mul(10000) // Base scale factor
@@ -401,6 +648,9 @@ impl TradingModule {
             pool_data
         )?;
 
+        // Count the position towards the market's open interest on its side
+        market_info.increase_open_interest(side, position_value)?;
+
         // Generate a position ID
         let position_id = Self::generate_position_id(user_position)?;
         
@@ -420,6 +670,7 @@ impl TradingModule {
             entry_price: price,
             leverage,
             margin_used: required_margin,
+            margin_mode,
             position_value,
             timestamp: Clock::get()?.unix_timestamp,
             status: crate::state::PositionStatus::Open,
@@ -429,11 +680,15 @@ impl TradingModule {
                 leverage, 
                 market_info.maintenance_margin_ratio
             )?,
+            stop_loss_price,
+            take_profit_price,
             client_id,
+            // Funding only accrues from the moment the position opens
+            funding_index: market_info.cumulative_funding_index,
         };
         
         // Add the position to the user's account
-        user_position.leveraged_positions.push(new_position);
+        user_position.add_leveraged_position(new_position)?;
         
         // 3. Set up monitoring for position health
         Self::setup_position_monitoring(
@@ -458,19 +713,24 @@ impl TradingModule {
     }
     
     /// Close an existing leveraged position
+    /// A `close_size` below the position's size closes only that part, keeping the rest open;
+    /// 0 closes the whole position
     pub fn close_position<'a>(
-        user_position: &mut Account<'a, UserPosition>,
+        user_position: &mut UserPosition,
+        market_info: &mut Account<'a, MarketInfo>,
+        quote_pool: &mut Account<'a, Pool>,
         position_id: u64,
+        close_size: u64,
         execution_price: u64,
         pool_data: &HashMap<Pubkey, (u64, u64)>
     ) -> Result<()> {
         // Find the position with the given ID
-        let position_index = user_position.leveraged_positions
+        let position_index = user_position.leveraged_positions()
             .iter()
             .position(|p| p.id == position_id)
             .ok_or(OxygenError::PositionNotFound)?;
             
-        let position = &mut user_position.leveraged_positions[position_index];
+        let position = &mut user_position.leveraged_positions_mut()[position_index];
         
         // Ensure position is not already closed
         require!(
@@ -478,55 +738,262 @@ impl TradingModule {
             OxygenError::PositionAlreadyClosed
         );
         
-        // Calculate PnL
+        // Open interest is tracked per market, so only this market's positions can be settled here
+        require!(
+            position.market == market_info.serum_market,
+            OxygenError::InvalidSerumMarket
+        );
+        
+        let is_full_close = close_size == 0 || close_size >= position.size;
+        let closed_size = if is_full_close { position.size } else { close_size };
+        
+        // Calculate PnL on the closed part only
         let (pnl, is_profit) = Self::calculate_pnl(
             position.side,
             position.entry_price,
             execution_price,
-            position.size,
-            position.leverage
+            closed_size
         )?;
         
-        // Update position status
-        position.status = crate::state::PositionStatus::Closed;
+        // Value and margin are released in proportion to the closed size
+        let (closed_value, closed_margin) = if is_full_close {
+            (position.position_value, position.margin_used)
+        } else {
+            (
+                Self::pro_rata(position.position_value, closed_size, position.size)?,
+                Self::pro_rata(position.margin_used, closed_size, position.size)?,
+            )
+        };
         
-        // In a real implementation, we would:
-        // 1. Return the margin to the user's available collateral
-        // 2. Apply the PnL to the user's balance
-        // 3. Close the position on Serum DEX
+        if is_full_close {
+            position.status = crate::state::PositionStatus::Closed;
+        } else {
+            position.size -= closed_size;
+            position.position_value -= closed_value;
+            position.margin_used -= closed_margin;
+            
+            // Size and margin shrink together, so the remainder keeps its leverage
+            position.liquidation_price = Self::calculate_liquidation_price(
+                position.side,
+                position.entry_price,
+                position.leverage,
+                market_info.maintenance_margin_ratio
+            )?;
+        }
+        
+        // The closed part no longer counts towards the market's open interest
+        market_info.decrease_open_interest(position.side, closed_value)?;
+        
+        // Return the closed part's margin to the user's available collateral
+        Self::release_margin(user_position, closed_margin);
+        
+        // PnL is denominated in the quote asset, so it settles against the user's quote collateral
+        let realized_pnl = if is_profit {
+            i64::try_from(pnl).map_err(|_| OxygenError::PnlOverflow)?
+        } else {
+            -i64::try_from(pnl).map_err(|_| OxygenError::PnlOverflow)?
+        };
+        let settled = Self::apply_realized_pnl(user_position, realized_pnl, market_info, quote_pool)?;
+        
+        // Profit is capped by the losses the market has collected, so record what was paid
+        let realized_pnl = if is_profit {
+            i64::try_from(settled).map_err(|_| OxygenError::PnlOverflow)?
+        } else {
+            realized_pnl
+        };
+        
+        // In a real implementation, we would also close the position on Serum DEX
         
         // Update user's position health factor after closing
         let _ = user_position.calculate_health_factor(pool_data)?;
         
         msg!(
-            "Leveraged position closed: ID={}, PnL={}{}, Exit Price={}",
+            "Leveraged position closed: ID={}, Size={}, PnL={}{}, Exit Price={}",
             position_id,
+            closed_size,
             if is_profit { "+" } else { "-" },
             pnl,
             execution_price
         );
         
-        // In a full implementation, we might want to keep closed positions for history
-        // but for now we'll just remove it
-        user_position.leveraged_positions.remove(position_index);
+        // Keep a record of the fully closed position before removing it
+        if is_full_close {
+            let entry = ClosedPosition::from_position(
+                &user_position.leveraged_positions()[position_index],
+                execution_price,
+                realized_pnl,
+                crate::state::PositionStatus::Closed,
+                Clock::get()?.unix_timestamp
+            );
+            user_position.record_closed_position(entry);
+            user_position.remove_leveraged_position(position_index);
+        }
+        
+        Ok(())
+    }
+    
+    // Helper function to take `part / whole` of an amount
+    fn pro_rata(amount: u64, part: u64, whole: u64) -> Result<u64> {
+        let share = (amount as u128)
+            .checked_mul(part as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(whole as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        u64::try_from(share).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+    
+    /// Add margin to an open leveraged position, lowering its leverage and
+    /// moving its liquidation price further from the mark
+    pub fn add_position_margin<'a>(
+        user_position: &mut UserPosition,
+        market_info: &MarketInfo,
+        position_id: u64,
+        amount: u64,
+        pool_data: &HashMap<Pubkey, (u64, u64)>
+    ) -> Result<()> {
+        require!(amount > 0, OxygenError::InvalidParameter);
+        
+        let position_index = Self::find_open_position(user_position, market_info, position_id)?;
+        
+        // Margin beyond the position's value would take leverage under 1x
+        let position = &user_position.leveraged_positions()[position_index];
+        let new_margin = position.margin_used
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(new_margin <= position.position_value, OxygenError::InvalidParameter);
+        
+        // The extra margin must come out of the user's free collateral
+        Self::lock_margin_from_collateral(user_position, amount, pool_data)?;
+        
+        Self::set_position_margin(user_position, market_info, position_index, new_margin)?;
+        
+        Ok(())
+    }
+    
+    /// Remove excess margin from an open leveraged position
+    /// The position must stay within the market's leverage limits and the
+    /// account must stay above the leveraged health factor minimum
+    pub fn remove_position_margin<'a>(
+        user_position: &mut UserPosition,
+        market_info: &MarketInfo,
+        position_id: u64,
+        amount: u64,
+        pool_data: &HashMap<Pubkey, (u64, u64)>
+    ) -> Result<()> {
+        require!(amount > 0, OxygenError::InvalidParameter);
+        
+        let position_index = Self::find_open_position(user_position, market_info, position_id)?;
+        
+        let position = &user_position.leveraged_positions()[position_index];
+        require!(amount < position.margin_used, OxygenError::InvalidParameter);
+        let new_margin = position.margin_used - amount;
+        
+        // Positions with near-zero margin cost more to monitor and liquidate than they're worth
+        require!(
+            new_margin >= market_info.min_margin,
+            OxygenError::InsufficientCollateral
+        );
+        
+        let new_leverage = Self::set_position_margin(user_position, market_info, position_index, new_margin)?;
+        require!(
+            new_leverage <= market_info.max_leverage,
+            OxygenError::LeverageExceedsMaximum
+        );
+        
+        Self::release_margin(user_position, amount);
+        
+        // Thinner margin means more risk, so the account must still clear the leveraged minimum
+        let health_factor = Self::simulate_position_health_factor(user_position, pool_data, 0, 0)?;
+        require!(
+            health_factor >= Self::MIN_LEVERAGE_HEALTH_FACTOR,
+            OxygenError::HealthFactorTooLow
+        );
         
         Ok(())
     }
     
+    // Helper function to find an open position on this market by ID
+    fn find_open_position(
+        user_position: &UserPosition,
+        market_info: &MarketInfo,
+        position_id: u64
+    ) -> Result<usize> {
+        let position_index = user_position.leveraged_positions()
+            .iter()
+            .position(|p| p.id == position_id)
+            .ok_or(OxygenError::PositionNotFound)?;
+        
+        let position = &user_position.leveraged_positions()[position_index];
+        
+        require!(
+            position.status == crate::state::PositionStatus::Open,
+            OxygenError::PositionAlreadyClosed
+        );
+        require!(
+            position.market == market_info.serum_market,
+            OxygenError::InvalidSerumMarket
+        );
+        
+        Ok(position_index)
+    }
+    
+    // Helper function to apply a new margin to a position, recomputing its leverage
+    // and liquidation price. Returns the new leverage
+    fn set_position_margin(
+        user_position: &mut UserPosition,
+        market_info: &MarketInfo,
+        position_index: usize,
+        new_margin: u64
+    ) -> Result<u64> {
+        let position = &mut user_position.leveraged_positions_mut()[position_index];
+        
+        let new_leverage = (position.position_value as u128)
+            .checked_mul(10000)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(new_margin as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let new_leverage = u64::try_from(new_leverage)
+            .map_err(|_| ErrorCode::MathOverflow)?;
+        
+        position.margin_used = new_margin;
+        position.leverage = new_leverage;
+        position.liquidation_price = Self::calculate_liquidation_price(
+            position.side,
+            position.entry_price,
+            new_leverage,
+            market_info.maintenance_margin_ratio
+        )?;
+        
+        msg!(
+            "Position {} margin set to {}: leverage={}x, liquidation price={}",
+            position.id,
+            new_margin,
+            new_leverage as f64 / 10000.0,
+            position.liquidation_price
+        );
+        
+        Ok(new_leverage)
+    }
+    
     /// Liquidate an underwater leveraged position
+    /// Returns the margin left to the user after the loss is forfeited
     pub fn liquidate_position<'a>(
-        user_position: &mut Account<'a, UserPosition>,
+        user_position: &mut UserPosition,
+        market_info: &mut Account<'a, MarketInfo>,
+        quote_pool: &mut Account<'a, Pool>,
         position_id: u64,
         liquidation_price: u64,
         pool_data: &HashMap<Pubkey, (u64, u64)>
-    ) -> Result<()> {
+    ) -> Result<u64> {
         // Find the position with the given ID
-        let position_index = user_position.leveraged_positions
+        let position_index = user_position.leveraged_positions()
             .iter()
             .position(|p| p.id == position_id)
             .ok_or(OxygenError::PositionNotFound)?;
             
-        let position = &mut user_position.leveraged_positions[position_index];
+        let position = &mut user_position.leveraged_positions_mut()[position_index];
         
         // Ensure position is open
         require!(
@@ -534,6 +1001,12 @@ impl TradingModule {
             OxygenError::PositionAlreadyClosed
         );
         
+        // Open interest is tracked per market, so only this market's positions can be settled here
+        require!(
+            position.market == market_info.serum_market,
+            OxygenError::InvalidSerumMarket
+        );
+        
         // Check if position is eligible for liquidation
         let is_liquidatable = match position.side {
             OrderSide::Buy => liquidation_price <= position.liquidation_price,
@@ -545,54 +1018,159 @@ impl TradingModule {
         // Calculate remaining margin after liquidation (if any)
         // Note: In a real implementation, this would be more sophisticated
         // and include liquidation penalties
-        let remaining_margin = if liquidation_price == 0 {
-            0 // Full liquidation
+        let (remaining_margin, excess_loss) = if liquidation_price == 0 {
+            (0, 0) // Full liquidation
         } else {
-            let (loss, _) = Self::calculate_pnl(
+            let (loss, is_profit) = Self::calculate_pnl(
                 position.side,
                 position.entry_price,
                 liquidation_price,
-                position.size,
-                position.leverage
+                position.size
             )?;
             
-            if loss >= position.margin_used {
-                0 // No margin remaining
+            if is_profit {
+                (position.margin_used, 0)
+            } else if loss >= position.margin_used {
+                (0, loss - position.margin_used) // No margin remaining
             } else {
-                position.margin_used - loss
+                (position.margin_used - loss, 0)
             }
         };
         
         // Update position status
         position.status = crate::state::PositionStatus::Liquidated;
+        let margin_used = position.margin_used;
+        let margin_mode = position.margin_mode;
+        
+        let market = position.market;
+        let margin_lost = margin_used - remaining_margin;
+        
+        // Cross positions also charge losses beyond their margin to the user's
+        // remaining quote collateral, while isolated ones stop at their own margin
+        let (amount_to_forfeit, uncovered_loss) = match margin_mode {
+            MarginMode::Cross => (
+                margin_lost.checked_add(excess_loss).ok_or(ErrorCode::MathOverflow)?,
+                0
+            ),
+            MarginMode::Isolated => (margin_lost, excess_loss),
+        };
         
-        // In a real implementation, we would:
-        // 1. Return any remaining margin to the user
-        // 2. Apply liquidation penalties
-        // 3. Close the position on Serum DEX
+        // The position no longer counts towards the market's open interest
+        market_info.decrease_open_interest(position.side, position.position_value)?;
+        
+        // Unlock the whole margin: the remainder becomes available collateral again,
+        // while the lost portion is taken out of the user's quote collateral below
+        Self::release_margin(user_position, margin_used);
+        
+        // The lost margin stays in the quote reserve rather than being owed back, and
+        // funds the profit of the market's winning positions
+        let margin_forfeited = Self::forfeit_margin(user_position, quote_pool, amount_to_forfeit)?;
+        market_info.realized_loss_balance = market_info.realized_loss_balance
+            .checked_add(margin_forfeited)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        // Whatever the position may not or could not take from the user's
+        // collateral is absorbed by the protocol
+        let shortfall = amount_to_forfeit
+            .checked_sub(margin_forfeited)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_add(uncovered_loss)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Self::record_bad_debt(quote_pool, shortfall)?;
+        
+        // In a real implementation, we would also:
+        // 1. Apply liquidation penalties
+        // 2. Close the position on Serum DEX
+        
+        emit!(PositionLiquidatedEvent {
+            position_id,
+            user: user_position.owner,
+            market,
+            liquidation_price,
+            remaining_margin,
+            margin_lost: margin_forfeited,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
         
         msg!(
-            "Leveraged position liquidated: ID={}, Price={}, Remaining Margin={}",
+            "Leveraged position liquidated: ID={}, Price={}, Remaining Margin={}, Margin Lost={}",
             position_id,
             liquidation_price,
-            remaining_margin
+            remaining_margin,
+            margin_forfeited
         );
         
-        // Remove the liquidated position
-        user_position.leveraged_positions.remove(position_index);
+        // Record the liquidation, with the forfeited margin as the realized loss,
+        // then remove the liquidated position
+        let realized_pnl = -i64::try_from(margin_forfeited).map_err(|_| OxygenError::PnlOverflow)?;
+        let entry = ClosedPosition::from_position(
+            &user_position.leveraged_positions()[position_index],
+            liquidation_price,
+            realized_pnl,
+            crate::state::PositionStatus::Liquidated,
+            Clock::get()?.unix_timestamp
+        );
+        user_position.record_closed_position(entry);
+        user_position.remove_leveraged_position(position_index);
         
         // Update user's position health factor after liquidation
         let _ = user_position.calculate_health_factor(pool_data)?;
         
+        Ok(remaining_margin)
+    }
+    
+    /// Validate that stop-loss and take-profit prices are on the correct side of the entry price
+    /// A long stops out below entry and takes profit above it, and a short the other way around
+    fn validate_exit_triggers(
+        side: OrderSide,
+        entry_price: u64,
+        stop_loss_price: u64,
+        take_profit_price: u64
+    ) -> Result<()> {
+        let (stop_loss_valid, take_profit_valid) = match side {
+            OrderSide::Buy => (
+                stop_loss_price < entry_price,
+                take_profit_price > entry_price,
+            ),
+            OrderSide::Sell => (
+                stop_loss_price > entry_price,
+                take_profit_price < entry_price,
+            ),
+        };
+        
+        require!(
+            stop_loss_price == 0 || stop_loss_valid,
+            OxygenError::InvalidParameter
+        );
+        require!(
+            take_profit_price == 0 || take_profit_valid,
+            OxygenError::InvalidParameter
+        );
+        
         Ok(())
     }
     
+    /// Whether the current price has crossed the position's stop-loss or take-profit price
+    fn is_exit_triggered(position: &LeveragedPosition, current_price: u64) -> bool {
+        let stop_loss_hit = position.stop_loss_price != 0 && match position.side {
+            OrderSide::Buy => current_price <= position.stop_loss_price,
+            OrderSide::Sell => current_price >= position.stop_loss_price,
+        };
+        
+        let take_profit_hit = position.take_profit_price != 0 && match position.side {
+            OrderSide::Buy => current_price >= position.take_profit_price,
+            OrderSide::Sell => current_price <= position.take_profit_price,
+        };
+        
+        stop_loss_hit || take_profit_hit
+    }
+    
     /// Generate a unique position ID
     fn generate_position_id(user_position: &UserPosition) -> Result<u64> {
         // Simple ID generation for MVP
         // In a real implementation, this would be more sophisticated
         let mut max_id = 0;
-        for pos in &user_position.leveraged_positions {
+        for pos in user_position.leveraged_positions() {
             if pos.id > max_id {
                 max_id = pos.id;
             }
@@ -610,21 +1188,25 @@ impl TradingModule {
         leverage: u64,
         maintenance_margin_ratio: u64
     ) -> Result<u64> {
-        // For long positions: liquidation_price = entry_price * (1 - maintenance_margin_ratio * leverage / 10000)
-        // For short positions: liquidation_price = entry_price * (1 + maintenance_margin_ratio * leverage / 10000)
+        // The position is liquidated once losses eat through the initial margin
+        // (1 / leverage) down to the maintenance margin, so:
+        // For long positions: liquidation_price = entry_price * (1 - (1 / leverage - maintenance_margin_ratio))
+        // For short positions: liquidation_price = entry_price * (1 + (1 / leverage - maintenance_margin_ratio))
+        // Higher leverage means a thinner margin and a liquidation price closer to entry
         
-        let margin_impact = (maintenance_margin_ratio as u128)
-            .checked_mul(leverage as u128)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::MathOverflow)? as u64;
+        // Initial margin ratio in basis points (e.g. 2x leverage = 5000 = 50%)
+        let initial_margin_ratio = 100_000_000u128 // 10000 (bps) * 10000 (1x leverage)
+            .checked_div(leverage as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        // If the initial margin doesn't even cover the maintenance margin,
+        // the position is liquidatable right at entry
+        let margin_impact = initial_margin_ratio
+            .saturating_sub(maintenance_margin_ratio as u128)
+            .min(10000) as u64;
             
         match side {
             OrderSide::Buy => {
-                if margin_impact >= 10000 {
-                    return Ok(0); // Will be liquidated immediately
-                }
-                
                 let factor = 10000u64
                     .checked_sub(margin_impact)
                     .ok_or(ErrorCode::MathOverflow)?;
@@ -633,7 +1215,10 @@ impl TradingModule {
                     .checked_mul(factor as u128)
                     .ok_or(ErrorCode::MathOverflow)?
                     .checked_div(10000)
-                    .ok_or(ErrorCode::MathOverflow)? as u64;
+                    .ok_or(ErrorCode::MathOverflow)?;
+                
+                let liquidation_price = u64::try_from(liquidation_price)
+                    .map_err(|_| ErrorCode::MathOverflow)?;
                     
                 Ok(liquidation_price)
             },
@@ -646,43 +1231,122 @@ impl TradingModule {
                     .checked_mul(factor as u128)
                     .ok_or(ErrorCode::MathOverflow)?
                     .checked_div(10000)
-                    .ok_or(ErrorCode::MathOverflow)? as u64;
+                    .ok_or(ErrorCode::MathOverflow)?;
+                
+                let liquidation_price = u64::try_from(liquidation_price)
+                    .map_err(|_| ErrorCode::MathOverflow)?;
                     
                 Ok(liquidation_price)
             }
         }
     }
     
+    /// Close `size` worth of open positions on the opposite side of `side` at `price`
+    /// Rejected if there isn't enough opposing size, since the rest would flip the
+    /// net position. Returns the ID of the last position reduced
+    fn reduce_opposing_positions<'a>(
+        user_position: &mut UserPosition,
+        market_info: &mut Account<'a, MarketInfo>,
+        quote_pool: &mut Account<'a, Pool>,
+        side: OrderSide,
+        size: u64,
+        price: u64,
+        pool_data: &HashMap<Pubkey, (u64, u64)>
+    ) -> Result<u64> {
+        let opposing: Vec<(u64, u64)> = user_position.leveraged_positions()
+            .iter()
+            .filter(|position| {
+                position.status == crate::state::PositionStatus::Open
+                    && position.market == market_info.serum_market
+                    && !matches!(
+                        (position.side, side),
+                        (OrderSide::Buy, OrderSide::Buy) | (OrderSide::Sell, OrderSide::Sell)
+                    )
+            })
+            .map(|position| (position.id, position.size))
+            .collect();
+        
+        let opposing_size = opposing
+            .iter()
+            .try_fold(0u64, |total, (_, position_size)| total.checked_add(*position_size))
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        require!(
+            !opposing.is_empty() && size <= opposing_size,
+            OxygenError::ReduceOnlyViolation
+        );
+        
+        let mut remaining = size;
+        let mut last_position_id = 0;
+        
+        for (position_id, position_size) in opposing {
+            if remaining == 0 {
+                break;
+            }
+            
+            let close_size = std::cmp::min(remaining, position_size);
+            Self::close_position(
+                user_position,
+                market_info,
+                quote_pool,
+                position_id,
+                close_size,
+                price,
+                pool_data
+            )?;
+            
+            remaining -= close_size;
+            last_position_id = position_id;
+        }
+        
+        msg!("Reduce-only order netted {} against opposing positions", size);
+        
+        Ok(last_position_id)
+    }
+    
+    /// Signed PnL a position would realize if closed at `mark_price`
+    pub fn calculate_unrealized_pnl(position: &LeveragedPosition, mark_price: u64) -> Result<i64> {
+        let (pnl, is_profit) = Self::calculate_pnl(
+            position.side,
+            position.entry_price,
+            mark_price,
+            position.size
+        )?;
+        
+        let pnl = i64::try_from(pnl).map_err(|_| OxygenError::PnlOverflow)?;
+        
+        Ok(if is_profit { pnl } else { -pnl })
+    }
+    
     /// Calculate PnL for a position
-    fn calculate_pnl(
+    /// `size` is the position's full leveraged size, so the price move applies to it directly
+    pub(crate) fn calculate_pnl(
         side: OrderSide,
         entry_price: u64,
         exit_price: u64,
-        size: u64,
-        leverage: u64
+        size: u64
     ) -> Result<(u64, bool)> {
-        // Calculate raw PnL
-        let (raw_pnl, is_profit) = match side {
+        let (pnl, is_profit) = match side {
             OrderSide::Buy => {
                 if exit_price > entry_price {
                     // Profit
                     let diff = exit_price
                         .checked_sub(entry_price)
-                        .ok_or(ErrorCode::MathOverflow)?;
+                        .ok_or(OxygenError::PnlOverflow)?;
                         
                     ((diff as u128)
                         .checked_mul(size as u128)
-                        .ok_or(ErrorCode::MathOverflow)? as u64, 
+                        .ok_or(OxygenError::PnlOverflow)?, 
                      true)
                 } else {
                     // Loss
                     let diff = entry_price
                         .checked_sub(exit_price)
-                        .ok_or(ErrorCode::MathOverflow)?;
+                        .ok_or(OxygenError::PnlOverflow)?;
                         
                     ((diff as u128)
                         .checked_mul(size as u128)
-                        .ok_or(ErrorCode::MathOverflow)? as u64, 
+                        .ok_or(OxygenError::PnlOverflow)?, 
                      false)
                 }
             },
@@ -691,109 +1355,536 @@ impl TradingModule {
                     // Profit
                     let diff = entry_price
                         .checked_sub(exit_price)
-                        .ok_or(ErrorCode::MathOverflow)?;
+                        .ok_or(OxygenError::PnlOverflow)?;
                         
                     ((diff as u128)
                         .checked_mul(size as u128)
-                        .ok_or(ErrorCode::MathOverflow)? as u64, 
+                        .ok_or(OxygenError::PnlOverflow)?, 
                      true)
                 } else {
                     // Loss
                     let diff = exit_price
                         .checked_sub(entry_price)
-                        .ok_or(ErrorCode::MathOverflow)?;
+                        .ok_or(OxygenError::PnlOverflow)?;
                         
                     ((diff as u128)
                         .checked_mul(size as u128)
-                        .ok_or(ErrorCode::MathOverflow)? as u64, 
+                        .ok_or(OxygenError::PnlOverflow)?, 
                      false)
                 }
             }
         };
         
-        // Apply leverage
-        let leveraged_pnl = (raw_pnl as u128)
-            .checked_mul(leverage as u128)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::MathOverflow)? as u64;
-            
-        Ok((leveraged_pnl, is_profit))
+        let pnl = u64::try_from(pnl).map_err(|_| OxygenError::PnlOverflow)?;
+        
+        Ok((pnl, is_profit))
     }
 
     /// Monitor open positions and check for liquidation conditions
+    /// `mark_price` must be the market's oracle mark price, see `mark_price`
     pub fn monitor_positions<'a>(
-        user_position: &mut Account<'a, UserPosition>,
-        current_prices: &HashMap<Pubkey, u64>,
+        user_position: &mut UserPosition,
+        market_info: &mut Account<'a, MarketInfo>,
+        quote_pool: &mut Account<'a, Pool>,
+        mark_price: u64,
         pool_data: &HashMap<Pubkey, (u64, u64)>
     ) -> Result<()> {
         let mut positions_to_liquidate = Vec::new();
+        let mut positions_to_close = Vec::new();
         
-        for (i, position) in user_position.leveraged_positions.iter().enumerate() {
-            if position.status != crate::state::PositionStatus::Open {
+        for (i, position) in user_position.leveraged_positions().iter().enumerate() {
+            // Only this market's open positions are monitored here
+            if position.status != crate::state::PositionStatus::Open
+                || position.market != market_info.serum_market {
                 continue;
             }
             
-            // Get current price for the market
-            if let Some(&current_price) = current_prices.get(&position.market) {
-                let is_liquidatable = match position.side {
-                    OrderSide::Buy => current_price <= position.liquidation_price,
-                    OrderSide::Sell => current_price >= position.liquidation_price,
-                };
-                
-                if is_liquidatable {
-                    positions_to_liquidate.push((i, position.id, current_price));
-                }
+            let is_liquidatable = match position.side {
+                OrderSide::Buy => mark_price <= position.liquidation_price,
+                OrderSide::Sell => mark_price >= position.liquidation_price,
+            };
+            
+            if is_liquidatable {
+                positions_to_liquidate.push((i, position.id, mark_price));
+            } else if Self::is_exit_triggered(position, mark_price) {
+                // Stop-loss and take-profit orders close at the mark price
+                positions_to_close.push((i, position.id, mark_price));
             }
         }
         
         // Liquidate positions (in reverse order to not mess up indices)
         for (_, position_id, price) in positions_to_liquidate.iter().rev() {
-            let _ = Self::liquidate_position(user_position, *position_id, *price, pool_data)?;
+            let _ = Self::liquidate_position(user_position, market_info, quote_pool, *position_id, *price, pool_data)?;
+        }
+        
+        // Close positions whose exit triggers were hit
+        for (_, position_id, price) in positions_to_close.iter().rev() {
+            Self::close_position(user_position, market_info, quote_pool, *position_id, 0, *price, pool_data)?;
         }
         
         Ok(())
     }
 
-    /// Initialize Serum open orders account for a user (if needed)
-    pub fn initialize_open_orders_account(_ctx: &Context<'_, '_,'_, '_>) -> Result<()> {
-        // This would be implemented in a full version
-        // and would integrate with the Serum DEX program
-        // to create an open orders account for the user
-        msg!("Initializing open orders account");
+    /// Settle funding for the user's open positions on this market
+    /// Each position owes the market's funding index accrued since it last settled, at the
+    /// rate the market derives from its open interest skew rather than one anyone supplies.
+    /// Paying positions fund the market's funding pool out of their margin, and receiving
+    /// positions are paid from it, so longs and shorts only ever exchange what was paid in.
+    /// A position whose margin can't cover its payment is liquidated at the oracle mark
+    /// price rather than left with negative margin
+    pub fn settle_funding<'a>(
+        user_position: &mut UserPosition,
+        market_info: &mut Account<'a, MarketInfo>,
+        quote_pool: &mut Account<'a, Pool>,
+        mark_price: u64,
+    ) -> Result<()> {
+        let timestamp = Clock::get()?.unix_timestamp;
+        market_info.accrue_funding(timestamp)?;
+        let funding_index = market_info.cumulative_funding_index;
+        
+        let mut liquidated_indices = Vec::new();
+        
+        for i in 0..user_position.leveraged_positions().len() {
+            let position = &user_position.leveraged_positions()[i];
+            
+            // Only this market's open positions pay or receive its funding
+            if position.status != crate::state::PositionStatus::Open
+                || position.market != market_info.serum_market {
+                continue;
+            }
+            
+            // Positive = longs pay shorts, negative = shorts pay longs
+            let funding_delta = funding_index
+                .checked_sub(position.funding_index)
+                .ok_or(ErrorCode::MathOverflow)?;
+            
+            // The index accrues millionths per hour for every second, so scale back
+            // by both to get the share of the position's value owed
+            let funding_amount = (position.position_value as u128)
+                .checked_mul(funding_delta.unsigned_abs() as u128)
+                .ok_or(OxygenError::PnlOverflow)?
+                .checked_div(1_000_000 * 3600)
+                .ok_or(OxygenError::PnlOverflow)?;
+            
+            let funding_amount = u64::try_from(funding_amount)
+                .map_err(|_| OxygenError::PnlOverflow)?;
+            
+            // Leave the snapshot alone until something is owed, so frequent settling
+            // can't round a position's funding away
+            if funding_amount == 0 {
+                continue;
+            }
+            
+            let pays_funding = match position.side {
+                OrderSide::Buy => funding_delta > 0,
+                OrderSide::Sell => funding_delta < 0,
+            };
+            
+            let position_id = position.id;
+            let margin_used = position.margin_used;
+            let mut flagged_for_liquidation = false;
+            
+            user_position.leveraged_positions_mut()[i].funding_index = funding_index;
+            
+            let settled_amount = if pays_funding {
+                // Funding comes out of the position's margin and into the market's funding pool
+                let payable = std::cmp::min(funding_amount, margin_used);
+                let paid = Self::forfeit_margin(user_position, quote_pool, payable)?;
+                Self::release_margin(user_position, paid);
+                
+                market_info.funding_pool_balance = market_info.funding_pool_balance
+                    .checked_add(paid)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                
+                let position = &mut user_position.leveraged_positions_mut()[i];
+                position.margin_used = position.margin_used.saturating_sub(paid);
+                
+                // Margin that can't cover funding liquidates the position on the spot
+                if paid < funding_amount {
+                    position.status = crate::state::PositionStatus::Liquidated;
+                    let side = position.side;
+                    let position_value = position.position_value;
+                    let entry_price = position.entry_price;
+                    let size = position.size;
+                    let margin_released = position.margin_used;
+                    
+                    // Whatever margin the payment didn't take is unlocked again
+                    market_info.decrease_open_interest(side, position_value)?;
+                    Self::release_margin(user_position, margin_released);
+                    
+                    // The position closes at the oracle mark price like any other, so its
+                    // trading PnL is realized alongside the funding it paid
+                    let (pnl, is_profit) = Self::calculate_pnl(side, entry_price, mark_price, size)?;
+                    let pnl = i64::try_from(pnl).map_err(|_| OxygenError::PnlOverflow)?;
+                    let settled = Self::apply_realized_pnl(
+                        user_position,
+                        if is_profit { pnl } else { -pnl },
+                        market_info,
+                        quote_pool
+                    )?;
+                    let settled = i64::try_from(settled).map_err(|_| OxygenError::PnlOverflow)?;
+                    let trading_pnl = if is_profit { settled } else { -pnl };
+                    
+                    // Record it with the funding paid and the trading PnL as the realized
+                    // PnL; the position itself is removed once the loop is done
+                    let realized_pnl = trading_pnl
+                        .checked_sub(i64::try_from(paid).map_err(|_| OxygenError::PnlOverflow)?)
+                        .ok_or(OxygenError::PnlOverflow)?;
+                    let entry = ClosedPosition::from_position(
+                        &user_position.leveraged_positions()[i],
+                        mark_price,
+                        realized_pnl,
+                        crate::state::PositionStatus::Liquidated,
+                        timestamp
+                    );
+                    user_position.record_closed_position(entry);
+                    liquidated_indices.push(i);
+                    flagged_for_liquidation = true;
+                    
+                    emit!(FundingLiquidationEvent {
+                        user: user_position.owner,
+                        market: market_info.serum_market,
+                        position_id,
+                        funding_owed: funding_amount,
+                        funding_paid: paid,
+                        margin_released,
+                        exit_price: mark_price,
+                        trading_pnl,
+                        timestamp,
+                    });
+                }
+                
+                -(paid as i64)
+            } else {
+                // Receivers are only paid what the paying side has funded
+                let received = std::cmp::min(funding_amount, market_info.funding_pool_balance);
+                Self::credit_profit(user_position, quote_pool, received)?;
+                
+                market_info.funding_pool_balance -= received;
+                
+                received as i64
+            };
+            
+            emit!(FundingPaidEvent {
+                user: user_position.owner,
+                market: market_info.serum_market,
+                position_id,
+                amount: settled_amount,
+                flagged_for_liquidation,
+                timestamp,
+            });
+            
+            msg!("Position {} funding payment: {}", position_id, settled_amount);
+        }
+        
+        // Remove liquidated positions in reverse order so earlier indices stay valid
+        for index in liquidated_indices.into_iter().rev() {
+            user_position.remove_leveraged_position(index);
+        }
+        
         Ok(())
     }
+    
+    /// Initialize Serum open orders account for a user (if needed)
+    /// The account lives at a PDA per market and user, paid for by the user and
+    /// owned by their wallet, and is recorded on the position for later trades
+    pub fn initialize_open_orders_account<'info>(
+        ctx: &mut Context<'_, '_, '_, 'info, TradeWithLeverage<'info>>
+    ) -> Result<()> {
+        let serum_market = ctx.accounts.market_info.serum_market;
+        let open_orders_key = ctx.accounts.open_orders.key();
+        
+        // Reuse the account created on an earlier trade
+        if let Some(recorded) = ctx.accounts.user_position.load()?.open_orders_for_market(&serum_market) {
+            require!(recorded == open_orders_key, OxygenError::InvalidParameter);
+            return Ok(());
+        }
+        
+        require!(
+            ctx.accounts.user_position.load()?.open_orders_accounts().len() < UserPosition::MAX_OPEN_ORDERS_ACCOUNTS,
+            OxygenError::MaxPositionsReached
+        );
+        
+        if ctx.accounts.open_orders.data_is_empty() {
+            msg!("Initializing open orders account");
+            
+            let user_key = ctx.accounts.user.key();
+            let bump = *ctx.bumps.get("open_orders").ok_or(OxygenError::InvalidParameter)?;
+            let open_orders_seeds = &[
+                b"open_orders".as_ref(),
+                serum_market.as_ref(),
+                user_key.as_ref(),
+                &[bump],
+            ];
+            let open_orders_signer = &[&open_orders_seeds[..]];
+            
+            // The DEX program takes ownership of the account and expects it at its full size
+            let lamports = Rent::get()?.minimum_balance(OPEN_ORDERS_ACCOUNT_SIZE);
+            
+            system_program::create_account(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::CreateAccount {
+                        from: ctx.accounts.user.to_account_info(),
+                        to: ctx.accounts.open_orders.to_account_info(),
+                    },
+                    open_orders_signer,
+                ),
+                lamports,
+                OPEN_ORDERS_ACCOUNT_SIZE as u64,
+                &ctx.accounts.dex_program.key(),
+            )?;
+            
+            dex::init_open_orders(CpiContext::new(
+                ctx.accounts.dex_program.to_account_info(),
+                InitOpenOrders {
+                    open_orders: ctx.accounts.open_orders.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                    market: ctx.accounts.serum_market.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+            ))?;
+        }
+        
+        ctx.accounts.user_position.load_mut()?.add_open_orders_account(OpenOrdersAccount {
+            market: serum_market,
+            open_orders: open_orders_key,
+        })
+    }
 
-    /// Apply realized PnL to the user's account
-    pub fn apply_realized_pnl(
+    /// Apply realized PnL to the user's quote collateral
+    /// A loss is taken out of the user's deposit and held for the market's winning
+    /// positions; any loss larger than the user's balance becomes bad debt on the quote
+    /// pool. Profit is only paid out of losses collected this way, since no tokens
+    /// come in for it. Returns the amount actually credited or debited
+    pub fn apply_realized_pnl<'a>(
         user_position: &mut UserPosition,
         realized_pnl: i64, // Positive for profit, negative for loss
-        base_pool: &Pubkey,
-        quote_pool: &Pubkey
-    ) -> Result<()> {
-        // In a real implementation, this would handle:
-        // 1. Increasing user's balance in case of profit
-        // 2. Decreasing user's balance in case of loss
-        // 3. Updating affected pool balances
+        market_info: &mut MarketInfo,
+        quote_pool: &mut Account<'a, Pool>
+    ) -> Result<u64> {
+        // Settlement pool must be the market's own quote pool
+        require!(
+            quote_pool.asset_mint == market_info.quote_mint,
+            OxygenError::SettlementPoolMismatch
+        );
+        
+        // PnL is denominated in the quote asset, so it settles against the
+        // user's existing collateral entry in the quote pool
+        let has_settlement_collateral = user_position.collaterals()
+            .iter()
+            .any(|collateral| collateral.pool == quote_pool.key());
+            
+        require!(has_settlement_collateral, OxygenError::CollateralNotFound);
+        
+        let amount = realized_pnl.unsigned_abs();
         
         if realized_pnl > 0 {
-            // Mock handling of profit
-            msg!("Realized profit: {}", realized_pnl);
+            let paid = std::cmp::min(amount, market_info.realized_loss_balance);
+            Self::credit_profit(user_position, quote_pool, paid)?;
+            market_info.realized_loss_balance -= paid;
+            
+            msg!("Realized profit: {} (unfunded: {})", paid, amount - paid);
+            
+            Ok(paid)
         } else if realized_pnl < 0 {
-            // Mock handling of loss
-            msg!("Realized loss: {}", realized_pnl.abs());
+            let debited = Self::forfeit_margin(user_position, quote_pool, amount)?;
+            market_info.realized_loss_balance = market_info.realized_loss_balance
+                .checked_add(debited)
+                .ok_or(ErrorCode::MathOverflow)?;
+            
+            let shortfall = amount - debited;
+            Self::record_bad_debt(quote_pool, shortfall)?;
+            
+            msg!("Realized loss: {} (bad debt: {})", amount, shortfall);
+            
+            Ok(debited)
+        } else {
+            Ok(0)
+        }
+    }
+    
+    /// Record a loss the protocol absorbed because the user's collateral couldn't cover it
+    fn record_bad_debt<'a>(quote_pool: &mut Account<'a, Pool>, shortfall: u64) -> Result<()> {
+        if shortfall == 0 {
+            return Ok(());
         }
         
+        quote_pool.bad_debt = quote_pool.bad_debt
+            .checked_add(shortfall)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        msg!("Recorded {} of bad debt", shortfall);
+        
         Ok(())
     }
 }
 
-// Event emitted when a new position is created for off-chain monitoring
-#[event]
-pub struct PositionCreatedEvent {
-    pub position_id: u64,
-    pub market: Pubkey,
-    pub user: Pubkey,
-    pub liquidation_price: u64,
-    pub timestamp: i64,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::position::tests::{borrow, collateral};
+    
+    fn market(serum_market: Pubkey) -> MarketInfo {
+        MarketInfo {
+            serum_market,
+            asset_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            oracle: Pubkey::default(),
+            last_oracle_price: 0,
+            max_oracle_deviation: 500,
+            optimal_leverage: 30000,
+            max_leverage: 100000,
+            liquidation_fee: 100,
+            maintenance_margin_ratio: 500,
+            min_margin: 500,
+            min_position_value: 0,
+            long_open_interest: 0,
+            short_open_interest: 0,
+            max_open_interest: 0,
+            funding_pool_balance: 0,
+            realized_loss_balance: 0,
+            max_funding_rate: 0,
+            funding_rate: 0,
+            cumulative_funding_index: 0,
+            last_funding_update: 0,
+            bump: 0,
+        }
+    }
+    
+    // 100_000 quote tokens of collateral and a 5x cross long worth 10_000 on `serum_market`
+    fn trading_position(serum_market: Pubkey) -> (UserPosition, HashMap<Pubkey, (u64, u64)>) {
+        let quote_pool = Pubkey::new_unique();
+        let mut position = UserPosition::with_positions(&[collateral(quote_pool, 100_000)], &[]);
+        position.add_leveraged_position(LeveragedPosition {
+            id: 1,
+            market: serum_market,
+            side: OrderSide::Buy,
+            size: 10,
+            entry_price: 1_000,
+            leverage: 50000,
+            margin_used: 2_000,
+            position_value: 10_000,
+            ..Default::default()
+        }).unwrap();
+        position.locked_trading_margin = 2_000;
+        (position, HashMap::from([(quote_pool, (1, 8000))]))
+    }
+    
+    #[test]
+    fn pnl_follows_the_side_of_the_position() {
+        assert_eq!(TradingModule::calculate_pnl(OrderSide::Buy, 100, 110, 5).unwrap(), (50, true));
+        assert_eq!(TradingModule::calculate_pnl(OrderSide::Buy, 100, 90, 5).unwrap(), (50, false));
+        assert_eq!(TradingModule::calculate_pnl(OrderSide::Sell, 100, 90, 5).unwrap(), (50, true));
+        assert_eq!(TradingModule::calculate_pnl(OrderSide::Sell, 100, 110, 5).unwrap(), (50, false));
+        
+        let (position, _) = trading_position(Pubkey::new_unique());
+        assert_eq!(TradingModule::calculate_unrealized_pnl(&position.leveraged_positions()[0], 950).unwrap(), -500);
+    }
+    
+    #[test]
+    fn liquidation_price_sits_at_the_maintenance_margin() {
+        // 2x leaves a 50% margin, of which 45% can be lost before the 5% maintenance margin
+        assert_eq!(TradingModule::calculate_liquidation_price(OrderSide::Buy, 1_000, 20000, 500).unwrap(), 550);
+        assert_eq!(TradingModule::calculate_liquidation_price(OrderSide::Sell, 1_000, 20000, 500).unwrap(), 1_450);
+        
+        // At 25x the 4% margin is already under maintenance, so entry is liquidatable
+        assert_eq!(TradingModule::calculate_liquidation_price(OrderSide::Buy, 1_000, 250000, 500).unwrap(), 1_000);
+    }
+    
+    #[test]
+    fn exit_triggers_must_be_on_the_right_side_of_entry() {
+        assert!(TradingModule::validate_exit_triggers(OrderSide::Buy, 1_000, 900, 1_100).is_ok());
+        assert!(TradingModule::validate_exit_triggers(OrderSide::Buy, 1_000, 1_100, 0).is_err());
+        assert!(TradingModule::validate_exit_triggers(OrderSide::Sell, 1_000, 1_100, 900).is_ok());
+        assert!(TradingModule::validate_exit_triggers(OrderSide::Sell, 1_000, 0, 1_100).is_err());
+        
+        let (mut position, _) = trading_position(Pubkey::new_unique());
+        let long = &mut position.leveraged_positions_mut()[0];
+        long.stop_loss_price = 900;
+        long.take_profit_price = 1_100;
+        assert!(!TradingModule::is_exit_triggered(long, 1_000));
+        assert!(TradingModule::is_exit_triggered(long, 900));
+        assert!(TradingModule::is_exit_triggered(long, 1_100));
+    }
+    
+    #[test]
+    fn price_deviation_is_relative_to_the_reference() {
+        assert!(TradingModule::require_within_deviation(10_500, 10_000, 500).is_ok());
+        assert!(TradingModule::require_within_deviation(9_500, 10_000, 500).is_ok());
+        assert!(TradingModule::require_within_deviation(10_501, 10_000, 500).is_err());
+        assert!(TradingModule::require_within_deviation(1, 0, 500).is_err());
+    }
+    
+    #[test]
+    fn available_collateral_is_eighty_percent_of_the_excess() {
+        let (mut position, mut pool_data) = trading_position(Pubkey::new_unique());
+        
+        // 100_000 of collateral less the 2_000 margin already in use
+        assert_eq!(TradingModule::calculate_user_available_collateral(&position, &pool_data).unwrap(), 78_400);
+        
+        let debt_pool = Pubkey::new_unique();
+        position.borrow_slots[0] = borrow(debt_pool, 98_000);
+        position.borrow_count = 1;
+        pool_data.insert(debt_pool, (1, 8000));
+        assert_eq!(TradingModule::calculate_user_available_collateral(&position, &pool_data).unwrap(), 0);
+    }
+    
+    #[test]
+    fn adding_margin_lowers_leverage_and_locks_collateral() {
+        let serum_market = Pubkey::new_unique();
+        let (mut position, pool_data) = trading_position(serum_market);
+        
+        TradingModule::add_position_margin(&mut position, &market(serum_market), 1, 3_000, &pool_data).unwrap();
+        
+        let long = &position.leveraged_positions()[0];
+        assert_eq!(long.margin_used, 5_000);
+        assert_eq!(long.leverage, 20000);
+        assert_eq!(long.liquidation_price, 550);
+        assert_eq!(position.locked_trading_margin, 5_000);
+        
+        // Margin can't exceed the position's value
+        assert!(TradingModule::add_position_margin(&mut position, &market(serum_market), 1, 5_001, &pool_data).is_err());
+    }
+    
+    #[test]
+    fn removing_margin_respects_the_market_limits() {
+        let serum_market = Pubkey::new_unique();
+        let (mut position, pool_data) = trading_position(serum_market);
+        
+        TradingModule::remove_position_margin(&mut position, &market(serum_market), 1, 1_000, &pool_data).unwrap();
+        assert_eq!(position.leveraged_positions()[0].leverage, 100000);
+        assert_eq!(position.locked_trading_margin, 1_000);
+        
+        // Going past 10x, below the minimum margin or onto another market is rejected
+        let (mut position, pool_data) = trading_position(serum_market);
+        assert_eq!(
+            TradingModule::remove_position_margin(&mut position, &market(serum_market), 1, 1_200, &pool_data).unwrap_err(),
+            OxygenError::LeverageExceedsMaximum.into()
+        );
+        let (mut position, pool_data) = trading_position(serum_market);
+        assert_eq!(
+            TradingModule::remove_position_margin(&mut position, &market(serum_market), 1, 1_600, &pool_data).unwrap_err(),
+            OxygenError::InsufficientCollateral.into()
+        );
+        assert_eq!(
+            TradingModule::remove_position_margin(&mut position, &market(Pubkey::new_unique()), 1, 100, &pool_data).unwrap_err(),
+            OxygenError::InvalidSerumMarket.into()
+        );
+    }
+    
+    #[test]
+    fn native_pc_qty_scales_by_lot_size_and_adds_taker_fee() {
+        let native_pc_qty = 5 * 2_000 * 100;
+        let max_native_pc_qty = TradingModule::native_pc_qty_with_taker_fee(5, 2_000, 100).unwrap();
+        
+        assert_eq!(max_native_pc_qty, native_pc_qty + FeeTier::Base.taker_fee(native_pc_qty));
+        assert!(max_native_pc_qty > native_pc_qty);
+    }
+    
+    #[test]
+    fn native_pc_qty_overflow_is_an_error() {
+        assert!(TradingModule::native_pc_qty_with_taker_fee(u64::MAX, 2, 1).is_err());
+        assert!(TradingModule::native_pc_qty_with_taker_fee(1 << 32, 1 << 16, 1 << 16).is_err());
+    }
 }
\ No newline at end of file
@@ -1,7 +1,9 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 use std::collections::HashMap;
 use crate::state::{Pool, UserPosition, CollateralPosition};
 use crate::errors::OxygenError;
+use crate::events::YieldClaimedEvent;
 use crate::modules::wallet_integration::WalletIntegration;
 
 /// Module for handling yield generation and distribution
@@ -24,11 +26,11 @@ impl YieldModule {
             return Ok(0);
         }
         
-        // Calculate the ratio of current lending rate to the rate when the deposit was made
-        // This gives us the growth factor of the deposit
+        // The scaled amount was recorded against the lending rate at deposit (or last claim),
+        // so growing it by the current rate gives the deposit's value including yield
         let principal_value = collateral_position.amount_deposited;
         
-        // Calculate accrued value using the ratio of scaled amount to current exchange rate
+        // current_value = amount_scaled * cumulative_lending_rate / 10^12
         let current_value = (collateral_position.amount_scaled as u128)
             .checked_mul(pool.cumulative_lending_rate)
             .ok_or(ErrorCode::MathOverflow)?
@@ -45,15 +47,114 @@ impl YieldModule {
         Ok(accrued_yield)
     }
     
-    /// Claim accrued yield for a user's lending position
+    /// Claim accrued yield for a user's lending position and transfer it to their wallet
     /// Non-custodial: requires user signature to claim their own yield
-    pub fn claim_yield<'a>(
-        pool: &mut Account<'a, Pool>,
-        user_position: &mut Account<'a, UserPosition>,
-        pool_key: &Pubkey,
+    pub fn claim_yield<'info>(
+        pool: &mut Account<'info, Pool>,
+        user_position: &mut UserPosition,
+        asset_mint: &InterfaceAccount<'info, Mint>,
+        asset_reserve: &InterfaceAccount<'info, TokenAccount>,
+        user_token_account: &InterfaceAccount<'info, TokenAccount>,
+        token_program: &Interface<'info, TokenInterface>,
+        user: &Signer<'info>,
+        current_timestamp: i64,
+    ) -> Result<u64> {
+        let claimed_yield = Self::settle_yield(pool, user_position, user, current_timestamp)?;
+        if claimed_yield == 0 {
+            return Ok(0);
+        }
+        
+        // Non-custodial: we transfer directly to the user's wallet, not to protocol-controlled accounts
+        let pool_seeds = &[
+            b"pool".as_ref(),
+            pool.asset_mint.as_ref(),
+            &[pool.bump],
+        ];
+        
+        let pool_signer = &[&pool_seeds[..]];
+        
+        let cpi_accounts = TransferChecked {
+            from: asset_reserve.to_account_info(),
+            mint: asset_mint.to_account_info(),
+            to: user_token_account.to_account_info(),
+            authority: pool.to_account_info(),
+        };
+        
+        let cpi_context = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            pool_signer,
+        );
+        
+        token_interface::transfer_checked(cpi_context, claimed_yield, asset_mint.decimals)?;
+        
+        emit!(YieldClaimedEvent {
+            user: user.key(),
+            pool: pool.key(),
+            asset_mint: pool.asset_mint,
+            amount: claimed_yield,
+            reinvested: false,
+            timestamp: current_timestamp,
+        });
+        
+        Ok(claimed_yield)
+    }
+    
+    /// Fold accrued yield back into the user's lending deposit
+    /// The yield is already in the reserve, so no tokens move; the deposit and
+    /// pool totals grow exactly as if it had been claimed and deposited for lending
+    pub fn compound_yield<'info>(
+        pool: &mut Account<'info, Pool>,
+        user_position: &mut UserPosition,
+        user: &Signer<'info>,
+        current_timestamp: i64,
+    ) -> Result<u64> {
+        let accrued_yield = Self::settle_yield(pool, user_position, user, current_timestamp)?;
+        if accrued_yield == 0 {
+            return Ok(0);
+        }
+        
+        let collateral_index = user_position
+            .collaterals()
+            .iter()
+            .position(|collateral| collateral.pool == pool.key() && collateral.is_lending)
+            .ok_or(OxygenError::CollateralNotFound)?;
+        
+        let additional_scaled = pool.deposit_to_scaled(accrued_yield)?;
+        let collateral = &mut user_position.collaterals_mut()[collateral_index];
+        collateral.amount_deposited = collateral.amount_deposited
+            .checked_add(accrued_yield)
+            .ok_or(ErrorCode::MathOverflow)?;
+        collateral.amount_scaled = collateral.amount_scaled
+            .checked_add(additional_scaled)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        // Compounded yield stays lent out, so it adds to total_lent as well, or
+        // withdrawing the grown deposit would take more than was ever lent
+        pool.add_lent_deposit(accrued_yield)?;
+        
+        emit!(YieldClaimedEvent {
+            user: user.key(),
+            pool: pool.key(),
+            asset_mint: pool.asset_mint,
+            amount: accrued_yield,
+            reinvested: true,
+            timestamp: current_timestamp,
+        });
+        
+        Ok(accrued_yield)
+    }
+    
+    /// Settle accrued yield for a user's lending position without moving tokens
+    /// Resets the position's scaled amount so the settled yield can't be claimed again
+    pub fn settle_yield<'info>(
+        pool: &Account<'info, Pool>,
+        user_position: &mut UserPosition,
+        user: &Signer<'info>,
         current_timestamp: i64,
-        user: &Signer<'a>,
     ) -> Result<u64> {
+        let pool_key = pool.key();
+        
         // First validate non-custodial requirements
         require!(pool.immutable, OxygenError::PoolIsUpgradable);
         require!(pool.admin_less, OxygenError::AdminOperationsNotSupported);
@@ -65,8 +166,8 @@ impl YieldModule {
         let mut collateral_index = None;
         
         // Find the collateral position for this pool
-        for (i, collateral) in user_position.collaterals.iter().enumerate() {
-            if collateral.pool == *pool_key && collateral.is_lending {
+        for (i, collateral) in user_position.collaterals().iter().enumerate() {
+            if collateral.pool == pool_key && collateral.is_lending {
                 // Calculate accrued yield
                 let accrued_yield = Self::calculate_accrued_yield(
                     pool,
@@ -99,7 +200,7 @@ impl YieldModule {
         
         // Update the collateral position to reflect claimed yield
         let index = collateral_index.unwrap();
-        let collateral = &mut user_position.collaterals[index];
+        let collateral = &mut user_position.collaterals_mut()[index];
         
         // When claiming yield, we need to update the scaled amount to match the current rate
         // This effectively resets the yield calculation
@@ -111,80 +212,15 @@ impl YieldModule {
             
         collateral.amount_scaled = new_scaled_amount;
         
-        // In a full implementation, we would now transfer the yield to the user's wallet
-        // Non-custodial: we transfer directly to the user's wallet, not to protocol-controlled accounts
-        
-        // Return the amount of yield claimed
+        // Return the amount of yield settled
         Ok(total_accrued_yield)
     }
     
-    /// Update lending yields for all deposits in a pool
-    pub fn update_pool_yields<'a>(
-        pool: &mut Account<'a, Pool>,
-        current_timestamp: i64
-    ) -> Result<()> {
-        // Verify the pool is non-custodial and immutable
-        require!(pool.immutable, OxygenError::PoolIsUpgradable);
-        require!(pool.admin_less, OxygenError::AdminOperationsNotSupported);
-        
-        if pool.last_updated == 0 || pool.last_updated == current_timestamp {
-            return Ok(());
-        }
-        
-        // Calculate time elapsed since last update
-        let time_elapsed = (current_timestamp - pool.last_updated) as u128;
-        if time_elapsed == 0 {
-            return Ok(());
-        }
-        
-        // Calculate the lending APY based on pool utilization
-        let utilization_rate = if pool.available_lending_supply > 0 {
-            (pool.total_borrows as u128)
-                .checked_mul(10000)
-                .unwrap_or(0) / (pool.available_lending_supply as u128)
-        } else {
-            0
-        };
-        
-        // Simple lending rate model
-        // Base yield is 80% of the borrow rate, scaled by utilization
-        let lending_rate = utilization_rate
-            .checked_mul(80)
-            .unwrap_or(0)
-            .checked_div(100)
-            .unwrap_or(0);
-            
-        // Update cumulative lending rate
-        // Formula: previous_rate + (lending_rate * time_elapsed / SECONDS_PER_YEAR)
-        const SECONDS_PER_YEAR: u128 = 31536000; // 365 * 24 * 60 * 60
-        
-        let rate_increase = lending_rate
-            .checked_mul(time_elapsed)
-            .unwrap_or(0)
-            .checked_div(SECONDS_PER_YEAR)
-            .unwrap_or(0);
-            
-        // Update pool's cumulative lending rate
-        // If this is the first update, initialize with 1 * 10^12 as base value
-        if pool.cumulative_lending_rate == 0 {
-            pool.cumulative_lending_rate = 1_000_000_000_000;
-        }
-        
-        pool.cumulative_lending_rate = pool.cumulative_lending_rate
-            .checked_add(rate_increase)
-            .unwrap_or(pool.cumulative_lending_rate);
-            
-        // Update timestamp
-        pool.last_updated = current_timestamp;
-        
-        Ok(())
-    }
-    
     /// Check if a user has any lending positions enabled
     pub fn has_lending_positions(
         user_position: &UserPosition,
     ) -> bool {
-        for collateral in &user_position.collaterals {
+        for collateral in user_position.collaterals() {
             if collateral.is_lending {
                 return true;
             }
@@ -197,7 +233,7 @@ impl YieldModule {
         user_position: &UserPosition,
         pool_key: &Pubkey
     ) -> bool {
-        for collateral in &user_position.collaterals {
+        for collateral in user_position.collaterals() {
             if &collateral.pool == pool_key && collateral.is_lending {
                 return true;
             }
@@ -207,14 +243,14 @@ impl YieldModule {
     
     /// Enable or disable lending for a specific deposit
     pub fn set_lending_status<'a>(
-        user_position: &mut Account<'a, UserPosition>,
+        user_position: &mut UserPosition,
         pool_key: &Pubkey,
         enable_lending: bool,
         pool_data: &HashMap<Pubkey, (u64, u64)>
     ) -> Result<()> {
         let mut found = false;
         
-        for collateral in &mut user_position.collaterals {
+        for collateral in user_position.collaterals_mut() {
             if collateral.pool == *pool_key {
                 collateral.is_lending = enable_lending;
                 found = true;
@@ -229,4 +265,85 @@ impl YieldModule {
         
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::position::tests::collateral;
+    
+    fn signer(key: &'static Pubkey) -> Signer<'static> {
+        let info = AccountInfo::new(
+            key,
+            true,
+            false,
+            Box::leak(Box::new(0)),
+            Box::leak(Box::new([])),
+            &crate::ID,
+            false,
+            0
+        );
+        Signer::try_from(&info).unwrap()
+    }
+    
+    // 1000 tokens lent at a 1.0 lending rate that has since grown to 1.1
+    fn lending_setup(owner: Pubkey) -> (Account<'static, Pool>, UserPosition) {
+        let pool = Pool {
+            cumulative_lending_rate: 1_100_000_000_000,
+            last_updated: 1,
+            total_deposits: 1000,
+            total_lent: 1000,
+            available_lending_supply: 1000,
+            immutable: true,
+            admin_less: true,
+            ..Default::default()
+        }.into_test_account();
+        
+        let mut lent = collateral(pool.key(), 1000);
+        lent.is_lending = true;
+        let mut position = UserPosition::with_positions(&[lent], &[]);
+        position.owner = owner;
+        (pool, position)
+    }
+    
+    #[test]
+    fn compounding_matches_claiming_and_redepositing() {
+        let owner: &'static Pubkey = Box::leak(Box::new(Pubkey::new_unique()));
+        let user = signer(owner);
+        
+        let (mut compounded_pool, mut compounded_position) = lending_setup(*owner);
+        let compounded = YieldModule::compound_yield(&mut compounded_pool, &mut compounded_position, &user, 2).unwrap();
+        
+        // Claim the yield, then deposit it again for lending the way `deposit` does
+        let (mut redeposited_pool, mut redeposited_position) = lending_setup(*owner);
+        let claimed = YieldModule::settle_yield(&redeposited_pool, &mut redeposited_position, &user, 2).unwrap();
+        let scaled = redeposited_pool.deposit_to_scaled(claimed).unwrap();
+        redeposited_position.add_collateral(redeposited_pool.key(), claimed, scaled).unwrap();
+        redeposited_pool.add_lent_deposit(claimed).unwrap();
+        
+        assert_eq!(compounded, 100);
+        assert_eq!(claimed, compounded);
+        
+        assert_eq!(compounded_pool.total_deposits, redeposited_pool.total_deposits);
+        assert_eq!(compounded_pool.total_lent, redeposited_pool.total_lent);
+        assert_eq!(compounded_pool.available_lending_supply, redeposited_pool.available_lending_supply);
+        
+        let (compounded_entry, redeposited_entry) = (compounded_position.collaterals()[0], redeposited_position.collaterals()[0]);
+        assert_eq!(compounded_entry.amount_deposited, redeposited_entry.amount_deposited);
+        assert_eq!(compounded_entry.amount_scaled, redeposited_entry.amount_scaled);
+    }
+    
+    #[test]
+    fn compounded_deposit_can_be_withdrawn_by_last_lender() {
+        let owner: &'static Pubkey = Box::leak(Box::new(Pubkey::new_unique()));
+        let (mut pool, mut position) = lending_setup(*owner);
+        
+        YieldModule::compound_yield(&mut pool, &mut position, &signer(owner), 2).unwrap();
+        
+        // Withdrawing the whole grown deposit is what used to underflow total_lent
+        let deposited = position.collaterals()[0].amount_deposited;
+        assert_eq!(deposited, 1100);
+        assert_eq!(pool.total_lent.checked_sub(deposited), Some(0));
+        assert_eq!(pool.total_deposits.checked_sub(deposited), Some(0));
+    }
 }
\ No newline at end of file
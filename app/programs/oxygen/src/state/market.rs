@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use crate::errors::OxygenError;
+use crate::instructions::OrderSide;
 
 #[account]
 pub struct MarketInfo {
@@ -6,10 +8,23 @@ pub struct MarketInfo {
     pub asset_mint: Pubkey,              // Base token mint
     pub quote_mint: Pubkey,              // Quote token mint
     pub oracle: Pubkey,                  // Price oracle address
+    pub last_oracle_price: u64,          // Last recorded market oracle price
+    pub max_oracle_deviation: u64,       // Max market vs pool oracle price deviation (bps)
     pub optimal_leverage: u64,           // Recommended max leverage
     pub max_leverage: u64,               // Maximum allowed leverage
     pub liquidation_fee: u64,            // Fee during liquidations
     pub maintenance_margin_ratio: u64,   // Min required margin
+    pub min_margin: u64,                 // Minimum margin per position
+    pub min_position_value: u64,         // Minimum notional per position
+    pub long_open_interest: u64,         // Aggregate notional of open long positions
+    pub short_open_interest: u64,        // Aggregate notional of open short positions
+    pub max_open_interest: u64,          // Max aggregate notional allowed on each side
+    pub funding_pool_balance: u64,       // Funding paid by one side not yet received by the other
+    pub realized_loss_balance: u64,      // Trading losses collected from positions, not yet paid out as profit
+    pub max_funding_rate: u64,           // Funding rate at fully one-sided open interest (millionths per hour)
+    pub funding_rate: i64,               // Current funding rate, positive = longs pay shorts (millionths per hour)
+    pub cumulative_funding_index: i64,   // Sum of funding rate times elapsed seconds since the market opened
+    pub last_funding_update: i64,        // Time the funding index was last accrued
     pub bump: u8,                        // PDA bump
 }
 
@@ -20,10 +35,23 @@ impl MarketInfo {
         32 + // asset_mint
         32 + // quote_mint
         32 + // oracle
+        8 + // last_oracle_price
+        8 + // max_oracle_deviation
         8 + // optimal_leverage
         8 + // max_leverage
         8 + // liquidation_fee
         8 + // maintenance_margin_ratio
+        8 + // min_margin
+        8 + // min_position_value
+        8 + // long_open_interest
+        8 + // short_open_interest
+        8 + // max_open_interest
+        8 + // funding_pool_balance
+        8 + // realized_loss_balance
+        8 + // max_funding_rate
+        8 + // funding_rate
+        8 + // cumulative_funding_index
+        8 + // last_funding_update
         1   // bump
     }
     
@@ -31,6 +59,80 @@ impl MarketInfo {
         requested_leverage <= self.max_leverage
     }
     
+    /// Accrue the current funding rate into the funding index up to `now`
+    pub fn accrue_funding(&mut self, now: i64) -> Result<()> {
+        let elapsed = now.saturating_sub(self.last_funding_update);
+        if elapsed <= 0 {
+            return Ok(());
+        }
+        
+        let accrued = self.funding_rate
+            .checked_mul(elapsed)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        self.cumulative_funding_index = self.cumulative_funding_index
+            .checked_add(accrued)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.last_funding_update = now;
+        
+        Ok(())
+    }
+    
+    /// Re-derive the funding rate from the open interest skew, so the crowded side
+    /// pays the other, scaled up to `max_funding_rate` when all open interest is on one side
+    fn refresh_funding_rate(&mut self) {
+        let long = self.long_open_interest as i128;
+        let short = self.short_open_interest as i128;
+        let total = long + short;
+        
+        self.funding_rate = if total == 0 {
+            0
+        } else {
+            // |long - short| <= total, so the rate never exceeds max_funding_rate
+            ((self.max_funding_rate as i128) * (long - short) / total) as i64
+        };
+    }
+    
+    /// Add a new position's notional to its side, rejecting it if the side's cap would be exceeded
+    pub fn increase_open_interest(&mut self, side: OrderSide, position_value: u64) -> Result<()> {
+        // Funding up to now accrues at the rate the old open interest set
+        self.accrue_funding(Clock::get()?.unix_timestamp)?;
+        
+        let open_interest = match side {
+            OrderSide::Buy => &mut self.long_open_interest,
+            OrderSide::Sell => &mut self.short_open_interest,
+        };
+        
+        let new_open_interest = open_interest
+            .checked_add(position_value)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        require!(
+            new_open_interest <= self.max_open_interest,
+            OxygenError::OpenInterestCapExceeded
+        );
+        
+        *open_interest = new_open_interest;
+        self.refresh_funding_rate();
+        
+        Ok(())
+    }
+    
+    /// Remove a closed or liquidated position's notional from its side
+    pub fn decrease_open_interest(&mut self, side: OrderSide, position_value: u64) -> Result<()> {
+        self.accrue_funding(Clock::get()?.unix_timestamp)?;
+        
+        let open_interest = match side {
+            OrderSide::Buy => &mut self.long_open_interest,
+            OrderSide::Sell => &mut self.short_open_interest,
+        };
+        
+        *open_interest = open_interest.saturating_sub(position_value);
+        self.refresh_funding_rate();
+        
+        Ok(())
+    }
+    
     pub fn calculate_margin_requirement(&self, position_size: u64, price: u64) -> Result<u64> {
         // Calculate position value
         let position_value = (position_size as u128)
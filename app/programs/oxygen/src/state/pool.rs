@@ -1,33 +1,66 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022;
+use crate::errors::OxygenError;
+use crate::modules::interest::InterestRateModel;
 
 #[account]
+#[derive(Default)]
 pub struct Pool {
     pub asset_mint: Pubkey,              // Token mint address
     pub asset_reserve: Pubkey,           // Pool's token account
     pub total_deposits: u64,             // Total deposits in the pool
     pub total_borrows: u64,              // Total borrows from the pool
+    pub debt_ceiling: u64,               // Max total borrows allowed (0 = unlimited)
     pub available_lending_supply: u64,   // Amount available to be lent out
     pub cumulative_borrow_rate: u128,    // Accumulated borrow rate
     pub cumulative_lending_rate: u128,   // Accumulated lending rate
     pub last_updated: i64,               // Last update timestamp
     pub optimal_utilization: u64,        // Target utilization rate
+    pub base_rate: u64,                  // Borrow rate at zero utilization (bps)
+    pub slope1: u64,                     // Borrow rate added up to optimal utilization (bps)
+    pub slope2: u64,                     // Borrow rate added from optimal to full utilization (bps), or to the second kink
+    pub interest_rate_model: InterestRateModelKind, // Shape of the borrow rate curve
+    pub second_optimal_utilization: u64, // Second kink for three-slope models (bps)
+    pub slope3: u64,                     // Borrow rate added from the second kink to full utilization (bps)
     pub loan_to_value: u64,              // Max LTV ratio for this asset
     pub liquidation_threshold: u64,      // Liquidation threshold
     pub liquidation_bonus: u64,          // Bonus for liquidators
+    pub liquidation_penalty: u64,        // Extra collateral seized into reserves on liquidation
+    pub liquidation_grace_period: i64,   // Seconds a borrower stays unhealthy before liquidation is allowed
+    pub min_open_health_factor: u64,     // Min health factor a new borrow may leave (bps)
+    pub min_withdraw_health_factor: u64, // Min health factor a collateral withdrawal may leave (bps)
     pub borrow_fee: u64,                 // Fee for borrowing
     pub flash_loan_fee: u64,             // Fee for flash loans
     pub host_fee_percentage: u8,         // Host fee percentage
     pub protocol_fee_percentage: u8,     // Protocol fee percentage
+    pub accrued_protocol_fees: u64,      // Protocol share of borrow fees held in the reserve
+    pub accrued_host_fees: u64,          // Host share of borrow fees held in the reserve
+    pub pool_deposit_cap: u64,           // Max total deposits in the pool (0 = unlimited)
+    pub per_user_deposit_cap: u64,       // Max deposits a single user may hold in the pool (0 = unlimited)
     pub lending_enabled: bool,           // Whether lending is enabled
     pub max_lending_ratio: u64,          // Maximum % of deposits for lending
     pub min_lending_duration: u64,       // Minimum duration for lending
     pub lending_fee: u64,                // Fee for lending (bps)
+    pub lending_withdrawal_fee: u64,     // Fee on lending withdrawals kept in reserves (bps)
     pub lending_interest_share: u64,     // % of interest to lenders
     pub total_lent: u64,                 // Total amount being lent
+    pub bad_debt: u64,                   // Trading losses the protocol absorbed beyond users' collateral
+    pub insurance_fund: u64,             // Reserve tokens set aside to cover bad debt before lenders
+    pub insurance_fund_share: u64,       // Share of liquidation bonuses paid into the insurance fund (bps)
     pub operation_state_flags: u8,       // Flags for pausing operations
     pub price_oracle: Pubkey,            // Oracle account for price feeds
     pub last_oracle_price: u64,          // Last recorded oracle price
     pub last_oracle_update: i64,         // Timestamp of last oracle update
+    pub max_oracle_staleness: i64,       // Max oracle price age (seconds) before liquidations are blocked
+    pub max_confidence_bps: u64,         // Max oracle confidence interval relative to price (bps)
+    pub twap_enabled: bool,              // Whether liquidations use the TWAP instead of the spot price
+    pub price_samples: [u64; 8],         // Ring of the most recent oracle prices
+    pub price_sample_count: u8,          // Number of filled slots in price_samples
+    pub price_sample_index: u8,          // Slot the next oracle price is written to
+    pub max_price_change_bps: u64,       // Max oracle price move in one update before the pool pauses (bps, 0 = off)
+    pub circuit_breaker_cooldown: i64,   // Seconds the pool stays paused after the circuit breaker trips
+    pub paused_until: i64,               // When a circuit breaker pause lifts (0 = not paused by the breaker)
     pub bump: u8,                        // PDA bump
 
     /// Track individual user deposits in a PDA-based mapping
@@ -40,86 +73,209 @@ pub struct Pool {
     pub admin_less: bool,
 }
 
+/// Borrow rate curve used by a pool
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum InterestRateModelKind {
+    TwoSlope,   // base_rate, slope1 up to optimal_utilization, slope2 beyond it
+    ThreeSlope, // Adds slope3 beyond second_optimal_utilization
+    Fixed,      // Always base_rate
+}
+
+impl Default for InterestRateModelKind {
+    fn default() -> Self {
+        InterestRateModelKind::TwoSlope
+    }
+}
+
 impl Pool {
+    /// Bit in `operation_state_flags` that pauses pool operations
+    pub const OPERATION_PAUSED_FLAG: u8 = 0x1;
+    
+    /// Number of oracle prices kept for the TWAP
+    pub const PRICE_SAMPLE_SLOTS: usize = 8;
+    
     pub fn space() -> usize {
         8 + // Anchor account discriminator
         32 + // asset_mint
         32 + // asset_reserve
         8 + // total_deposits
         8 + // total_borrows
+        8 + // debt_ceiling
         8 + // available_lending_supply
         16 + // cumulative_borrow_rate
         16 + // cumulative_lending_rate
         8 + // last_updated
         8 + // optimal_utilization
+        8 + // base_rate
+        8 + // slope1
+        8 + // slope2
+        1 + // interest_rate_model
+        8 + // second_optimal_utilization
+        8 + // slope3
         8 + // loan_to_value
         8 + // liquidation_threshold
         8 + // liquidation_bonus
+        8 + // liquidation_penalty
+        8 + // liquidation_grace_period
+        8 + // min_open_health_factor
+        8 + // min_withdraw_health_factor
         8 + // borrow_fee
         8 + // flash_loan_fee
         1 + // host_fee_percentage
         1 + // protocol_fee_percentage
+        8 + // accrued_protocol_fees
+        8 + // accrued_host_fees
+        8 + // pool_deposit_cap
+        8 + // per_user_deposit_cap
         1 + // lending_enabled
         8 + // max_lending_ratio
         8 + // min_lending_duration
         8 + // lending_fee
+        8 + // lending_withdrawal_fee
         8 + // lending_interest_share
         8 + // total_lent
+        8 + // bad_debt
+        8 + // insurance_fund
+        8 + // insurance_fund_share
          1 + // operation_state_flags
         32 + // price_oracle
         8 + // last_oracle_price
         8 + // last_oracle_update
+        8 + // max_oracle_staleness
+        8 + // max_confidence_bps
+        1 + // twap_enabled
+        8 * Self::PRICE_SAMPLE_SLOTS + // price_samples
+        1 + // price_sample_count
+        1 + // price_sample_index
+        8 + // max_price_change_bps
+        8 + // circuit_breaker_cooldown
+        8 + // paused_until
          1 + // bump
         32 + // user_deposits_authority
         1 + // immutable
         1   // admin_less
     }
 
-    pub fn update_rates(&mut self, current_timestamp: i64) -> Result<()> {
-        // Update interest rates based on pool utilization
-        if self.total_deposits == 0 {
-            return Ok(());
+    // Whether the stored oracle price is recent enough to act on
+    // Pools without an oracle have no stored price that could go stale
+    pub fn is_oracle_fresh(&self, current_timestamp: i64) -> bool {
+        if self.price_oracle == Pubkey::default() {
+            return true;
         }
+        
+        current_timestamp.saturating_sub(self.last_oracle_update) <= self.max_oracle_staleness
+    }
 
-        let utilization_rate = if self.total_deposits > 0 {
-            (self.total_borrows as u128).checked_mul(10000).unwrap_or(0) / (self.total_deposits as u128)
-        } else {
-            0
-        };
+    // Price used for health calculations: the stored oracle price, rejected once
+    // stale, or a 1:1 price ratio for pools without an oracle
+    pub fn health_price(&self, current_timestamp: i64) -> Result<u64> {
+        if self.price_oracle == Pubkey::default() {
+            return Ok(10000);
+        }
+        
+        require!(
+            self.is_oracle_fresh(current_timestamp),
+            OxygenError::StaleOracleData
+        );
+        
+        Ok(self.last_oracle_price)
+    }
 
-        // Simple interest rate model based on utilization
-        // More sophisticated models can be implemented later
-        let borrow_rate = if utilization_rate < (self.optimal_utilization as u128) {
-            // Below optimal: lower rate
-            utilization_rate.checked_mul(10).unwrap_or(0) / 100
-        } else {
-            // Above optimal: increase rate more aggressively
-            let base_rate = (self.optimal_utilization as u128).checked_mul(10).unwrap_or(0) / 100;
-            let excess_utilization = utilization_rate.checked_sub(self.optimal_utilization as u128).unwrap_or(0);
-            let excess_rate = excess_utilization.checked_mul(20).unwrap_or(0) / 100;
-            base_rate.checked_add(excess_rate).unwrap_or(0)
-        };
+    // Record a newly published oracle price in the TWAP ring, overwriting the oldest sample
+    pub fn record_price_sample(&mut self, price: u64) {
+        self.price_samples[self.price_sample_index as usize] = price;
+        self.price_sample_index = ((self.price_sample_index as usize + 1) % Self::PRICE_SAMPLE_SLOTS) as u8;
+        if (self.price_sample_count as usize) < Self::PRICE_SAMPLE_SLOTS {
+            self.price_sample_count += 1;
+        }
+    }
 
-        // Time elapsed since last update (in seconds)
-        let time_elapsed = (current_timestamp - self.last_updated) as u128;
-        
-        // Update cumulative borrow rate
-        // Formula: previous_rate + (borrow_rate * time_elapsed / SECONDS_PER_YEAR)
-        const SECONDS_PER_YEAR: u128 = 31536000; // 365 * 24 * 60 * 60
+    // Average of the recorded oracle prices, or the last price before any are recorded
+    pub fn twap_price(&self) -> u64 {
+        if self.price_sample_count == 0 {
+            return self.last_oracle_price;
+        }
         
-        let rate_increase = borrow_rate
-            .checked_mul(time_elapsed).unwrap_or(0)
-            .checked_div(SECONDS_PER_YEAR).unwrap_or(0);
-            
-        self.cumulative_borrow_rate = self.cumulative_borrow_rate
-            .checked_add(rate_increase).unwrap_or(self.cumulative_borrow_rate);
-            
-        // Update timestamp
-        self.last_updated = current_timestamp;
+        let count = self.price_sample_count as usize;
+        let sum: u128 = self.price_samples[..count].iter().map(|&price| price as u128).sum();
+        (sum / count as u128) as u64
+    }
+
+    // Price used to decide liquidations: the TWAP when enabled, so a single
+    // spiking oracle update can't make a position liquidatable on its own
+    pub fn liquidation_price(&self, current_timestamp: i64) -> Result<u64> {
+        let spot_price = self.health_price(current_timestamp)?;
+        if !self.twap_enabled || self.price_oracle == Pubkey::default() {
+            return Ok(spot_price);
+        }
         
+        Ok(self.twap_price())
+    }
+
+    // Re-assert the risk parameter invariants checked by init_pool before they feed
+    // a capacity decision: a borrow limit below the liquidation threshold, and a
+    // threshold no higher than the collateral's full value
+    pub fn validate_risk_params(&self) -> Result<()> {
+        require!(
+            self.loan_to_value < self.liquidation_threshold && self.liquidation_threshold <= 10000,
+            OxygenError::InvalidRiskParameters
+        );
         Ok(())
     }
 
+    // Whether the pool's asset is wrapped SOL under either token program, which
+    // lets users deposit and withdraw native SOL directly
+    pub fn is_native_sol(&self) -> bool {
+        self.asset_mint == spl_token::native_mint::ID
+            || self.asset_mint == spl_token_2022::native_mint::ID
+    }
+
+    // Borrow fee charged on `amount`, split into (total, host share, protocol share)
+    // Whatever isn't assigned to the host or protocol stays in the reserve for lenders
+    pub fn split_borrow_fee(&self, amount: u64) -> Result<(u64, u64, u64)> {
+        let fee = (amount as u128)
+            .checked_mul(self.borrow_fee as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(10000)
+            .ok_or(ErrorCode::MathOverflow)? as u64;
+        
+        let host_fee = (fee as u128)
+            .checked_mul(self.host_fee_percentage as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(100)
+            .ok_or(ErrorCode::MathOverflow)? as u64;
+        
+        let protocol_fee = (fee as u128)
+            .checked_mul(self.protocol_fee_percentage as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(100)
+            .ok_or(ErrorCode::MathOverflow)? as u64;
+        
+        Ok((fee, host_fee, protocol_fee))
+    }
+
+    // Fee charged on a lending withdrawal of `amount`, credited to the insurance fund
+    // The fee never leaves the reserve, so the fund is backed by the tokens kept back
+    pub fn charge_lending_withdrawal_fee(&mut self, amount: u64) -> Result<u64> {
+        let fee = (amount as u128)
+            .checked_mul(self.lending_withdrawal_fee as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(10000)
+            .ok_or(ErrorCode::MathOverflow)? as u64;
+        
+        self.insurance_fund = self.insurance_fund
+            .checked_add(fee)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        Ok(fee)
+    }
+
+    // Accrue interest into the cumulative borrow rate by compounding
+    // Borrow scaling divides by this rate, so it must grow multiplicatively
+    pub fn update_rates(&mut self, current_timestamp: i64) -> Result<()> {
+        InterestRateModel::update_cumulative_rate(self, current_timestamp)
+    }
+
     pub fn get_utilization_rate(&self) -> u64 {
         if self.total_deposits == 0 {
             return 0;
@@ -128,13 +284,22 @@ impl Pool {
         ((self.total_borrows as u128).checked_mul(10000).unwrap_or(0) / (self.total_deposits as u128)) as u64
     }
 
-    pub fn deposit_to_scaled(&self, amount: u64) -> Result<u128> {
-        // Convert deposit amount to scaled amount based on the current exchange rate
-        if self.total_deposits == 0 {
-            // First deposit, 1:1 ratio
-            return Ok(amount as u128);
+    // Share of lent funds currently drawn by borrowers, in basis points
+    // available_lending_supply tracks what remains lendable, so the borrowed
+    // portion is whatever has been lent but is no longer available
+    pub fn get_lending_utilization_rate(&self) -> u64 {
+        if self.total_lent == 0 {
+            return 0;
         }
         
+        let lent_and_borrowed = self.total_lent.saturating_sub(self.available_lending_supply);
+        
+        ((lent_and_borrowed as u128).checked_mul(10000).unwrap_or(0) / (self.total_lent as u128)) as u64
+    }
+
+    pub fn deposit_to_scaled(&self, amount: u64) -> Result<u128> {
+        // Convert deposit amount to scaled amount based on the current exchange rate
+        // The first deposit is scaled the same way so every balance shares one unit
         // Scale by cumulative lending rate
         // scaled_amount = amount * 10^12 / cumulative_lending_rate
         let scaled_amount = (amount as u128)
@@ -146,84 +311,26 @@ impl Pool {
         Ok(scaled_amount)
     }
     
-    pub fn update_utilization_rate(&mut self) -> Result<()> {
-        // Calculate the current utilization rate of the pool
-        if self.total_deposits == 0 {
-            // No deposits, zero utilization
-            return Ok(());
-        }
+    /// Account for `amount` deposited into the pool and lent out
+    pub fn add_lent_deposit(&mut self, amount: u64) -> Result<()> {
+        self.total_deposits = self.total_deposits
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
         
-        // Also update available for lending based on lending flags
-        // This function should be called after deposit/withdraw/borrow/repay operations
-        let lending_utilization = if self.available_lending_supply > 0 {
-            (self.total_borrows as u128)
-                .checked_mul(10000)
-                .unwrap_or(0) / (self.available_lending_supply as u128)
-        } else {
-            0
-        };
+        self.available_lending_supply = self.available_lending_supply
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
         
-        // Update lending rate based on lending utilization
-        // This determines the yield distributed to lenders
-        if self.last_updated > 0 {
-            let utilization_factor = std::cmp::min(lending_utilization as u64, 10000);
-            let base_lending_rate = (utilization_factor as u128)
-                .checked_mul(8) // 80% of borrow rate goes to lenders
-                .unwrap_or(0)
-                .checked_div(10)
-                .unwrap_or(0);
-                
-            // Update cumulative lending rate
-            const SECONDS_PER_YEAR: u128 = 31536000; // 365 * 24 * 60 * 60
-            let time_elapsed = (Clock::get().unwrap().unix_timestamp - self.last_updated) as u128;
-            
-            let rate_increase = base_lending_rate
-                .checked_mul(time_elapsed).unwrap_or(0)
-                .checked_div(SECONDS_PER_YEAR).unwrap_or(0);
-                
-            self.cumulative_lending_rate = self.cumulative_lending_rate
-                .checked_add(rate_increase).unwrap_or(self.cumulative_lending_rate);
-        }
+        self.total_lent = self.total_lent
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
         
         Ok(())
     }
-
+    
     // Get the current borrow interest rate for the pool
     pub fn get_borrow_rate(&self) -> Result<u64> {
-        let utilization_rate = self.get_utilization_rate();
-        
-        // Using the same interest model as in update_rates
-        let borrow_rate = if utilization_rate < self.optimal_utilization {
-            // Below optimal: lower rate
-            (utilization_rate as u128)
-                .checked_mul(10)
-                .unwrap_or(0)
-                .checked_div(100)
-                .unwrap_or(0) as u64
-        } else {
-            // Above optimal: increase rate more aggressively
-            let base_rate = (self.optimal_utilization as u128)
-                .checked_mul(10)
-                .unwrap_or(0)
-                .checked_div(100)
-                .unwrap_or(0) as u64;
-                
-            let excess_utilization = utilization_rate
-                .checked_sub(self.optimal_utilization)
-                .unwrap_or(0);
-                
-            let excess_rate = (excess_utilization as u128)
-                .checked_mul(20)
-                .unwrap_or(0)
-                .checked_div(100)
-                .unwrap_or(0) as u64;
-                
-            base_rate
-                .checked_add(excess_rate)
-                .ok_or(ErrorCode::MathOverflow)?
-        };
-        
-        Ok(borrow_rate)
+        InterestRateModel::calculate_borrow_rate(self, self.get_utilization_rate())
     }
     
     // Get the current lending interest rate for the pool
@@ -241,6 +348,53 @@ impl Pool {
         Ok(lending_rate)
     }
 
+    /// Reject operations while the pool's pause bit is set
+    /// Pauses set by the circuit breaker lift on their own once the cooldown ends
+    ///
+    /// Called at the start of every instruction that moves a pool's funds or changes
+    /// its accounting, including repay and liquidate, since both act on oracle prices
+    /// that may be the reason for the pause. Only deposit_to_insurance_fund, which can
+    /// only add to the reserve, and refresh_pool_price, which records the circuit
+    /// breaker itself, stay enabled while a pool is paused
+    pub fn ensure_not_paused(&self) -> Result<()> {
+        if self.operation_state_flags & Self::OPERATION_PAUSED_FLAG != 0 {
+            require!(
+                self.paused_until != 0 && Clock::get()?.unix_timestamp >= self.paused_until,
+                OxygenError::OperationPaused
+            );
+        }
+        Ok(())
+    }
+
+    // Whether a new oracle price moved further from the last recorded price than
+    // the circuit breaker allows
+    pub fn exceeds_price_change_limit(&self, new_price: u64) -> bool {
+        if self.max_price_change_bps == 0 || self.last_oracle_price == 0 {
+            return false;
+        }
+        
+        let price_change = (new_price as u128).abs_diff(self.last_oracle_price as u128);
+        let price_change_bps = price_change * 10000 / self.last_oracle_price as u128;
+        price_change_bps > self.max_price_change_bps as u128
+    }
+
+    // Pause the pool for the circuit breaker cooldown
+    pub fn trip_circuit_breaker(&mut self, current_timestamp: i64) -> Result<()> {
+        self.operation_state_flags |= Self::OPERATION_PAUSED_FLAG;
+        self.paused_until = current_timestamp
+            .checked_add(self.circuit_breaker_cooldown)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // Clear a circuit breaker pause whose cooldown has ended
+    pub fn clear_expired_pause(&mut self, current_timestamp: i64) {
+        if self.paused_until != 0 && current_timestamp >= self.paused_until {
+            self.operation_state_flags &= !Self::OPERATION_PAUSED_FLAG;
+            self.paused_until = 0;
+        }
+    }
+
     /// Verify a transaction is authorized by the rightful owner
     pub fn verify_owner_signed(&self, signer: &Signer) -> Result<()> {
         require!(
@@ -255,4 +409,166 @@ impl Pool {
         require!(self.immutable, OxygenError::PoolIsUpgradable);
         Ok(())
     }
+}
+
+#[cfg(test)]
+impl Pool {
+    /// Pool owned by this program at a fresh address, for code that needs its key
+    pub(crate) fn into_test_account(self) -> Account<'static, Pool> {
+        let mut data = Vec::with_capacity(Self::space());
+        self.try_serialize(&mut data).unwrap();
+        
+        let info = AccountInfo::new(
+            Box::leak(Box::new(Pubkey::new_unique())),
+            false,
+            true,
+            Box::leak(Box::new(0)),
+            Box::leak(data.into_boxed_slice()),
+            &crate::ID,
+            false,
+            0
+        );
+        Account::try_from(&info).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn oracle_pool(last_oracle_price: u64, last_oracle_update: i64) -> Pool {
+        Pool {
+            price_oracle: Pubkey::new_unique(),
+            last_oracle_price,
+            last_oracle_update,
+            max_oracle_staleness: 60,
+            ..Pool::default()
+        }
+    }
+    
+    #[test]
+    fn space_matches_serialized_size() {
+        let mut data = Vec::new();
+        Pool::default().try_serialize(&mut data).unwrap();
+        
+        assert_eq!(data.len(), Pool::space());
+    }
+    
+    #[test]
+    fn health_price_rejects_stale_oracle() {
+        let pool = oracle_pool(25_000, 1000);
+        
+        assert_eq!(pool.health_price(1060).unwrap(), 25_000);
+        assert_eq!(pool.health_price(1061).unwrap_err(), OxygenError::StaleOracleData.into());
+        
+        // Pools without an oracle are priced 1:1 and never go stale
+        assert_eq!(Pool::default().health_price(i64::MAX).unwrap(), 10000);
+    }
+    
+    #[test]
+    fn twap_averages_the_most_recent_samples() {
+        let mut pool = oracle_pool(100, 1000);
+        assert_eq!(pool.twap_price(), 100);
+        
+        // The first two of ten samples fall out of the eight-slot ring
+        for price in [1, 1, 10, 10, 10, 10, 20, 20, 20, 20] {
+            pool.record_price_sample(price);
+        }
+        assert_eq!(pool.price_sample_count as usize, Pool::PRICE_SAMPLE_SLOTS);
+        assert_eq!(pool.twap_price(), 15);
+        
+        assert_eq!(pool.liquidation_price(1000).unwrap(), 100);
+        pool.twap_enabled = true;
+        assert_eq!(pool.liquidation_price(1000).unwrap(), 15);
+    }
+    
+    #[test]
+    fn borrow_fee_is_split_between_host_and_protocol() {
+        let pool = Pool {
+            borrow_fee: 100,
+            host_fee_percentage: 20,
+            protocol_fee_percentage: 30,
+            ..Pool::default()
+        };
+        
+        // 1% of 50_000, of which 20% goes to the host and 30% to the protocol
+        assert_eq!(pool.split_borrow_fee(50_000).unwrap(), (500, 100, 150));
+    }
+    
+    #[test]
+    fn utilization_rates() {
+        let pool = Pool {
+            total_deposits: 1000,
+            total_borrows: 250,
+            total_lent: 800,
+            available_lending_supply: 600,
+            ..Pool::default()
+        };
+        
+        assert_eq!(pool.get_utilization_rate(), 2500);
+        assert_eq!(pool.get_lending_utilization_rate(), 2500);
+        assert_eq!(Pool::default().get_utilization_rate(), 0);
+        assert_eq!(Pool::default().get_lending_utilization_rate(), 0);
+    }
+    
+    #[test]
+    fn deposits_scale_by_the_lending_rate() {
+        let pool = Pool { cumulative_lending_rate: 1_250_000_000_000, ..Pool::default() };
+        
+        assert_eq!(pool.deposit_to_scaled(1000).unwrap(), 800);
+    }
+    
+    #[test]
+    fn lent_deposits_count_towards_each_total() {
+        let mut pool = Pool::default();
+        pool.add_lent_deposit(500).unwrap();
+        
+        assert_eq!(pool.total_deposits, 500);
+        assert_eq!(pool.available_lending_supply, 500);
+        assert_eq!(pool.total_lent, 500);
+    }
+    
+    #[test]
+    fn lending_rate_is_a_share_of_the_borrow_rate() {
+        let pool = Pool {
+            interest_rate_model: InterestRateModelKind::Fixed,
+            base_rate: 1000,
+            lending_interest_share: 8000,
+            ..Pool::default()
+        };
+        
+        assert_eq!(pool.get_lending_rate().unwrap(), 800);
+    }
+    
+    #[test]
+    fn circuit_breaker_trips_on_large_moves_and_expires() {
+        let mut pool = Pool {
+            last_oracle_price: 10_000,
+            max_price_change_bps: 1000,
+            circuit_breaker_cooldown: 300,
+            ..Pool::default()
+        };
+        
+        assert!(!pool.exceeds_price_change_limit(11_000));
+        assert!(pool.exceeds_price_change_limit(11_001));
+        assert!(pool.exceeds_price_change_limit(8_999));
+        
+        pool.trip_circuit_breaker(1000).unwrap();
+        assert_eq!(pool.operation_state_flags & Pool::OPERATION_PAUSED_FLAG, Pool::OPERATION_PAUSED_FLAG);
+        
+        pool.clear_expired_pause(1299);
+        assert_eq!(pool.paused_until, 1300);
+        pool.clear_expired_pause(1300);
+        assert_eq!(pool.operation_state_flags, 0);
+        assert!(pool.ensure_not_paused().is_ok());
+    }
+    
+    #[test]
+    fn risk_params_need_ltv_below_threshold() {
+        let pool = |loan_to_value, liquidation_threshold| Pool { loan_to_value, liquidation_threshold, ..Pool::default() };
+        
+        assert!(pool(7500, 8000).validate_risk_params().is_ok());
+        assert!(pool(8000, 8000).validate_risk_params().is_err());
+        assert!(pool(7500, 10001).validate_risk_params().is_err());
+    }
 }
\ No newline at end of file
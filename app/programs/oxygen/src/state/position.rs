@@ -1,21 +1,83 @@
 use anchor_lang::prelude::*;
 use std::collections::HashMap;
+use crate::errors::OxygenError;
+use crate::state::Pool;
 
 /// User position in the protocol
-#[account]
-#[derive(Default)]
+/// Zero-copy, so each list lives in a fixed-size array of which only the first
+/// `*_count` slots are in use; read them through the matching accessor
+#[account(zero_copy(unsafe))]
+#[repr(C)]
 pub struct UserPosition {
     pub owner: Pubkey,                              // User wallet
-    pub collaterals: Vec<CollateralPosition>,       // User collaterals
-    pub borrows: Vec<BorrowPosition>,               // User borrows
-    pub leveraged_positions: Vec<LeveragedPosition>, // User's leveraged trading positions
+    pub collateral_slots: [CollateralPosition; 10], // User collaterals, see `collaterals`
+    pub borrow_slots: [BorrowPosition; 10],         // User borrows, see `borrows`
+    pub leveraged_position_slots: [LeveragedPosition; 10], // User's leveraged trading positions, see `leveraged_positions`
+    pub locked_trading_margin: u64,                 // Collateral locked as margin for open trades
+    pub open_orders_slots: [OpenOrdersAccount; 10], // Serum open orders account per market, see `open_orders_accounts`
+    pub position_history_slots: [ClosedPosition; 10], // Most recent closed and liquidated leveraged positions, see `position_history`
     pub health_factor: u64,                         // Current health factor
+    pub unhealthy_since: i64,                       // When health last fell below 1.0 (0 = healthy)
     pub last_updated: i64,                          // Last update timestamp
+    pub window_start: i64,                          // Start of the current rate limit window
+    pub collateral_count: u8,                       // Collateral slots in use
+    pub borrow_count: u8,                           // Borrow slots in use
+    pub leveraged_position_count: u8,               // Leveraged position slots in use
+    pub open_orders_count: u8,                      // Open orders slots in use
+    pub position_history_count: u8,                 // Position history slots in use
+    pub recent_action_count: u8,                    // State-changing actions in the current rate limit window
     pub bump: u8,                                   // PDA bump
 }
 
+/// Borsh-serialized layout of `UserPosition` used before it became zero-copy
+/// Only read by `migrate_user_position` to carry existing positions over
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyUserPosition {
+    pub owner: Pubkey,
+    pub collaterals: Vec<CollateralPosition>,
+    pub borrows: Vec<LegacyBorrowPosition>,
+    pub leveraged_positions: Vec<LegacyLeveragedPosition>,
+    pub locked_trading_margin: u64,
+    pub open_orders_accounts: Vec<OpenOrdersAccount>,
+    pub position_history: Vec<ClosedPosition>,
+    pub health_factor: u64,
+    pub unhealthy_since: i64,
+    pub last_updated: i64,
+    pub bump: u8,
+}
+
+/// Borsh-serialized layout of `BorrowPosition` used before borrows recorded their origination
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyBorrowPosition {
+    pub pool: Pubkey,
+    pub amount_borrowed: u64,
+    pub amount_scaled: u128,
+    pub interest_rate: u64,
+}
+
+/// Borsh-serialized layout of `LeveragedPosition` used before positions tracked funding
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyLeveragedPosition {
+    pub id: u64,
+    pub market: Pubkey,
+    pub side: crate::instructions::OrderSide,
+    pub size: u64,
+    pub entry_price: u64,
+    pub leverage: u64,
+    pub margin_used: u64,
+    pub margin_mode: MarginMode,
+    pub position_value: u64,
+    pub liquidation_price: u64,
+    pub stop_loss_price: u64,
+    pub take_profit_price: u64,
+    pub timestamp: i64,
+    pub status: PositionStatus,
+    pub client_id: u64,
+}
+
 /// Status of a leveraged position
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+#[repr(u8)]
 pub enum PositionStatus {
     Open,       // Position is open
     Closed,     // Position was closed by the user
@@ -28,8 +90,24 @@ impl Default for PositionStatus {
     }
 }
 
+/// How much of the account a leveraged position can draw on
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum MarginMode {
+    Cross,      // Losses can reach the account's wider collateral and count towards account health
+    Isolated,   // Losses are limited to the position's own margin
+}
+
+impl Default for MarginMode {
+    fn default() -> Self {
+        MarginMode::Cross
+    }
+}
+
 /// Leveraged trading position
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[derive(AnchorSerialize, AnchorDeserialize, Default)]
 pub struct LeveragedPosition {
     pub id: u64,                     // Unique position identifier
     pub market: Pubkey,              // Market address (Serum DEX market)
@@ -38,15 +116,85 @@ pub struct LeveragedPosition {
     pub entry_price: u64,            // Entry price
     pub leverage: u64,               // Leverage used (in basis points, 10000 = 1x)
     pub margin_used: u64,            // Margin used for this position
+    pub margin_mode: MarginMode,     // Cross or isolated margin
     pub position_value: u64,         // Total value of the position
     pub liquidation_price: u64,      // Price at which position will be liquidated
+    pub stop_loss_price: u64,        // Price that closes the position at a loss (0 = unset)
+    pub take_profit_price: u64,      // Price that closes the position at a profit (0 = unset)
     pub timestamp: i64,              // Time when position was opened
     pub status: PositionStatus,      // Current status of the position
     pub client_id: u64,              // Client order ID for identification
+    pub funding_index: i64,          // Market funding index funding was last settled at
+}
+
+/// Read-only summary of a leveraged position, see `get_position_details`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PositionView {
+    pub id: u64,                     // Unique position identifier
+    pub market: Pubkey,              // Market address (Serum DEX market)
+    pub side: crate::instructions::OrderSide, // Buy or sell side
+    pub size: u64,                   // Position size
+    pub entry_price: u64,            // Entry price
+    pub leverage: u64,               // Leverage used (in basis points, 10000 = 1x)
+    pub margin_used: u64,            // Margin used for this position
+    pub liquidation_price: u64,      // Price at which position will be liquidated
+    pub mark_price: u64,             // Price the unrealized PnL was computed at
+    pub unrealized_pnl: i64,         // PnL if the position closed at the mark price
+}
+
+/// Record of a leveraged position that was fully closed or liquidated
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[derive(AnchorSerialize, AnchorDeserialize, Default)]
+pub struct ClosedPosition {
+    pub id: u64,                     // Identifier the position had while open
+    pub market: Pubkey,              // Market address (Serum DEX market)
+    pub side: crate::instructions::OrderSide, // Buy or sell side
+    pub size: u64,                   // Position size when it was closed
+    pub entry_price: u64,            // Entry price
+    pub exit_price: u64,             // Execution or liquidation price
+    pub leverage: u64,               // Leverage used (in basis points, 10000 = 1x)
+    pub realized_pnl: i64,           // PnL settled against the user's collateral on close
+    pub close_reason: PositionStatus, // Closed by the user or liquidated
+    pub closed_at: i64,              // Time when the position was closed
+}
+
+impl ClosedPosition {
+    pub fn from_position(
+        position: &LeveragedPosition,
+        exit_price: u64,
+        realized_pnl: i64,
+        close_reason: PositionStatus,
+        closed_at: i64
+    ) -> Self {
+        Self {
+            id: position.id,
+            market: position.market,
+            side: position.side,
+            size: position.size,
+            entry_price: position.entry_price,
+            exit_price,
+            leverage: position.leverage,
+            realized_pnl,
+            close_reason,
+            closed_at,
+        }
+    }
+}
+
+/// Serum open orders account created for one market
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[derive(AnchorSerialize, AnchorDeserialize, Default)]
+pub struct OpenOrdersAccount {
+    pub market: Pubkey,              // Serum market address
+    pub open_orders: Pubkey,         // Open orders PDA for this market
 }
 
 /// Collateral position
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[derive(AnchorSerialize, AnchorDeserialize, Default)]
 pub struct CollateralPosition {
     pub pool: Pubkey,                // Pool address
     pub amount_deposited: u64,       // Deposited amount
@@ -56,53 +204,420 @@ pub struct CollateralPosition {
     pub deposit_timestamp: i64,      // When the position was created/modified
 }
 
-/// Borrow position
+/// Components of a position's health factor, see `UserPosition::compute_health_breakdown`
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct HealthBreakdown {
+    pub weighted_collateral_value: u128, // Collateral value weighted by liquidation thresholds
+    pub borrowed_value: u128,        // Value of borrows including known accrued interest
+    pub leveraged_risk: u128,        // Position value minus margin across open leveraged positions
+    pub locked_trading_margin: u64,  // Collateral locked as trading margin (informational)
+    pub health_factor: u64,          // Final health factor (10000 = 1.0)
+}
+
+impl HealthBreakdown {
+    /// Health factor once `additional_borrow_value` more debt is taken on,
+    /// reusing the summed values instead of walking the position again
+    pub fn health_factor_with_borrow(&self, additional_borrow_value: u128) -> Result<u64> {
+        let total_risk = self.borrowed_value
+            .checked_add(self.leveraged_risk)
+            .ok_or(OxygenError::BorrowValueOverflow)?
+            .checked_add(additional_borrow_value)
+            .ok_or(OxygenError::BorrowValueOverflow)?;
+        
+        health_factor_for(self.weighted_collateral_value, total_risk)
+    }
+}
+
+// Health factor = (collateral value * liquidation threshold) / risk, scaled by
+// 10000 to preserve precision, or u64::MAX when there is nothing at risk
+fn health_factor_for(weighted_collateral_value: u128, total_risk: u128) -> Result<u64> {
+    if total_risk == 0 {
+        return Ok(u64::MAX); // No borrows, so perfectly healthy
+    }
+    
+    Ok((weighted_collateral_value
+        .checked_mul(10000)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(total_risk)
+        .ok_or(ErrorCode::MathOverflow)?) as u64)
+}
+
+/// Borrow position
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[derive(AnchorSerialize, AnchorDeserialize, Default)]
 pub struct BorrowPosition {
     pub pool: Pubkey,                // Pool address
     pub amount_borrowed: u64,        // Borrowed amount
     pub amount_scaled: u128,         // Scaled amount (for interest)
     pub interest_rate: u64,          // Interest rate at time of borrow
+    pub originated_at: i64,          // When the borrow was taken out
+}
+
+impl BorrowPosition {
+    /// Current debt including interest accrued through the pool's cumulative borrow rate
+    pub fn current_debt(&self, pool: &Pool) -> Result<u64> {
+        self.debt_at_rate(pool.cumulative_borrow_rate)
+    }
+    
+    /// Debt at a given cumulative borrow rate
+    /// Never less than the principal, so rounding in the scaled amount can't forgive debt
+    pub fn debt_at_rate(&self, cumulative_borrow_rate: u128) -> Result<u64> {
+        let accrued_debt = self.amount_scaled
+            .checked_mul(cumulative_borrow_rate)
+            .ok_or(OxygenError::BorrowValueOverflow)?
+            .checked_div(1_000_000_000_000) // 10^12 precision
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let accrued_debt = u64::try_from(accrued_debt)
+            .map_err(|_| OxygenError::BorrowValueOverflow)?;
+        
+        Ok(std::cmp::max(accrued_debt, self.amount_borrowed))
+    }
+    
+    /// Pay down `amount` of a `current_debt` obtained from `current_debt`
+    /// Principal and scaled units are cleared pro rata to the share of the debt
+    /// repaid, and a full repayment clears both entirely. Returns the principal
+    /// repaid; the rest of `amount` is interest
+    pub fn reduce_debt(&mut self, amount: u64, current_debt: u64) -> Result<u64> {
+        require!(amount <= current_debt, OxygenError::InvalidParameter);
+        
+        let (principal_to_remove, scaled_amount_to_remove) = if amount == current_debt {
+            (self.amount_borrowed, self.amount_scaled)
+        } else {
+            let principal = (amount as u128)
+                .checked_mul(self.amount_borrowed as u128)
+                .ok_or(ErrorCode::MathOverflow)?
+                .checked_div(current_debt as u128)
+                .ok_or(ErrorCode::MathOverflow)? as u64;
+            let scaled = (amount as u128)
+                .checked_mul(self.amount_scaled)
+                .ok_or(ErrorCode::MathOverflow)?
+                .checked_div(current_debt as u128)
+                .ok_or(ErrorCode::MathOverflow)?;
+            (principal, scaled)
+        };
+        
+        self.amount_borrowed = self.amount_borrowed
+            .checked_sub(principal_to_remove)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        self.amount_scaled = self.amount_scaled
+            .checked_sub(scaled_amount_to_remove)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        Ok(principal_to_remove)
+    }
+    
+    /// Whether both the principal and any accrued interest have been repaid
+    pub fn is_repaid(&self) -> bool {
+        self.amount_borrowed == 0 && self.amount_scaled == 0
+    }
+}
+
+// Append `item` after the `count` slots in use, or return false when all are taken
+fn push_slot<T>(slots: &mut [T], count: &mut u8, item: T) -> bool {
+    let index = *count as usize;
+    if index >= slots.len() {
+        return false;
+    }
+    
+    slots[index] = item;
+    *count += 1;
+    true
+}
+
+// Take the slot at `index` out of the `count` in use, shifting later slots down
+// so the ones in use stay contiguous
+fn remove_slot<T: Copy + Default>(slots: &mut [T], count: &mut u8, index: usize) -> T {
+    let len = *count as usize;
+    assert!(index < len, "slot index out of bounds");
+    
+    let item = slots[index];
+    slots.copy_within(index + 1..len, index);
+    slots[len - 1] = T::default();
+    *count -= 1;
+    item
 }
 
 impl UserPosition {
+    // Lengths of the slot arrays above
     pub const MAX_COLLATERALS: usize = 10;
     pub const MAX_BORROWS: usize = 10;
+    pub const MAX_LEVERAGED_POSITIONS: usize = 10;
+    pub const MAX_OPEN_ORDERS_ACCOUNTS: usize = 10;
+    pub const MAX_POSITION_HISTORY: usize = 10;
+    
+    // Rate limit on state-changing actions, see `record_action`
+    pub const RATE_LIMIT_WINDOW: i64 = 60;
+    pub const MAX_ACTIONS_PER_WINDOW: u8 = 20;
+    
+    // Health factor below which a position can be liquidated, 1.0 in basis points
+    pub const LIQUIDATION_THRESHOLD: u64 = 10000;
     
     pub fn space() -> usize {
         8 + // Anchor account discriminator
-        32 + // owner
-        4 + (Self::MAX_COLLATERALS * std::mem::size_of::<CollateralPosition>()) + // collaterals vector
-        4 + (Self::MAX_BORROWS * std::mem::size_of::<BorrowPosition>()) + // borrows vector
-        8 + // health_factor
-        8 + // last_updated
-        1  // bump
+        std::mem::size_of::<UserPosition>()
+    }
+    
+    /// Collateral positions in use
+    pub fn collaterals(&self) -> &[CollateralPosition] {
+        &self.collateral_slots[..self.collateral_count as usize]
+    }
+    
+    pub fn collaterals_mut(&mut self) -> &mut [CollateralPosition] {
+        &mut self.collateral_slots[..self.collateral_count as usize]
+    }
+    
+    /// Borrow positions in use
+    pub fn borrows(&self) -> &[BorrowPosition] {
+        &self.borrow_slots[..self.borrow_count as usize]
+    }
+    
+    pub fn borrows_mut(&mut self) -> &mut [BorrowPosition] {
+        &mut self.borrow_slots[..self.borrow_count as usize]
+    }
+    
+    /// Leveraged positions in use
+    pub fn leveraged_positions(&self) -> &[LeveragedPosition] {
+        &self.leveraged_position_slots[..self.leveraged_position_count as usize]
+    }
+    
+    pub fn leveraged_positions_mut(&mut self) -> &mut [LeveragedPosition] {
+        &mut self.leveraged_position_slots[..self.leveraged_position_count as usize]
+    }
+    
+    /// Serum open orders accounts created so far
+    pub fn open_orders_accounts(&self) -> &[OpenOrdersAccount] {
+        &self.open_orders_slots[..self.open_orders_count as usize]
+    }
+    
+    /// Closed and liquidated positions, oldest first
+    pub fn position_history(&self) -> &[ClosedPosition] {
+        &self.position_history_slots[..self.position_history_count as usize]
+    }
+    
+    pub fn remove_collateral(&mut self, index: usize) -> CollateralPosition {
+        remove_slot(&mut self.collateral_slots, &mut self.collateral_count, index)
+    }
+    
+    pub fn remove_borrow(&mut self, index: usize) -> BorrowPosition {
+        remove_slot(&mut self.borrow_slots, &mut self.borrow_count, index)
+    }
+    
+    pub fn add_leveraged_position(&mut self, position: LeveragedPosition) -> Result<()> {
+        require!(
+            push_slot(&mut self.leveraged_position_slots, &mut self.leveraged_position_count, position),
+            OxygenError::MaxPositionsReached
+        );
+        Ok(())
+    }
+    
+    pub fn remove_leveraged_position(&mut self, index: usize) -> LeveragedPosition {
+        remove_slot(&mut self.leveraged_position_slots, &mut self.leveraged_position_count, index)
+    }
+    
+    pub fn add_open_orders_account(&mut self, account: OpenOrdersAccount) -> Result<()> {
+        require!(
+            push_slot(&mut self.open_orders_slots, &mut self.open_orders_count, account),
+            OxygenError::MaxPositionsReached
+        );
+        Ok(())
+    }
+    
+    /// Forget every open orders account, e.g. when they no longer belong to the owner
+    pub fn clear_open_orders_accounts(&mut self) {
+        self.open_orders_slots = [OpenOrdersAccount::default(); Self::MAX_OPEN_ORDERS_ACCOUNTS];
+        self.open_orders_count = 0;
+    }
+    
+    /// Append a closed position to the history, dropping the oldest entry once full
+    pub fn record_closed_position(&mut self, entry: ClosedPosition) {
+        if self.position_history_count as usize >= Self::MAX_POSITION_HISTORY {
+            remove_slot(&mut self.position_history_slots, &mut self.position_history_count, 0);
+        }
+        push_slot(&mut self.position_history_slots, &mut self.position_history_count, entry);
+    }
+    
+    /// Copy a position stored in the pre-zero-copy layout into this account
+    pub fn load_legacy(&mut self, legacy: &LegacyUserPosition) -> Result<()> {
+        require!(
+            legacy.collaterals.len() <= Self::MAX_COLLATERALS
+                && legacy.borrows.len() <= Self::MAX_BORROWS
+                && legacy.leveraged_positions.len() <= Self::MAX_LEVERAGED_POSITIONS
+                && legacy.open_orders_accounts.len() <= Self::MAX_OPEN_ORDERS_ACCOUNTS,
+            OxygenError::InvalidParameter
+        );
+        
+        self.owner = legacy.owner;
+        for collateral in &legacy.collaterals {
+            push_slot(&mut self.collateral_slots, &mut self.collateral_count, *collateral);
+        }
+        for borrow in &legacy.borrows {
+            let borrow = BorrowPosition {
+                pool: borrow.pool,
+                amount_borrowed: borrow.amount_borrowed,
+                amount_scaled: borrow.amount_scaled,
+                interest_rate: borrow.interest_rate,
+                originated_at: legacy.last_updated,
+            };
+            push_slot(&mut self.borrow_slots, &mut self.borrow_count, borrow);
+        }
+        for position in &legacy.leveraged_positions {
+            let position = LeveragedPosition {
+                id: position.id,
+                market: position.market,
+                side: position.side,
+                size: position.size,
+                entry_price: position.entry_price,
+                leverage: position.leverage,
+                margin_used: position.margin_used,
+                margin_mode: position.margin_mode,
+                position_value: position.position_value,
+                liquidation_price: position.liquidation_price,
+                stop_loss_price: position.stop_loss_price,
+                take_profit_price: position.take_profit_price,
+                timestamp: position.timestamp,
+                status: position.status,
+                client_id: position.client_id,
+                funding_index: 0,
+            };
+            push_slot(&mut self.leveraged_position_slots, &mut self.leveraged_position_count, position);
+        }
+        for account in &legacy.open_orders_accounts {
+            push_slot(&mut self.open_orders_slots, &mut self.open_orders_count, *account);
+        }
+        for entry in &legacy.position_history {
+            self.record_closed_position(*entry);
+        }
+        self.locked_trading_margin = legacy.locked_trading_margin;
+        self.health_factor = legacy.health_factor;
+        self.unhealthy_since = legacy.unhealthy_since;
+        self.last_updated = legacy.last_updated;
+        self.bump = legacy.bump;
+        
+        Ok(())
+    }
+    
+    /// Open orders account recorded for a Serum market, if one was created
+    pub fn open_orders_for_market(&self, market: &Pubkey) -> Option<Pubkey> {
+        self.open_orders_accounts()
+            .iter()
+            .find(|account| account.market == *market)
+            .map(|account| account.open_orders)
+    }
+    
+    /// Count a state-changing action against the per-minute limit, starting a new
+    /// window once the current one has passed
+    pub fn record_action(&mut self, current_timestamp: i64) -> Result<()> {
+        if current_timestamp.saturating_sub(self.window_start) >= Self::RATE_LIMIT_WINDOW {
+            self.window_start = current_timestamp;
+            self.recent_action_count = 0;
+        }
+        
+        require!(
+            self.recent_action_count < Self::MAX_ACTIONS_PER_WINDOW,
+            OxygenError::RateLimitExceeded
+        );
+        
+        self.recent_action_count += 1;
+        Ok(())
+    }
+    
+    /// Index of the user's collateral position in `pool`, if they have one
+    pub fn collateral_index(&self, pool: &Pubkey) -> Option<usize> {
+        self.collaterals()
+            .iter()
+            .position(|collateral| collateral.pool == *pool)
+    }
+    
+    /// Index of the user's oldest borrow position in `pool`, if they have one
+    pub fn borrow_index(&self, pool: &Pubkey) -> Option<usize> {
+        self.borrows()
+            .iter()
+            .position(|borrow| borrow.pool == *pool)
+    }
+    
+    /// Indices of the user's borrow positions in `pool`, highest origination rate
+    /// first and oldest first among equal rates
+    pub fn borrow_indices_by_rate(&self, pool: &Pubkey) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.borrows().len())
+            .filter(|&i| self.borrows()[i].pool == *pool)
+            .collect();
+        
+        indices.sort_by_key(|&i| {
+            let borrow = &self.borrows()[i];
+            (std::cmp::Reverse(borrow.interest_rate), borrow.originated_at)
+        });
+        indices
+    }
+    
+    /// Debt across all of the user's borrow positions in `pool` at a given cumulative borrow rate
+    pub fn total_debt_at_rate(&self, pool: &Pubkey, cumulative_borrow_rate: u128) -> Result<u64> {
+        let mut total_debt = 0u64;
+        for borrow in self.borrows().iter().filter(|borrow| borrow.pool == *pool) {
+            total_debt = total_debt
+                .checked_add(borrow.debt_at_rate(cumulative_borrow_rate)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(total_debt)
+    }
+    
+    /// Pay down up to `amount` of the user's debt in `pool`, highest-rate borrows first,
+    /// removing the ones that are cleared. Returns the amount repaid and the principal
+    /// part of it; the rest is interest
+    pub fn reduce_pool_debt(&mut self, pool: &Pubkey, amount: u64, cumulative_borrow_rate: u128) -> Result<(u64, u64)> {
+        let mut remaining = amount;
+        let mut principal_repaid = 0u64;
+        
+        for index in self.borrow_indices_by_rate(pool) {
+            if remaining == 0 {
+                break;
+            }
+            
+            let borrow = &mut self.borrows_mut()[index];
+            let current_debt = borrow.debt_at_rate(cumulative_borrow_rate)?;
+            let payment = std::cmp::min(remaining, current_debt);
+            
+            principal_repaid = principal_repaid
+                .checked_add(borrow.reduce_debt(payment, current_debt)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+            remaining -= payment;
+        }
+        
+        // Remove cleared entries from the back so earlier indices stay valid
+        for index in (0..self.borrows().len()).rev() {
+            if self.borrows()[index].pool == *pool && self.borrows()[index].is_repaid() {
+                self.remove_borrow(index);
+            }
+        }
+        
+        Ok((amount - remaining, principal_repaid))
     }
     
     pub fn add_collateral(&mut self, pool: Pubkey, amount: u64, scaled_amount: u128) -> Result<()> {
         // Check if we already have this collateral
-        for collateral in &mut self.collaterals {
-            if collateral.pool == pool {
-                // Update existing collateral position
-                collateral.amount_deposited = collateral.amount_deposited.checked_add(amount)
-                    .ok_or(ErrorCode::MathOverflow)?;
-                collateral.amount_scaled = collateral.amount_scaled.checked_add(scaled_amount)
-                    .ok_or(ErrorCode::MathOverflow)?;
-                collateral.is_collateral = true;
-                return Ok(());
-            }
+        if let Some(index) = self.collateral_index(&pool) {
+            // Update existing collateral position
+            let collateral = &mut self.collaterals_mut()[index];
+            collateral.amount_deposited = collateral.amount_deposited.checked_add(amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            collateral.amount_scaled = collateral.amount_scaled.checked_add(scaled_amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            collateral.is_collateral = true;
+            return Ok(());
         }
         
         // Add new collateral if not found and we have space
-        if self.collaterals.len() < Self::MAX_COLLATERALS {
-            self.collaterals.push(CollateralPosition {
-                pool,
-                amount_deposited: amount,
-                amount_scaled: scaled_amount,
-                is_collateral: true,
-                is_lending: false,
-                deposit_timestamp: Clock::get()?.unix_timestamp,
-            });
+        let collateral = CollateralPosition {
+            pool,
+            amount_deposited: amount,
+            amount_scaled: scaled_amount,
+            is_collateral: true,
+            is_lending: false,
+            deposit_timestamp: Clock::get()?.unix_timestamp,
+        };
+        if push_slot(&mut self.collateral_slots, &mut self.collateral_count, collateral) {
             return Ok(());
         }
         
@@ -110,27 +625,38 @@ impl UserPosition {
         Err(ErrorCode::AccountDidNotSerialize.into())
     }
     
-    pub fn add_borrow(&mut self, pool: Pubkey, amount: u64, scaled_amount: u128, interest_rate: u64) -> Result<()> {
-        // Check if we already have this borrow
-        for borrow in &mut self.borrows {
-            if borrow.pool == pool {
-                // Update existing borrow position
-                borrow.amount_borrowed = borrow.amount_borrowed.checked_add(amount)
-                    .ok_or(ErrorCode::MathOverflow)?;
-                borrow.amount_scaled = borrow.amount_scaled.checked_add(scaled_amount)
-                    .ok_or(ErrorCode::MathOverflow)?;
-                return Ok(());
-            }
+    pub fn add_borrow(
+        &mut self,
+        pool: Pubkey,
+        amount: u64,
+        scaled_amount: u128,
+        interest_rate: u64,
+        originated_at: i64
+    ) -> Result<()> {
+        // Each origination rate gets its own entry, so borrowing more never averages
+        // away a rate locked in earlier; only borrows at the same rate are merged
+        if let Some(index) = self.borrows()
+            .iter()
+            .position(|borrow| borrow.pool == pool && borrow.interest_rate == interest_rate)
+        {
+            // Update existing borrow position
+            let borrow = &mut self.borrows_mut()[index];
+            borrow.amount_borrowed = borrow.amount_borrowed.checked_add(amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            borrow.amount_scaled = borrow.amount_scaled.checked_add(scaled_amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            return Ok(());
         }
         
         // Add new borrow if not found and we have space
-        if self.borrows.len() < Self::MAX_BORROWS {
-            self.borrows.push(BorrowPosition {
-                pool,
-                amount_borrowed: amount,
-                amount_scaled: scaled_amount,
-                interest_rate,
-            });
+        let borrow = BorrowPosition {
+            pool,
+            amount_borrowed: amount,
+            amount_scaled: scaled_amount,
+            interest_rate,
+            originated_at,
+        };
+        if push_slot(&mut self.borrow_slots, &mut self.borrow_count, borrow) {
             return Ok(());
         }
         
@@ -140,12 +666,79 @@ impl UserPosition {
     
     // Calculate health factor based on collateral value and borrowed amounts
     // Health factor = (collateral value * liquidation threshold) / borrowed value
+    // Borrows are valued at their principal; use calculate_health_factor_with_rates
+    // to include accrued interest for pools whose borrow rate is known
     pub fn calculate_health_factor(&mut self, pool_data: &HashMap<Pubkey, (u64, u64)>) -> Result<u64> {
-        let mut total_collateral_value = 0u128;
-        let mut total_borrowed_value = 0u128;
+        self.calculate_health_factor_with_rates(pool_data, &HashMap::new())
+    }
+    
+    // Same as calculate_health_factor, but borrows in pools present in `borrow_rates`
+    // (pool -> cumulative borrow rate) are valued at their interest-inclusive debt
+    pub fn calculate_health_factor_with_rates(
+        &mut self,
+        pool_data: &HashMap<Pubkey, (u64, u64)>,
+        borrow_rates: &HashMap<Pubkey, u128>
+    ) -> Result<u64> {
+        let breakdown = self.compute_health_breakdown(pool_data, borrow_rates)?;
+        self.record_health_factor(breakdown.health_factor)
+    }
+    
+    // Store an already computed health factor
+    // Leaves unhealthy_since alone, since most callers don't price every pool
+    pub fn record_health_factor(&mut self, health_factor: u64) -> Result<u64> {
+        self.health_factor = health_factor;
+        Ok(self.health_factor)
+    }
+    
+    // Remember when the position first became liquidatable, so liquidations can
+    // wait out the grace period, and forget it once health recovers
+    // Only `mark_unhealthy` and `liquidate` call this, after pricing every pool the
+    // user holds from fresh oracles, so a partial valuation can't start the clock
+    pub fn update_unhealthy_since(&mut self, current_timestamp: i64) {
+        if self.health_factor >= Self::LIQUIDATION_THRESHOLD {
+            self.unhealthy_since = 0;
+        } else if self.unhealthy_since == 0 {
+            self.unhealthy_since = current_timestamp;
+        }
+    }
+    
+    // Whether a liquidatable position has been unhealthy for at least `grace_period`
+    pub fn liquidation_grace_elapsed(&self, current_timestamp: i64, grace_period: i64) -> bool {
+        self.unhealthy_since != 0
+            && current_timestamp.saturating_sub(self.unhealthy_since) >= grace_period
+    }
+    
+    // Every pool the user holds collateral in or owes to must be priced, otherwise
+    // it would count as worthless and misstate the health factor
+    pub fn require_priced_pools(&self, pool_data: &HashMap<Pubkey, (u64, u64)>) -> Result<()> {
+        let mut user_pools = self.collaterals()
+            .iter()
+            .map(|collateral| collateral.pool)
+            .chain(self.borrows().iter().map(|borrow| borrow.pool));
+        
+        require!(
+            user_pools.all(|pool_key| pool_data.contains_key(&pool_key)),
+            OxygenError::InvalidParameter
+        );
+        
+        Ok(())
+    }
+    
+    // Single source of truth for health: returns every component alongside the
+    // final factor, which is always
+    // weighted_collateral_value * 10000 / (borrowed_value + leveraged_risk)
+    // or u64::MAX when there is nothing at risk
+    pub fn compute_health_breakdown(
+        &self,
+        pool_data: &HashMap<Pubkey, (u64, u64)>,
+        borrow_rates: &HashMap<Pubkey, u128>
+    ) -> Result<HealthBreakdown> {
+        let mut weighted_collateral_value = 0u128;
+        let mut borrowed_value = 0u128;
+        let mut leveraged_risk = 0u128;
         
         // Calculate collateral value
-        for collateral in &self.collaterals {
+        for collateral in self.collaterals() {
             if !collateral.is_collateral {
                 continue;
             }
@@ -153,68 +746,501 @@ impl UserPosition {
             if let Some((price, liquidation_threshold)) = pool_data.get(&collateral.pool) {
                 let value = (collateral.amount_deposited as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
                 
                 let weighted_value = value
                     .checked_mul(*liquidation_threshold as u128)
-                    .ok_or(ErrorCode::MathOverflow)?
+                    .ok_or(OxygenError::CollateralValueOverflow)?
                     .checked_div(10000)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
                 
-                total_collateral_value = total_collateral_value
+                weighted_collateral_value = weighted_collateral_value
                     .checked_add(weighted_value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::CollateralValueOverflow)?;
             }
         }
         
         // Calculate borrowed value
-        for borrow in &self.borrows {
+        for borrow in self.borrows() {
             if let Some((price, _)) = pool_data.get(&borrow.pool) {
-                let value = (borrow.amount_borrowed as u128)
+                let debt = match borrow_rates.get(&borrow.pool) {
+                    Some(cumulative_borrow_rate) => borrow.debt_at_rate(*cumulative_borrow_rate)?,
+                    None => borrow.amount_borrowed,
+                };
+                
+                let value = (debt as u128)
                     .checked_mul(*price as u128)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::BorrowValueOverflow)?;
                 
-                total_borrowed_value = total_borrowed_value
+                borrowed_value = borrowed_value
                     .checked_add(value)
-                    .ok_or(ErrorCode::MathOverflow)?;
+                    .ok_or(OxygenError::BorrowValueOverflow)?;
             }
         }
         
         // Include leveraged positions in the risk calculation
-        for position in &self.leveraged_positions {
-            // Only consider open positions
-            if position.status != PositionStatus::Open {
+        for position in self.leveraged_positions() {
+            // Only consider open positions; isolated positions can never lose more
+            // than their own locked margin, so they don't weigh on account health
+            if position.status != PositionStatus::Open || position.margin_mode == MarginMode::Isolated {
                 continue;
             }
             
             // A leveraged position adds risk proportional to the leveraged value minus margin
-            let leveraged_risk = (position.position_value as u128)
+            let position_risk = (position.position_value as u128)
                 .checked_sub(position.margin_used as u128)
-                .ok_or(ErrorCode::MathOverflow)?;
+                .ok_or(OxygenError::BorrowValueOverflow)?;
                 
-            total_borrowed_value = total_borrowed_value
-                .checked_add(leveraged_risk)
-                .ok_or(ErrorCode::MathOverflow)?;
+            leveraged_risk = leveraged_risk
+                .checked_add(position_risk)
+                .ok_or(OxygenError::BorrowValueOverflow)?;
         }
         
-        // Calculate health factor
-        if total_borrowed_value == 0 {
-            self.health_factor = u64::MAX; // No borrows, so perfectly healthy
-            return Ok(self.health_factor);
+        let total_risk = borrowed_value
+            .checked_add(leveraged_risk)
+            .ok_or(OxygenError::BorrowValueOverflow)?;
+        
+        let health_factor = health_factor_for(weighted_collateral_value, total_risk)?;
+        
+        Ok(HealthBreakdown {
+            weighted_collateral_value,
+            borrowed_value,
+            leveraged_risk,
+            locked_trading_margin: self.locked_trading_margin,
+            health_factor,
+        })
+    }
+    
+    // Largest amount of the collateral deposited in `pool` that can be withdrawn
+    // while keeping the health factor at or above `min_health_factor`, without
+    // dipping into collateral backing locked trading margin
+    pub fn max_withdrawable(
+        &self,
+        pool: &Pubkey,
+        pool_data: &HashMap<Pubkey, (u64, u64)>,
+        borrow_rates: &HashMap<Pubkey, u128>,
+        min_health_factor: u64
+    ) -> Result<u64> {
+        let deposited = match self.collaterals().iter().find(|c| c.pool == *pool && c.is_collateral) {
+            Some(collateral) => collateral.amount_deposited,
+            None => return Ok(0),
+        };
+        
+        // Collateral without a known price contributes nothing to health, so it can all go
+        let (price, liquidation_threshold) = match pool_data.get(pool) {
+            Some(data) => *data,
+            None => return Ok(deposited),
+        };
+        
+        if price == 0 {
+            return Ok(deposited);
         }
         
-        // Health factor = (collateral value * liquidation threshold) / borrowed value
-        // We multiply by 10000 to preserve precision
-        self.health_factor = (total_collateral_value
-            .checked_mul(10000)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(total_borrowed_value)
-            .ok_or(ErrorCode::MathOverflow)?) as u64;
+        let breakdown = self.compute_health_breakdown(pool_data, borrow_rates)?;
+        let mut max_amount = deposited;
+        
+        // Solve weighted_collateral_value - amount * price * threshold / 10000 >= min_health_factor * risk / 10000
+        let total_risk = breakdown.borrowed_value
+            .checked_add(breakdown.leveraged_risk)
+            .ok_or(OxygenError::BorrowValueOverflow)?;
+        
+        if total_risk > 0 && liquidation_threshold > 0 {
+            let required_weighted_value = total_risk
+                .checked_mul(min_health_factor as u128)
+                .ok_or(ErrorCode::MathOverflow)?
+                .checked_add(9999)
+                .ok_or(ErrorCode::MathOverflow)?
+                / 10000;
             
-        Ok(self.health_factor)
+            let spare_weighted_value = breakdown.weighted_collateral_value
+                .saturating_sub(required_weighted_value);
+            
+            let health_limit = spare_weighted_value
+                .checked_mul(10000)
+                .ok_or(ErrorCode::MathOverflow)?
+                / (price as u128)
+                    .checked_mul(liquidation_threshold as u128)
+                    .ok_or(ErrorCode::MathOverflow)?;
+            
+            max_amount = std::cmp::min(max_amount as u128, health_limit) as u64;
+        }
+        
+        // Locked margin is denominated in collateral value (amount * price), matching
+        // TradingModule::calculate_user_available_collateral
+        if self.locked_trading_margin > 0 {
+            let mut total_collateral_value = 0u128;
+            for collateral in self.collaterals().iter().filter(|c| c.is_collateral) {
+                if let Some((collateral_price, _)) = pool_data.get(&collateral.pool) {
+                    total_collateral_value = total_collateral_value
+                        .checked_add(
+                            (collateral.amount_deposited as u128)
+                                .checked_mul(*collateral_price as u128)
+                                .ok_or(OxygenError::CollateralValueOverflow)?
+                        )
+                        .ok_or(OxygenError::CollateralValueOverflow)?;
+                }
+            }
+            
+            let margin_limit = total_collateral_value
+                .saturating_sub(self.locked_trading_margin as u128)
+                / (price as u128);
+            
+            max_amount = std::cmp::min(max_amount as u128, margin_limit) as u64;
+        }
+        
+        Ok(max_amount)
     }
     
     pub fn is_healthy(&self, minimum_health_factor: u64) -> bool {
         self.health_factor >= minimum_health_factor
     }
+}
+
+#[cfg(test)]
+impl UserPosition {
+    /// Empty position holding the given collaterals and borrows
+    pub(crate) fn with_positions(collaterals: &[CollateralPosition], borrows: &[BorrowPosition]) -> Self {
+        let mut position: UserPosition = bytemuck::Zeroable::zeroed();
+        for collateral in collaterals {
+            assert!(push_slot(&mut position.collateral_slots, &mut position.collateral_count, *collateral));
+        }
+        for borrow in borrows {
+            assert!(push_slot(&mut position.borrow_slots, &mut position.borrow_count, *borrow));
+        }
+        position
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    
+    pub(crate) const RATE_PRECISION: u128 = 1_000_000_000_000;
+    
+    pub(crate) fn collateral(pool: Pubkey, amount: u64) -> CollateralPosition {
+        CollateralPosition {
+            pool,
+            amount_deposited: amount,
+            amount_scaled: amount as u128,
+            is_collateral: true,
+            is_lending: false,
+            deposit_timestamp: 0,
+        }
+    }
+    
+    pub(crate) fn borrow(pool: Pubkey, amount: u64) -> BorrowPosition {
+        BorrowPosition {
+            pool,
+            amount_borrowed: amount,
+            amount_scaled: amount as u128,
+            interest_rate: 0,
+            originated_at: 0,
+        }
+    }
+    
+    // 1000 tokens of collateral at 1.0 with an 80% threshold, 400 owed at 1.0
+    fn borrowing_position() -> (UserPosition, Pubkey, HashMap<Pubkey, (u64, u64)>) {
+        let collateral_pool = Pubkey::new_unique();
+        let debt_pool = Pubkey::new_unique();
+        let position = UserPosition::with_positions(
+            &[collateral(collateral_pool, 1000)],
+            &[borrow(debt_pool, 400)]
+        );
+        let pool_data = HashMap::from([
+            (collateral_pool, (10000, 8000)),
+            (debt_pool, (10000, 8000)),
+        ]);
+        (position, collateral_pool, pool_data)
+    }
+    
+    #[test]
+    fn max_withdrawable_without_borrows_is_whole_deposit() {
+        let pool = Pubkey::new_unique();
+        let position = UserPosition::with_positions(&[collateral(pool, 1000)], &[]);
+        let pool_data = HashMap::from([(pool, (10000, 8000))]);
+        
+        assert_eq!(position.max_withdrawable(&pool, &pool_data, &HashMap::new(), 11000).unwrap(), 1000);
+    }
+    
+    #[test]
+    fn max_withdrawable_with_borrows_keeps_min_health() {
+        let (mut position, collateral_pool, pool_data) = borrowing_position();
+        
+        // 550 left backs 440 of weighted value, exactly 1.1x the 400 owed
+        let max = position.max_withdrawable(&collateral_pool, &pool_data, &HashMap::new(), 11000).unwrap();
+        assert_eq!(max, 450);
+        
+        position.collaterals_mut()[0].amount_deposited -= max;
+        let breakdown = position.compute_health_breakdown(&pool_data, &HashMap::new()).unwrap();
+        assert_eq!(breakdown.health_factor, 11000);
+        
+        position.collaterals_mut()[0].amount_deposited -= 1;
+        let breakdown = position.compute_health_breakdown(&pool_data, &HashMap::new()).unwrap();
+        assert!(breakdown.health_factor < 11000);
+    }
+    
+    #[test]
+    fn max_withdrawable_counts_accrued_interest() {
+        let (position, collateral_pool, pool_data) = borrowing_position();
+        
+        // The 400 borrowed has grown to 500 at a 1.25 cumulative rate
+        let debt_pool = position.borrows()[0].pool;
+        let borrow_rates = HashMap::from([(debt_pool, RATE_PRECISION * 5 / 4)]);
+        
+        let max = position.max_withdrawable(&collateral_pool, &pool_data, &borrow_rates, 11000).unwrap();
+        assert_eq!(max, 312);
+    }
+    
+    #[test]
+    fn require_priced_pools_rejects_unpriced_borrow_pool() {
+        let (position, collateral_pool, pool_data) = borrowing_position();
+        assert!(position.require_priced_pools(&pool_data).is_ok());
+        
+        let collateral_only = HashMap::from([(collateral_pool, pool_data[&collateral_pool])]);
+        assert!(position.require_priced_pools(&collateral_only).is_err());
+    }
+    
+    // 500 tokens of collateral backing 400 of weighted value against 500 owed
+    fn unhealthy_position() -> (UserPosition, HashMap<Pubkey, (u64, u64)>) {
+        let collateral_pool = Pubkey::new_unique();
+        let debt_pool = Pubkey::new_unique();
+        let position = UserPosition::with_positions(
+            &[collateral(collateral_pool, 500)],
+            &[borrow(debt_pool, 500)]
+        );
+        let pool_data = HashMap::from([
+            (collateral_pool, (10000, 8000)),
+            (debt_pool, (10000, 8000)),
+        ]);
+        (position, pool_data)
+    }
+    
+    #[test]
+    fn health_checks_leave_unhealthy_since_alone() {
+        let (mut position, pool_data) = unhealthy_position();
+        
+        assert_eq!(position.calculate_health_factor(&pool_data).unwrap(), 8000);
+        assert_eq!(position.unhealthy_since, 0);
+        
+        // A partial valuation that looks healthy doesn't clear a recorded timestamp either
+        position.unhealthy_since = 1000;
+        assert_eq!(position.calculate_health_factor(&HashMap::new()).unwrap(), u64::MAX);
+        assert_eq!(position.unhealthy_since, 1000);
+    }
+    
+    #[test]
+    fn liquidation_waits_out_grace_period() {
+        let (mut position, pool_data) = unhealthy_position();
+        position.calculate_health_factor(&pool_data).unwrap();
+        
+        assert!(!position.liquidation_grace_elapsed(1000, 600));
+        
+        position.update_unhealthy_since(1000);
+        assert_eq!(position.unhealthy_since, 1000);
+        
+        // Staying unhealthy keeps the first timestamp
+        position.update_unhealthy_since(1300);
+        assert_eq!(position.unhealthy_since, 1000);
+        
+        assert!(!position.liquidation_grace_elapsed(1599, 600));
+        assert!(position.liquidation_grace_elapsed(1600, 600));
+    }
+    
+    #[test]
+    fn recovering_clears_unhealthy_since() {
+        let (mut position, pool_data) = unhealthy_position();
+        position.calculate_health_factor(&pool_data).unwrap();
+        position.update_unhealthy_since(1000);
+        
+        position.borrows_mut()[0].amount_borrowed = 300;
+        position.borrows_mut()[0].amount_scaled = 300;
+        position.calculate_health_factor(&pool_data).unwrap();
+        position.update_unhealthy_since(1100);
+        
+        assert_eq!(position.unhealthy_since, 0);
+        assert!(!position.liquidation_grace_elapsed(5000, 600));
+    }
+    
+    fn leveraged(margin_mode: MarginMode, position_value: u64, margin_used: u64) -> LeveragedPosition {
+        LeveragedPosition {
+            margin_mode,
+            position_value,
+            margin_used,
+            ..Default::default()
+        }
+    }
+    
+    #[test]
+    fn debt_grows_with_rate_but_never_below_principal() {
+        let debt = borrow(Pubkey::new_unique(), 400);
+        
+        assert_eq!(debt.debt_at_rate(RATE_PRECISION * 5 / 4).unwrap(), 500);
+        assert_eq!(debt.debt_at_rate(RATE_PRECISION / 2).unwrap(), 400);
+    }
+    
+    #[test]
+    fn partial_repayment_clears_principal_pro_rata() {
+        let mut debt = borrow(Pubkey::new_unique(), 400);
+        
+        // 250 of 500 owed is half the debt, so half of each part goes
+        assert_eq!(debt.reduce_debt(250, 500).unwrap(), 200);
+        assert_eq!(debt.amount_borrowed, 200);
+        assert_eq!(debt.amount_scaled, 200);
+        assert!(debt.reduce_debt(251, 250).is_err());
+        
+        assert_eq!(debt.reduce_debt(250, 250).unwrap(), 200);
+        assert!(debt.is_repaid());
+    }
+    
+    #[test]
+    fn borrows_merge_only_at_the_same_rate() {
+        let pool = Pubkey::new_unique();
+        let mut position = UserPosition::with_positions(&[], &[]);
+        
+        position.add_borrow(pool, 100, 100, 500, 10).unwrap();
+        position.add_borrow(pool, 50, 50, 500, 20).unwrap();
+        position.add_borrow(pool, 70, 70, 800, 30).unwrap();
+        
+        assert_eq!(position.borrows().len(), 2);
+        assert_eq!(position.borrows()[0].amount_borrowed, 150);
+        assert_eq!(position.borrows()[0].originated_at, 10);
+        assert_eq!(position.borrow_indices_by_rate(&pool), vec![1, 0]);
+    }
+    
+    #[test]
+    fn borrow_slots_reject_an_eleventh_entry() {
+        let mut position = UserPosition::with_positions(&[], &[]);
+        for _ in 0..UserPosition::MAX_BORROWS {
+            position.add_borrow(Pubkey::new_unique(), 1, 1, 0, 0).unwrap();
+        }
+        
+        assert!(position.add_borrow(Pubkey::new_unique(), 1, 1, 0, 0).is_err());
+    }
+    
+    #[test]
+    fn pool_debt_is_repaid_highest_rate_first() {
+        let pool = Pubkey::new_unique();
+        let mut cheap = borrow(pool, 300);
+        cheap.interest_rate = 300;
+        let mut expensive = borrow(pool, 200);
+        expensive.interest_rate = 900;
+        let mut position = UserPosition::with_positions(&[], &[cheap, expensive, borrow(Pubkey::new_unique(), 50)]);
+        
+        assert_eq!(position.total_debt_at_rate(&pool, RATE_PRECISION).unwrap(), 500);
+        assert_eq!(position.reduce_pool_debt(&pool, 250, RATE_PRECISION).unwrap(), (250, 250));
+        
+        // The expensive borrow is cleared and removed, the other pool's borrow is untouched
+        assert_eq!(position.borrows().len(), 2);
+        assert_eq!(position.borrows()[0].interest_rate, 300);
+        assert_eq!(position.borrows()[0].amount_borrowed, 250);
+        assert_eq!(position.borrows()[1].amount_borrowed, 50);
+        
+        // Paying more than is owed only takes what is owed
+        assert_eq!(position.reduce_pool_debt(&pool, 1000, RATE_PRECISION).unwrap(), (250, 250));
+        assert_eq!(position.borrow_index(&pool), None);
+    }
+    
+    #[test]
+    fn removing_a_collateral_keeps_slots_contiguous() {
+        let pools = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut position = UserPosition::with_positions(
+            &[collateral(pools[0], 1), collateral(pools[1], 2), collateral(pools[2], 3)],
+            &[]
+        );
+        
+        assert_eq!(position.remove_collateral(0).amount_deposited, 1);
+        assert_eq!(position.collaterals().len(), 2);
+        assert_eq!(position.collateral_index(&pools[2]), Some(1));
+        assert_eq!(position.collateral_slots[2].amount_deposited, 0);
+    }
+    
+    #[test]
+    fn actions_are_rate_limited_per_window() {
+        let mut position = UserPosition::with_positions(&[], &[]);
+        position.window_start = 1000;
+        
+        for _ in 0..UserPosition::MAX_ACTIONS_PER_WINDOW {
+            position.record_action(1000).unwrap();
+        }
+        assert!(position.record_action(1059).is_err());
+        
+        // A new window starts once the old one has passed
+        assert!(position.record_action(1060).is_ok());
+        assert_eq!(position.recent_action_count, 1);
+    }
+    
+    #[test]
+    fn position_history_drops_the_oldest_entry() {
+        let mut position = UserPosition::with_positions(&[], &[]);
+        for id in 0..=UserPosition::MAX_POSITION_HISTORY as u64 {
+            let closed = LeveragedPosition { id, ..Default::default() };
+            position.record_closed_position(ClosedPosition::from_position(&closed, 0, 0, PositionStatus::Closed, 0));
+        }
+        
+        let history = position.position_history();
+        assert_eq!(history.len(), UserPosition::MAX_POSITION_HISTORY);
+        assert_eq!(history[0].id, 1);
+        assert_eq!(history[history.len() - 1].id, UserPosition::MAX_POSITION_HISTORY as u64);
+    }
+    
+    #[test]
+    fn health_ignores_non_collateral_deposits_and_isolated_positions() {
+        let (mut position, collateral_pool, pool_data) = borrowing_position();
+        let mut lent = collateral(Pubkey::new_unique(), 5000);
+        lent.is_collateral = false;
+        position.collateral_slots[1] = lent;
+        position.collateral_count = 2;
+        position.add_leveraged_position(leveraged(MarginMode::Isolated, 1000, 100)).unwrap();
+        
+        let breakdown = position.compute_health_breakdown(&pool_data, &HashMap::new()).unwrap();
+        assert_eq!(breakdown.weighted_collateral_value, 8_000_000);
+        assert_eq!(breakdown.leveraged_risk, 0);
+        assert_eq!(breakdown.health_factor, 20000);
+        
+        // Cross positions add their value beyond the margin to the risk
+        position.add_leveraged_position(leveraged(MarginMode::Cross, 5_000_000, 1_000_000)).unwrap();
+        let breakdown = position.compute_health_breakdown(&pool_data, &HashMap::new()).unwrap();
+        assert_eq!(breakdown.leveraged_risk, 4_000_000);
+        assert_eq!(breakdown.health_factor, 10000);
+        assert_eq!(position.max_withdrawable(&collateral_pool, &pool_data, &HashMap::new(), 10000).unwrap(), 0);
+    }
+    
+    #[test]
+    fn health_with_borrow_matches_a_recomputed_breakdown() {
+        let (mut position, _, pool_data) = borrowing_position();
+        let breakdown = position.compute_health_breakdown(&pool_data, &HashMap::new()).unwrap();
+        
+        position.borrows_mut()[0].amount_borrowed = 800;
+        let recomputed = position.compute_health_breakdown(&pool_data, &HashMap::new()).unwrap();
+        
+        assert_eq!(breakdown.health_factor_with_borrow(4_000_000).unwrap(), recomputed.health_factor);
+        assert_eq!(recomputed.health_factor, 10000);
+    }
+    
+    #[test]
+    fn legacy_positions_carry_over() {
+        let pool = Pubkey::new_unique();
+        let legacy = LegacyUserPosition {
+            owner: Pubkey::new_unique(),
+            collaterals: vec![collateral(pool, 1000)],
+            borrows: vec![LegacyBorrowPosition { pool, amount_borrowed: 400, amount_scaled: 400, interest_rate: 500 }],
+            leveraged_positions: Vec::new(),
+            locked_trading_margin: 7,
+            open_orders_accounts: Vec::new(),
+            position_history: Vec::new(),
+            health_factor: 20000,
+            unhealthy_since: 0,
+            last_updated: 1234,
+            bump: 254,
+        };
+        let mut position = UserPosition::with_positions(&[], &[]);
+        position.load_legacy(&legacy).unwrap();
+        
+        assert_eq!(position.owner, legacy.owner);
+        assert_eq!(position.collaterals()[0].amount_deposited, 1000);
+        assert_eq!(position.borrows()[0].originated_at, 1234);
+        assert_eq!(position.borrows()[0].interest_rate, 500);
+        assert_eq!(position.locked_trading_margin, 7);
+        assert_eq!(position.bump, 254);
+    }
 }
\ No newline at end of file